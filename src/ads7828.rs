@@ -1,76 +1,131 @@
-use embassy_rp::i2c::{Blocking, Error as I2cError, I2c};
+use defmt::warn;
+use embassy_embedded_hal::SetConfig;
+use embassy_rp::i2c::{self, Blocking, Error as I2cError, I2c, Mode};
 use embassy_rp::peripherals::I2C1;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex; // or I2C1 if that’s your hardware
 
-// Map from your original code
-const ADS7828_CHANNEL_MAP: [u8; 8] = [
-    0b00000000, 0b01000000, 0b00010000, 0b01010000, 0b00100000, 0b01100000, 0b00110000, 0b01110000,
-];
+use crate::{
+    ads7828_protocol::{generate_command_byte, sample_from_bytes, ChannelMode},
+    utils::{validate_i2c_address, I2cTransfer, InvalidI2cAddress},
+};
 
-/// ADS7828 driver on a shared I2C bus (blocking mode).
+/// Full scale with the chip's internal reference switched on.
+pub const INTERNAL_REF_V: f32 = 2.5;
+/// Full scale from the external reference, tied to the 5 V rail on this board.
+pub const EXTERNAL_REF_V: f32 = 5.0;
+
+/// The only addresses the part answers on: 0b10010 followed by the A1/A0 pin levels.
+pub const ADS7828_ADDRESSES: [u8; 4] = [0x48, 0x49, 0x4A, 0x4B];
+
+/// ADS7828 driver on a shared I2C bus.
 ///
 /// `'d`: The Embassy "lifetime" for device usage
 /// `I2C1` is the peripheral instance
-/// `Blocking` is the embassy-rp "Mode" for blocking I2C
-pub struct Ads7828<'d> {
-    i2c: Mutex<CriticalSectionRawMutex, I2c<'d, I2C1, Blocking>>,
+/// `M` is the embassy-rp bus "Mode": `Async` lets other tasks run during each transfer,
+/// `Blocking` stalls the executor until it is done.
+pub struct Ads7828<'d, M: Mode = Blocking> {
+    i2c: Mutex<CriticalSectionRawMutex, I2c<'d, I2C1, M>>,
     address: u8,
+    use_internal_ref: bool,
 }
 
-impl<'d> Ads7828<'d> {
+impl<'d, M: Mode> Ads7828<'d, M>
+where
+    I2c<'d, I2C1, M>: I2cTransfer,
+{
     /// Create a new `Ads7828`.
-    /// `i2c` must be `I2c<'d, I2C1, Blocking>` (or similar),
-    /// `address` is the 7-bit address of the ADS7828.
-    pub fn new(i2c: I2c<'d, I2C1, Blocking>, address: u8) -> Self {
-        Self {
+    /// `i2c` must be `I2c<'d, I2C1, M>` (or similar),
+    /// `address` is the 7-bit address of the ADS7828, one of [`ADS7828_ADDRESSES`].
+    /// `use_internal_ref` powers up the 2.5 V internal reference for every conversion instead
+    /// of relying on REF IN.
+    pub fn new(
+        i2c: I2c<'d, I2C1, M>,
+        address: u8,
+        use_internal_ref: bool,
+    ) -> Result<Self, InvalidI2cAddress> {
+        let address = validate_i2c_address(address)?;
+        if !ADS7828_ADDRESSES.contains(&address) {
+            return Err(InvalidI2cAddress(address));
+        }
+        Ok(Self {
             i2c: Mutex::new(i2c),
             address,
-        }
+            use_internal_ref,
+        })
     }
 
-    /// Generate the command byte.
-    fn generate_command_byte(channel: u8, ref_on: bool, converter_on: bool) -> u8 {
-        let mut byte = 0b1000_0000; // single ended mode
-        if channel > 7 {
-            return 0; // clamp or handle error
+    /// Re-applies the bus configuration, e.g. to drop the clock to standard mode.
+    pub async fn set_bus_config(&self, config: &i2c::Config) {
+        if self.i2c.lock().await.set_config(config).is_err() {
+            warn!("ADS7828 I2C re-init rejected the bus config");
         }
-        byte |= ADS7828_CHANNEL_MAP[channel as usize];
+    }
 
-        if ref_on {
-            byte |= 0b0000_1000;
-        }
-        if converter_on {
-            byte |= 0b0000_0100;
+    /// Address selected by the A1/A0 strapping pins.
+    pub const fn address(a1: bool, a0: bool) -> u8 {
+        ADS7828_ADDRESSES[((a1 as usize) << 1) | a0 as usize]
+    }
+
+    /// Input voltage that reads as full scale with the configured reference.
+    pub fn full_scale_v(&self) -> f32 {
+        if self.use_internal_ref {
+            INTERNAL_REF_V
+        } else {
+            EXTERNAL_REF_V
         }
-        byte
     }
 
-    /// Get a single 12-bit reading from `channel` (0..7).
-    ///
-    /// `nostop` typically implies a repeated-start. In Embassy’s blocking
-    /// I2C, `write_then_read` does a repeated start, not a “no stop” cycle.
-    pub async fn get_channel(&self, channel: u8, _nostop: bool) -> Result<u16, I2cError> {
-        let cmd = Self::generate_command_byte(channel, false, true);
+    /// Converts a 12-bit code, or an average of codes, to volts at the configured reference.
+    pub fn code_to_voltage(&self, code: f32) -> f32 {
+        (code / 4095.0) * self.full_scale_v()
+    }
+
+    /// Get a single 12-bit reading from `channel` (0..7), with a STOP between the command
+    /// write and the read.
+    pub async fn get_channel(&self, channel: u8) -> Result<u16, I2cError> {
+        self.get_channel_mode(channel, ChannelMode::SingleEnded)
+            .await
+    }
+
+    /// Like [`Ads7828::get_channel`], with the input configuration chosen by `mode`.
+    pub async fn get_channel_mode(&self, channel: u8, mode: ChannelMode) -> Result<u16, I2cError> {
+        let cmd = generate_command_byte(channel, mode, self.use_internal_ref, true);
 
         let mut i2c_guard = self.i2c.lock().await;
         // Write command:
-        i2c_guard.blocking_write(self.address, &[cmd])?;
+        i2c_guard.write(self.address, &[cmd]).await?;
 
         // Read 2 bytes:
         let mut buf = [0; 2];
-        i2c_guard.blocking_read(self.address, &mut buf)?;
+        i2c_guard.read(self.address, &mut buf).await?;
 
-        // Extract the 12-bit sample:
-        let sample = (((buf[0] & 0x0F) as u16) << 8) | (buf[1] as u16);
-        Ok(sample)
+        Ok(sample_from_bytes(buf))
+    }
+
+    /// Like [`Ads7828::get_channel`], but the command byte and the result go in one
+    /// transaction with a repeated start between them, saving a STOP/START per conversion.
+    pub async fn get_channel_wr(&self, channel: u8) -> Result<u16, I2cError> {
+        let cmd = generate_command_byte(
+            channel,
+            ChannelMode::SingleEnded,
+            self.use_internal_ref,
+            true,
+        );
+        let mut buf = [0; 2];
+        self.i2c
+            .lock()
+            .await
+            .write_read(self.address, &[cmd], &mut buf)
+            .await?;
+        Ok(sample_from_bytes(buf))
     }
 
-    /// Read all 8 channels (0..7).
-    pub async fn get_channels(&self, _nostop: bool) -> Result<[u16; 8], I2cError> {
+    /// Read all 8 channels (0..7), one write-read transaction each.
+    pub async fn get_channels(&self) -> Result<[u16; 8], I2cError> {
         let mut out = [0; 8];
         for (i, val) in out.iter_mut().enumerate() {
-            *val = self.get_channel(i as u8, true).await?;
+            *val = self.get_channel_wr(i as u8).await?;
         }
         Ok(out)
     }
@@ -0,0 +1,96 @@
+//! The ADS7828's command byte and the format of its reply.
+
+// Map from your original code
+const ADS7828_CHANNEL_MAP: [u8; 8] = [
+    0b00000000, 0b01000000, 0b00010000, 0b01010000, 0b00100000, 0b01100000, 0b00110000, 0b01110000,
+];
+
+// Differential selects (SD = 0), indexed by C2 C1 C0:
+//   0: CH0+ CH1-    4: CH0- CH1+
+//   1: CH2+ CH3-    5: CH2- CH3+
+//   2: CH4+ CH5-    6: CH4- CH5+
+//   3: CH6+ CH7-    7: CH6- CH7+
+const ADS7828_DIFFERENTIAL_MAP: [u8; 8] = [
+    0b00000000, 0b00010000, 0b00100000, 0b00110000, 0b01000000, 0b01010000, 0b01100000, 0b01110000,
+];
+
+/// Input configuration for a conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    /// `channel` 0..7 against COM.
+    SingleEnded,
+    /// `channel` 0..7 selects a pair from `ADS7828_DIFFERENTIAL_MAP`. The result is still
+    /// straight binary, so a negative difference reads as 0.
+    Differential,
+}
+
+/// Command byte starting a conversion of `channel` (0..7), or 0 for a channel out of range.
+/// `ref_on` and `converter_on` are the PD1 and PD0 power-down bits, left set after it.
+pub fn generate_command_byte(
+    channel: u8,
+    mode: ChannelMode,
+    ref_on: bool,
+    converter_on: bool,
+) -> u8 {
+    if channel > 7 {
+        return 0; // clamp or handle error
+    }
+    let mut byte = match mode {
+        ChannelMode::SingleEnded => 0b1000_0000 | ADS7828_CHANNEL_MAP[channel as usize],
+        ChannelMode::Differential => ADS7828_DIFFERENTIAL_MAP[channel as usize],
+    };
+
+    if ref_on {
+        byte |= 0b0000_1000;
+    }
+    if converter_on {
+        byte |= 0b0000_0100;
+    }
+    byte
+}
+
+/// Extracts the 12-bit sample from the two bytes read back: four leading zero bits, then the
+/// conversion MSB first.
+pub fn sample_from_bytes(buf: [u8; 2]) -> u16 {
+    (((buf[0] & 0x0F) as u16) << 8) | (buf[1] as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_down_bits() {
+        let ch0 = |ref_on, converter_on| {
+            generate_command_byte(0, ChannelMode::SingleEnded, ref_on, converter_on)
+        };
+        assert_eq!(ch0(false, false), 0b1000_0000);
+        assert_eq!(ch0(false, true), 0b1000_0100);
+        assert_eq!(ch0(true, false), 0b1000_1000);
+        assert_eq!(ch0(true, true), 0b1000_1100);
+    }
+
+    #[test]
+    fn channel_select() {
+        // Single-ended CH1 is C2 C1 C0 = 100; differential pair 1 (CH2+ CH3-) is 001.
+        assert_eq!(
+            generate_command_byte(1, ChannelMode::SingleEnded, false, true),
+            0b1100_0100
+        );
+        assert_eq!(
+            generate_command_byte(1, ChannelMode::Differential, false, true),
+            0b0001_0100
+        );
+        assert_eq!(
+            generate_command_byte(8, ChannelMode::SingleEnded, true, true),
+            0
+        );
+    }
+    #[test]
+    fn reply_is_a_12_bit_sample() {
+        assert_eq!(sample_from_bytes([0x0A, 0xBC]), 0x0ABC);
+        assert_eq!(sample_from_bytes([0x0F, 0xFF]), 4095);
+        // The leading bits are not part of the sample.
+        assert_eq!(sample_from_bytes([0xF1, 0x23]), 0x0123);
+    }
+}
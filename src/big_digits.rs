@@ -0,0 +1,108 @@
+//! Double-height digits for the 16x2 LCD, drawn from eight custom CGRAM characters.
+//!
+//! Each digit is three columns wide and spans both rows; a decimal point takes one column.
+//! [`load_big_digits`] overwrites all eight CGRAM slots, so call it again after anything else
+//! has loaded its own characters.
+
+use core::fmt::Write;
+use heapless::String;
+
+use crate::lcd::{Lcd, LcdBus};
+
+// CGRAM slots holding the segment pieces.
+const LT: u8 = 0; // upper left corner
+const UB: u8 = 1; // upper bar
+const RT: u8 = 2; // upper right corner
+const LL: u8 = 3; // lower left corner
+const LB: u8 = 4; // lower bar
+const LR: u8 = 5; // lower right corner
+const UMB: u8 = 6; // upper bar with a middle bar below it
+const LMB: u8 = 7; // lower bar with a middle bar above it
+
+// HD44780 ROM (A00) full block and blank.
+const FULL: u8 = 0xFF;
+const BLANK: u8 = b' ';
+
+const PATTERNS: [[u8; 8]; 8] = [
+    [0x07, 0x0F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F], // LT
+    [0x1F, 0x1F, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00], // UB
+    [0x1C, 0x1E, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F], // RT
+    [0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x0F, 0x07], // LL
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x1F, 0x1F, 0x1F], // LB
+    [0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1E, 0x1C], // LR
+    [0x1F, 0x1F, 0x1F, 0x00, 0x00, 0x00, 0x1F, 0x1F], // UMB
+    [0x1F, 0x00, 0x00, 0x00, 0x00, 0x1F, 0x1F, 0x1F], // LMB
+];
+
+/// Top and bottom row of each digit, 0 to 9.
+const DIGITS: [([u8; 3], [u8; 3]); 10] = [
+    ([LT, UB, RT], [LL, LB, LR]),
+    ([UB, RT, BLANK], [LB, FULL, LB]),
+    ([UMB, UMB, RT], [LL, LMB, LMB]),
+    ([UMB, UMB, RT], [LMB, LMB, LR]),
+    ([LL, LB, FULL], [BLANK, BLANK, FULL]),
+    ([LL, UMB, UMB], [LMB, LMB, LR]),
+    ([LT, UMB, UMB], [LL, LMB, LR]),
+    ([UB, UB, RT], [BLANK, LT, BLANK]),
+    ([LT, UMB, RT], [LL, LMB, LR]),
+    ([LT, UMB, RT], [BLANK, BLANK, FULL]),
+];
+
+/// Longest number `draw_big_number` will format; anything past it is dropped.
+const MAX_CHARS: usize = 8;
+
+/// Loads the segment pieces into CGRAM slots 0-7.
+pub async fn load_big_digits<B: LcdBus>(lcd: &mut Lcd<B>) {
+    for (location, pattern) in PATTERNS.iter().enumerate() {
+        lcd.create_char(location as u8, pattern).await;
+    }
+}
+
+/// Draws `value` with `decimals` decimal places across both rows starting at column `col`, and
+/// returns the first column after it. Negative values are drawn as 0; there is no minus sign.
+/// Digits are separated by a blank column, the decimal point sits between them on its own.
+pub async fn draw_big_number<B: LcdBus>(
+    lcd: &mut Lcd<B>,
+    value: f32,
+    decimals: usize,
+    col: u8,
+) -> u8 {
+    let mut text = String::<MAX_CHARS>::new();
+    write!(&mut text, "{:.*}", decimals, value.max(0.0)).ok();
+
+    let mut x = col;
+    let mut previous_digit = false;
+    for ch in text.chars() {
+        let digit = ch.to_digit(10);
+        if digit.is_some() && previous_digit {
+            for row in 0..2 {
+                lcd.set_cursor(x, row).await;
+                lcd.write_char(BLANK).await;
+            }
+            x += 1;
+        }
+        match digit {
+            Some(d) => {
+                let (top, bottom) = DIGITS[d as usize];
+                for (row, glyph) in [(0, top), (1, bottom)] {
+                    lcd.set_cursor(x, row).await;
+                    for code in glyph {
+                        lcd.write_char(code).await;
+                    }
+                }
+                x += 3;
+            }
+            None if ch == '.' => {
+                lcd.set_cursor(x, 0).await;
+                lcd.write_char(BLANK).await;
+                lcd.set_cursor(x, 1).await;
+                lcd.write_char(b'.').await;
+                x += 1;
+            }
+            // "inf" and the like have no glyphs; callers clamp to what fits anyway.
+            None => continue,
+        }
+        previous_digit = digit.is_some();
+    }
+    x
+}
@@ -0,0 +1,126 @@
+//! Board-level peripheral assignment.
+//!
+//! Hardware revisions move signals around; the pin and slice choices live here so `main` and
+//! the control path never have to name them directly.
+
+use embassy_rp::i2c::Async;
+use embassy_rp::peripherals::{I2C0, PIN_0, PIN_1, PIN_26, PIN_29, PWM_SLICE0};
+
+use crate::ads7828::Ads7828;
+use crate::lcd::{Lcd, ParallelBus};
+use crate::mlx90614::{Mlx90614, ObjectChannel};
+use crate::sensors::{AdsChannelMap, PcbSensor};
+use crate::state::Calibration;
+
+/// PWM slice driving the half-bridge gate signals.
+pub type InverterPwmSlice = PWM_SLICE0;
+/// High-side gate signal, channel A of [`InverterPwmSlice`].
+pub type InverterPwmPinA = PIN_0;
+/// Low-side gate signal, channel B of [`InverterPwmSlice`].
+pub type InverterPwmPinB = PIN_1;
+
+/// DC bus voltage divider, on an on-chip ADC input (GPIO 26..29).
+pub type VoltageSensePin = PIN_26;
+/// Hall current sensor output, on an on-chip ADC input.
+pub type CurrentSensePin = PIN_29;
+
+/// Channel B is driven as the complement of channel A; the dead-time math in
+/// `utils::pwm_enable` relies on this.
+pub const INVERTER_INVERT_B: bool = true;
+
+/// Water-cooled heads wire a flow/pressure switch to `PIN_8`, closing to ground while coolant
+/// circulates. Heating will not start, and stops at once, while the switch is open.
+///
+/// Leave this off on air-cooled installs without a switch; the pull-up would read as no flow.
+pub const COOLANT_FLOW_REQUIRED: bool = false;
+
+/// Run the ADS7828 from its internal 2.5 V reference. Off on boards that feed REF IN from the
+/// 5 V rail; with it on, any input above 2.5 V reads as full scale.
+pub const ADS7828_INTERNAL_REF: bool = false;
+
+/// I2C standard mode, the rate every part on the buses is guaranteed to run at.
+pub const I2C_STANDARD_MODE_HZ: u32 = 100_000;
+/// I2C fast mode.
+pub const I2C_FAST_MODE_HZ: u32 = 400_000;
+
+/// Clock for the ADS7828 bus (I2C1). `main` reads the chip once at boot and drops the bus to
+/// [`I2C_STANDARD_MODE_HZ`] if fast mode does not answer; boards with long leads or weak
+/// pull-ups can set standard mode here outright.
+pub const ADS_I2C_FREQUENCY_HZ: u32 = I2C_FAST_MODE_HZ;
+/// Clock for the MLX90614 bus (I2C0). The MLX90614 is an SMBus part rated to 100 kHz, so it
+/// stays at standard mode; a faster rate here gets the same boot check as
+/// [`ADS_I2C_FREQUENCY_HZ`]. The bus recovery in `mlx_task` re-applies whichever rate is in use.
+pub const MLX_I2C_FREQUENCY_HZ: u32 = I2C_STANDARD_MODE_HZ;
+
+/// With the `rotary-encoder` feature, whether turning clockwise steps Up. Flip it if the A/B
+/// lines are wired the other way round.
+pub const ENCODER_CLOCKWISE_UP: bool = true;
+
+/// DC bus floor while heating: below it the inverter is tripped with `DcUndervoltage` rather
+/// than left hard-switching off a sagging bus. Set it under the lowest bus voltage the supply
+/// gives at full load; a 230 V single-phase supply rectifies to about 325 V.
+pub const DC_UNDERVOLTAGE_V: f32 = 250.0;
+
+/// IR thermometer zone read as the object temperature. `Object2` needs a dual-zone MLX90614
+/// (xBx); `mlx_task` drops back to `Object1` if the part turns out to be single-zone.
+pub const MLX_OBJECT_CHANNEL: ObjectChannel = ObjectChannel::Object1;
+
+/// ADS7828 inputs of the coil NTC and the PCB temperature sensor.
+pub const ADS_CHANNELS: AdsChannelMap = AdsChannelMap {
+    coil_ntc: 6,
+    pcb_temp: 3,
+};
+
+/// Sensor on the PCB temperature channel of the ADS7828. Boards with an NTC divider there use
+/// e.g. `PcbSensor::Ntc { beta: 3950.0, r0: 10_000.0, series: 10_000.0 }`.
+pub const PCB_SENSOR: PcbSensor = PcbSensor::Lm35;
+
+/// This unit's sensor trims, loaded into `state::CALIBRATION` at boot. Identity until the unit
+/// has been calibrated against reference instruments.
+pub const SENSOR_CALIBRATION: Calibration = Calibration::new();
+
+/// The operator display. Boards with a PCF8574 backpack swap in `lcd::Pcf8574Bus` here.
+pub type DisplayLcd = Lcd<ParallelBus<'static>>;
+
+/// The ADS7828 on I2C1. A board that has to keep the bus blocking puts `i2c::Blocking` here
+/// and builds the bus with `I2c::new_blocking` in `main`.
+pub type SensorAdc = Ads7828<'static, Async>;
+/// The MLX90614 on I2C0; the bus mode works as for [`SensorAdc`].
+pub type IrThermometer = Mlx90614<'static, I2C0, Async>;
+
+pub struct InverterPwmResources {
+    pub slice: InverterPwmSlice,
+    pub pin_a: InverterPwmPinA,
+    pub pin_b: InverterPwmPinB,
+}
+
+/// Moves the inverter PWM slice and pins out of `Peripherals`.
+///
+/// Keep the field names in sync with the type aliases above; a mismatch fails to compile.
+macro_rules! inverter_pwm_resources {
+    ($p:ident) => {
+        $crate::board::InverterPwmResources {
+            slice: $p.PWM_SLICE0,
+            pin_a: $p.PIN_0,
+            pin_b: $p.PIN_1,
+        }
+    };
+}
+pub(crate) use inverter_pwm_resources;
+
+pub struct SenseAdcResources {
+    pub voltage: VoltageSensePin,
+    pub current: CurrentSensePin,
+}
+
+/// Moves the voltage and current sense pins out of `Peripherals`, with the same rule as
+/// [`inverter_pwm_resources`].
+macro_rules! sense_adc_resources {
+    ($p:ident) => {
+        $crate::board::SenseAdcResources {
+            voltage: $p.PIN_26,
+            current: $p.PIN_29,
+        }
+    };
+}
+pub(crate) use sense_adc_resources;
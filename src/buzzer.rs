@@ -0,0 +1,56 @@
+use embassy_futures::select::{select, Either};
+use embassy_rp::gpio::Output;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+
+use crate::state::{WarningLevel, FAULT_STATE};
+
+const IDLE_POLL: Duration = Duration::from_millis(100);
+const CHIRP_ON: Duration = Duration::from_millis(40);
+
+static BUZZER_CHIRP: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Request a short acknowledgement beep. Ignored while a warning cadence is playing.
+pub fn chirp() {
+    BUZZER_CHIRP.signal(());
+}
+
+#[embassy_executor::task]
+pub async fn buzzer_task(mut buzzer: Output<'static>) {
+    buzzer.set_low();
+
+    loop {
+        let level = FAULT_STATE.lock().await.warning;
+
+        match cadence(level) {
+            Some((on, off)) => {
+                buzzer.set_high();
+                Timer::after(on).await;
+                if off > Duration::from_ticks(0) {
+                    buzzer.set_low();
+                    Timer::after(off).await;
+                }
+            }
+            None => {
+                buzzer.set_low();
+                if let Either::Second(()) =
+                    select(Timer::after(IDLE_POLL), BUZZER_CHIRP.wait()).await
+                {
+                    buzzer.set_high();
+                    Timer::after(CHIRP_ON).await;
+                    buzzer.set_low();
+                }
+            }
+        }
+    }
+}
+
+/// On/off times for each warning level; a zero off time holds the tone solid.
+fn cadence(level: WarningLevel) -> Option<(Duration, Duration)> {
+    match level {
+        WarningLevel::None => None,
+        WarningLevel::Approaching => Some((Duration::from_millis(80), Duration::from_millis(920))),
+        WarningLevel::Near => Some((Duration::from_millis(80), Duration::from_millis(220))),
+        WarningLevel::Trip => Some((Duration::from_millis(100), Duration::from_ticks(0))),
+    }
+}
@@ -0,0 +1,455 @@
+//! The persisted configuration block and its byte format.
+//!
+//! A block is a version byte, the encoded fields and a CRC-32 over both. Flash storage writes it
+//! to the settings sector, and anything dumping or loading the configuration over a serial link
+//! should move the same bytes. Blocks from older versions are shorter;
+//! [`PersistedConfig::deserialize`] reads them with defaults for whatever they lack, so a firmware
+//! update never strands a unit's settings. Whenever the layout grows, bump `CONFIG_VERSION` and
+//! add the previous version's length to the table in `decode`.
+
+use crate::settings::{
+    CalPair, Calibration, Commissioning, ControlMode, ControlSettings, IdleRunAction, LimitKind,
+    Limits, Profile, Profiles, TempUnit, UsageStats, PROFILE_COUNT, PROFILE_NAME_LEN,
+};
+
+const CONFIG_VERSION: u8 = 8;
+/// Length of a current block, CRC included.
+pub const CONFIG_LEN: usize = 180;
+// Version 1 records end before the temperature unit byte; they still load, in °C. Version 2
+// records end before the limits; they load with the default limits. Version 3 records end
+// before the run-time limit and the profiles; they load with no limit and the default profiles.
+// Version 4 records end before the cycle count, which starts from zero. Version 5 records end
+// before the soak time; they load with no soak. Version 6 blocks end before the sensor
+// calibration; they load with the caller's default calibration. Version 7 blocks end before the
+// tuned resonance; they load untuned.
+const V1_CONFIG_LEN: usize = 26;
+const V2_CONFIG_LEN: usize = 27;
+const V3_CONFIG_LEN: usize = 47;
+const V4_CONFIG_LEN: usize = 130;
+const V5_CONFIG_LEN: usize = 134;
+const V6_CONFIG_LEN: usize = 136;
+const V7_CONFIG_LEN: usize = 176;
+const LIMITS_OFFSET: usize = 23;
+const RUN_TIME_LIMIT_OFFSET: usize = 43;
+const SELECTED_PROFILE_OFFSET: usize = 45;
+const PROFILES_OFFSET: usize = 46;
+// Present flag, name, mode, power, target, run-time limit.
+const PROFILE_LEN: usize = 1 + PROFILE_NAME_LEN + 1 + 4 + 4 + 2;
+const HEATING_CYCLES_OFFSET: usize = PROFILES_OFFSET + PROFILE_COUNT * PROFILE_LEN;
+const SOAK_OFFSET: usize = HEATING_CYCLES_OFFSET + 4;
+// Offset and gain of each trim, in `Calibration` field order.
+const CALIBRATION_OFFSET: usize = SOAK_OFFSET + 2;
+const CAL_PAIR_LEN: usize = 8;
+// 0 for none.
+const RESONANT_FREQ_OFFSET: usize = CALIBRATION_OFFSET + 5 * CAL_PAIR_LEN;
+
+/// Everything that survives a power cycle.
+#[derive(Debug, Clone, Copy)]
+pub struct PersistedConfig {
+    pub settings: ControlSettings,
+    /// Commissioning state and the PCB sensor trim.
+    pub commissioning: Commissioning,
+    pub limits: Limits,
+    pub profiles: Profiles,
+    /// Heating cycle count.
+    pub usage: UsageStats,
+    pub calibration: Calibration,
+}
+
+impl PersistedConfig {
+    /// The current-version block for this configuration.
+    pub fn serialize(&self) -> [u8; CONFIG_LEN] {
+        encode(self)
+    }
+
+    /// Reads a block of any known version, or `None` if it is blank, from an unknown version
+    /// or corrupt. `buf` may be longer than the block. Blocks from before the sensor
+    /// calibration was stored load with `default_calibration`, the board's nominal trims.
+    pub fn deserialize(buf: &[u8], default_calibration: Calibration) -> Option<Self> {
+        decode(buf, default_calibration)
+    }
+}
+
+fn encode(stored: &PersistedConfig) -> [u8; CONFIG_LEN] {
+    let settings = &stored.settings;
+    let mut buf = [0u8; CONFIG_LEN];
+    buf[0] = CONFIG_VERSION;
+    buf[1] = mode_to_u8(settings.mode);
+    buf[2..6].copy_from_slice(&settings.manual_power_kw.to_le_bytes());
+    buf[6..10].copy_from_slice(&settings.target_temp_c.to_le_bytes());
+    buf[10] = settings.hold_at_target as u8;
+    buf[11..15].copy_from_slice(&settings.power_floor_kw.to_le_bytes());
+    buf[15] = match settings.idle_run_action {
+        IdleRunAction::Ignore => 0,
+        IdleRunAction::StartLastMode => 1,
+    };
+    buf[16] = mode_to_u8(settings.last_run_mode);
+    buf[17] = stored.commissioning.commissioned as u8;
+    buf[18..22].copy_from_slice(&stored.commissioning.pcb_temp_offset_c.to_le_bytes());
+    buf[22] = match settings.temp_unit {
+        TempUnit::Celsius => 0,
+        TempUnit::Fahrenheit => 1,
+    };
+    for (i, kind) in LimitKind::ALL.into_iter().enumerate() {
+        let at = LIMITS_OFFSET + i * 4;
+        buf[at..at + 4].copy_from_slice(&stored.limits.get(kind).to_le_bytes());
+    }
+    buf[RUN_TIME_LIMIT_OFFSET..RUN_TIME_LIMIT_OFFSET + 2]
+        .copy_from_slice(&settings.run_time_limit_s.to_le_bytes());
+    buf[SELECTED_PROFILE_OFFSET] = stored.profiles.selected as u8;
+    for (i, slot) in stored.profiles.slots.iter().enumerate() {
+        if let Some(profile) = slot {
+            encode_profile(
+                profile,
+                &mut buf[PROFILES_OFFSET + i * PROFILE_LEN..][..PROFILE_LEN],
+            );
+        }
+    }
+    buf[HEATING_CYCLES_OFFSET..HEATING_CYCLES_OFFSET + 4]
+        .copy_from_slice(&stored.usage.heating_cycles.to_le_bytes());
+    buf[SOAK_OFFSET..SOAK_OFFSET + 2].copy_from_slice(&settings.soak_s.to_le_bytes());
+    for (i, pair) in cal_pairs(&stored.calibration).into_iter().enumerate() {
+        let at = CALIBRATION_OFFSET + i * CAL_PAIR_LEN;
+        buf[at..at + 4].copy_from_slice(&pair.offset.to_le_bytes());
+        buf[at + 4..at + 8].copy_from_slice(&pair.gain.to_le_bytes());
+    }
+    buf[RESONANT_FREQ_OFFSET..RESONANT_FREQ_OFFSET + 4]
+        .copy_from_slice(&settings.resonant_freq_hz.unwrap_or(0.0).to_le_bytes());
+    let crc = crc32(&buf[..CONFIG_LEN - 4]);
+    buf[CONFIG_LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+    buf
+}
+
+fn decode(buf: &[u8], default_calibration: Calibration) -> Option<PersistedConfig> {
+    let len = match *buf.first()? {
+        1 => V1_CONFIG_LEN,
+        2 => V2_CONFIG_LEN,
+        3 => V3_CONFIG_LEN,
+        4 => V4_CONFIG_LEN,
+        5 => V5_CONFIG_LEN,
+        6 => V6_CONFIG_LEN,
+        7 => V7_CONFIG_LEN,
+        CONFIG_VERSION => CONFIG_LEN,
+        _ => return None,
+    };
+    if buf.len() < len {
+        return None;
+    }
+    let crc = u32::from_le_bytes(buf[len - 4..len].try_into().ok()?);
+    if crc != crc32(&buf[..len - 4]) {
+        return None;
+    }
+
+    let f32_at = |at: usize| buf[at..at + 4].try_into().ok().map(f32::from_le_bytes);
+    let settings = ControlSettings {
+        mode: mode_from_u8(buf[1])?,
+        manual_power_kw: f32_at(2)?,
+        target_temp_c: f32_at(6)?,
+        hold_at_target: buf[10] != 0,
+        power_floor_kw: f32_at(11)?,
+        idle_run_action: match buf[15] {
+            0 => IdleRunAction::Ignore,
+            1 => IdleRunAction::StartLastMode,
+            _ => return None,
+        },
+        last_run_mode: mode_from_u8(buf[16])?,
+        armed: false,
+        temp_unit: match (buf[0], buf[22]) {
+            (1, _) | (_, 0) => TempUnit::Celsius,
+            (_, 1) => TempUnit::Fahrenheit,
+            _ => return None,
+        },
+        run_time_limit_s: if buf[0] >= 4 {
+            u16::from_le_bytes([buf[RUN_TIME_LIMIT_OFFSET], buf[RUN_TIME_LIMIT_OFFSET + 1]])
+        } else {
+            0
+        },
+        manual_freq_hz: ControlSettings::new().manual_freq_hz,
+        soak_s: if buf[0] >= 6 {
+            u16::from_le_bytes([buf[SOAK_OFFSET], buf[SOAK_OFFSET + 1]])
+        } else {
+            0
+        },
+        resonant_freq_hz: if buf[0] >= 8 {
+            Some(f32_at(RESONANT_FREQ_OFFSET)?).filter(|&freq_hz| freq_hz > 0.0)
+        } else {
+            None
+        },
+    };
+    let commissioning = Commissioning {
+        commissioned: buf[17] != 0,
+        pcb_temp_offset_c: f32_at(18)?,
+    };
+    // Out-of-range limits (from a build with higher ceilings) are clamped, not rejected.
+    let mut limits = Limits::new();
+    if buf[0] >= 3 {
+        for (i, kind) in LimitKind::ALL.into_iter().enumerate() {
+            limits.set(kind, f32_at(LIMITS_OFFSET + i * 4)?);
+        }
+    }
+    let mut profiles = Profiles::new();
+    if buf[0] >= 4 {
+        profiles.selected = (buf[SELECTED_PROFILE_OFFSET] as usize).min(PROFILE_COUNT - 1);
+        for (i, slot) in profiles.slots.iter_mut().enumerate() {
+            *slot = decode_profile(&buf[PROFILES_OFFSET + i * PROFILE_LEN..][..PROFILE_LEN])?;
+        }
+    }
+    let mut usage = UsageStats::new();
+    if buf[0] >= 5 {
+        usage.heating_cycles = u32::from_le_bytes(
+            buf[HEATING_CYCLES_OFFSET..HEATING_CYCLES_OFFSET + 4]
+                .try_into()
+                .ok()?,
+        );
+    }
+    let mut calibration = default_calibration;
+    if buf[0] >= 7 {
+        let pair_at = |i: usize| {
+            let at = CALIBRATION_OFFSET + i * CAL_PAIR_LEN;
+            Some(CalPair {
+                offset: f32_at(at)?,
+                gain: f32_at(at + 4)?,
+            })
+        };
+        calibration = Calibration {
+            dc_voltage: pair_at(0)?,
+            coil_current: pair_at(1)?,
+            coil_temp: pair_at(2)?,
+            module_temp: pair_at(3)?,
+            object_temp: pair_at(4)?,
+        };
+    }
+    Some(PersistedConfig {
+        settings,
+        commissioning,
+        limits,
+        profiles,
+        usage,
+        calibration,
+    })
+}
+
+/// The trims in block order.
+fn cal_pairs(calibration: &Calibration) -> [CalPair; 5] {
+    [
+        calibration.dc_voltage,
+        calibration.coil_current,
+        calibration.coil_temp,
+        calibration.module_temp,
+        calibration.object_temp,
+    ]
+}
+
+fn encode_profile(profile: &Profile, buf: &mut [u8]) {
+    buf[0] = 1;
+    buf[1..9].copy_from_slice(&profile.name);
+    buf[9] = mode_to_u8(profile.mode);
+    buf[10..14].copy_from_slice(&profile.manual_power_kw.to_le_bytes());
+    buf[14..18].copy_from_slice(&profile.target_temp_c.to_le_bytes());
+    buf[18..20].copy_from_slice(&profile.run_time_limit_s.to_le_bytes());
+}
+
+/// `Some(None)` for an empty slot, `None` if the slot does not decode.
+fn decode_profile(buf: &[u8]) -> Option<Option<Profile>> {
+    if buf[0] == 0 {
+        return Some(None);
+    }
+    let f32_at = |at: usize| buf[at..at + 4].try_into().ok().map(f32::from_le_bytes);
+    Some(Some(Profile {
+        name: buf[1..9].try_into().ok()?,
+        mode: mode_from_u8(buf[9])?,
+        manual_power_kw: f32_at(10)?,
+        target_temp_c: f32_at(14)?,
+        run_time_limit_s: u16::from_le_bytes([buf[18], buf[19]]),
+    }))
+}
+
+/// Mode numbering of the config block; the Modbus mode register uses it too.
+pub fn mode_to_u8(mode: ControlMode) -> u8 {
+    match mode {
+        ControlMode::Idle => 0,
+        ControlMode::ManualPower => 1,
+        ControlMode::Temperature => 2,
+        ControlMode::Cooldown => 3,
+        ControlMode::AutoTune => 4,
+        ControlMode::ManualFrequency => 5,
+    }
+}
+
+pub fn mode_from_u8(value: u8) -> Option<ControlMode> {
+    match value {
+        0 => Some(ControlMode::Idle),
+        1 => Some(ControlMode::ManualPower),
+        2 => Some(ControlMode::Temperature),
+        3 => Some(ControlMode::Cooldown),
+        4 => Some(ControlMode::AutoTune),
+        5 => Some(ControlMode::ManualFrequency),
+        _ => None,
+    }
+}
+
+/// CRC-32 (IEEE 802.3, reflected), bitwise; the block is too small to need a table.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> PersistedConfig {
+        let mut settings = ControlSettings::new();
+        settings.mode = ControlMode::Temperature;
+        settings.manual_power_kw = 3.5;
+        settings.target_temp_c = 180.0;
+        settings.hold_at_target = true;
+        settings.power_floor_kw = 0.4;
+        settings.idle_run_action = IdleRunAction::StartLastMode;
+        settings.last_run_mode = ControlMode::ManualPower;
+        settings.temp_unit = TempUnit::Fahrenheit;
+        settings.run_time_limit_s = 90;
+        settings.soak_s = 30;
+        settings.resonant_freq_hz = Some(38_250.0);
+        let mut limits = Limits::new();
+        limits.set(LimitKind::PowerKw, 5.0);
+        let mut profiles = Profiles::new();
+        profiles.selected = 1;
+        profiles.slots[1] = Some(Profile::from_settings(*b"BEARING ", &settings));
+        let mut calibration = Calibration::new();
+        calibration.dc_voltage = CalPair {
+            offset: -1.5,
+            gain: 1.02,
+        };
+        calibration.object_temp = CalPair {
+            offset: 2.0,
+            gain: 0.98,
+        };
+        PersistedConfig {
+            settings,
+            commissioning: Commissioning {
+                commissioned: true,
+                pcb_temp_offset_c: -0.75,
+            },
+            limits,
+            profiles,
+            usage: UsageStats {
+                heating_cycles: 1234,
+            },
+            calibration,
+        }
+    }
+
+    /// Loads `buf` with identity trims as the default calibration.
+    fn load(buf: &[u8]) -> Option<PersistedConfig> {
+        PersistedConfig::deserialize(buf, Calibration::new())
+    }
+
+    /// `config` as a block of an older `version`, `len` bytes long.
+    fn as_version(config: &PersistedConfig, version: u8, len: usize) -> [u8; CONFIG_LEN] {
+        let mut buf = config.serialize();
+        buf[0] = version;
+        let crc = crc32(&buf[..len - 4]);
+        buf[len - 4..len].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let config = sample();
+        let loaded = load(&config.serialize()).unwrap();
+        let (s, l) = (&config.settings, &loaded.settings);
+        assert_eq!(l.mode, s.mode);
+        assert_eq!(l.manual_power_kw, s.manual_power_kw);
+        assert_eq!(l.target_temp_c, s.target_temp_c);
+        assert_eq!(l.hold_at_target, s.hold_at_target);
+        assert_eq!(l.power_floor_kw, s.power_floor_kw);
+        assert_eq!(l.idle_run_action, s.idle_run_action);
+        assert_eq!(l.last_run_mode, s.last_run_mode);
+        assert_eq!(l.temp_unit, s.temp_unit);
+        assert_eq!(l.run_time_limit_s, s.run_time_limit_s);
+        assert_eq!(l.soak_s, s.soak_s);
+        assert_eq!(l.resonant_freq_hz, s.resonant_freq_hz);
+        assert!(!l.armed);
+        assert!(loaded.commissioning.commissioned);
+        assert_eq!(loaded.commissioning.pcb_temp_offset_c, -0.75);
+        assert_eq!(loaded.limits, config.limits);
+        assert_eq!(loaded.profiles.selected, 1);
+        assert_eq!(loaded.profiles.slots, config.profiles.slots);
+        assert_eq!(loaded.usage.heating_cycles, 1234);
+        assert_eq!(loaded.calibration, config.calibration);
+    }
+
+    #[test]
+    fn rejects_corrupt_and_unknown_blocks() {
+        let mut buf = sample().serialize();
+        buf[5] ^= 0x01;
+        assert!(load(&buf).is_none());
+        assert!(load(&[0xFF; CONFIG_LEN]).is_none());
+        assert!(load(&[0x00; CONFIG_LEN]).is_none());
+        assert!(load(&sample().serialize()[..CONFIG_LEN - 1]).is_none());
+    }
+
+    #[test]
+    fn v7_loads_untuned() {
+        let config = sample();
+        let loaded = load(&as_version(&config, 7, V7_CONFIG_LEN)).unwrap();
+        assert_eq!(loaded.calibration, config.calibration);
+        assert_eq!(loaded.settings.resonant_freq_hz, None);
+    }
+
+    #[test]
+    fn v6_loads_with_default_calibration() {
+        let loaded = load(&as_version(&sample(), 6, V6_CONFIG_LEN)).unwrap();
+        assert_eq!(loaded.settings.soak_s, 30);
+        assert_eq!(loaded.calibration, Calibration::new());
+    }
+
+    #[test]
+    fn v5_loads_without_soak() {
+        let loaded = load(&as_version(&sample(), 5, V5_CONFIG_LEN)).unwrap();
+        assert_eq!(loaded.usage.heating_cycles, 1234);
+        assert_eq!(loaded.settings.soak_s, 0);
+    }
+
+    #[test]
+    fn v4_loads_with_no_cycles() {
+        let loaded = load(&as_version(&sample(), 4, V4_CONFIG_LEN)).unwrap();
+        assert_eq!(loaded.settings.run_time_limit_s, 90);
+        assert_eq!(loaded.profiles.selected, 1);
+        assert_eq!(loaded.usage.heating_cycles, 0);
+    }
+
+    #[test]
+    fn v3_loads_with_default_profiles() {
+        let config = sample();
+        let loaded = load(&as_version(&config, 3, V3_CONFIG_LEN)).unwrap();
+        assert_eq!(loaded.limits, config.limits);
+        assert_eq!(loaded.settings.run_time_limit_s, 0);
+        assert_eq!(loaded.profiles.slots, Profiles::new().slots);
+    }
+
+    #[test]
+    fn v2_loads_with_default_limits() {
+        let loaded = load(&as_version(&sample(), 2, V2_CONFIG_LEN)).unwrap();
+        assert_eq!(loaded.settings.temp_unit, TempUnit::Fahrenheit);
+        assert_eq!(loaded.limits, Limits::new());
+    }
+
+    #[test]
+    fn v1_loads_in_celsius() {
+        let loaded = load(&as_version(&sample(), 1, V1_CONFIG_LEN)).unwrap();
+        assert_eq!(loaded.settings.mode, ControlMode::Temperature);
+        assert_eq!(loaded.settings.target_temp_c, 180.0);
+        assert!(loaded.commissioning.commissioned);
+        assert_eq!(loaded.settings.temp_unit, TempUnit::Celsius);
+        assert_eq!(loaded.limits, Limits::new());
+    }
+}
@@ -1,206 +1,1052 @@
-use defmt::{info, warn};
-use embassy_rp::gpio::{Input, Output};
-use embassy_rp::pwm::Pwm;
-use embassy_time::{Duration, Instant, Timer};
-
-use crate::{
-    safety::current_fault,
-    state::{ControlMode, CONTROL_SETTINGS, CONTROL_STATUS, MEASUREMENTS, POWER_LIMIT_KW},
-    utils::{pwm_disable, pwm_enable},
-};
-
-const DEADTIME_NS: u32 = 512;
-const BASE_FREQUENCY_HZ: f32 = 45_000.0;
-const MIN_FREQUENCY_HZ: f32 = 29_700.0;
-const MAX_FREQUENCY_HZ: f32 = 45_000.0;
-const CONTROL_PERIOD: Duration = Duration::from_millis(10);
-const CONTROL_DT_S: f32 = 0.010;
-const RUN_DEBOUNCE: Duration = Duration::from_millis(80);
-const TARGET_TOLERANCE_C: f32 = 2.0;
-
-#[embassy_executor::task]
-pub async fn control_task(
-    pwm: &'static mut Pwm<'static>,
-    hs_enable: &'static mut Output<'static>,
-    ls_enable: &'static mut Output<'static>,
-    solenoid: &'static mut Output<'static>,
-    run_button: &'static mut Input<'static>,
-) {
-    let mut power_ctrl = PowerController::new(BASE_FREQUENCY_HZ);
-    let mut temp_ctrl = TemperatureController::new();
-    let mut run_active = false;
-    let mut last_button_low = false;
-    let mut last_toggle = Instant::now() - RUN_DEBOUNCE;
-    let mut pwm_running = false;
-    let mut last_mode = ControlMode::Idle;
-
-    ls_enable.set_low();
-    hs_enable.set_low();
-    solenoid.set_low();
-    pwm_disable(pwm);
-
-    loop {
-        let settings = *CONTROL_SETTINGS.lock().await;
-        let mode = settings.mode;
-        let fault = current_fault().await;
-
-        if mode != last_mode {
-            power_ctrl.reset(BASE_FREQUENCY_HZ);
-            temp_ctrl.reset();
-            run_active = false;
-            pwm_running = false;
-            pwm_disable(pwm);
-            last_mode = mode;
-        }
-
-        let button_low = run_button.is_low();
-        if button_low != last_button_low {
-            if button_low && Instant::now().saturating_duration_since(last_toggle) >= RUN_DEBOUNCE {
-                if matches!(mode, ControlMode::ManualPower | ControlMode::Temperature) {
-                    run_active = !run_active;
-                    info!("Run button toggled -> {}", run_active);
-                }
-                last_toggle = Instant::now();
-            }
-            last_button_low = button_low;
-        }
-
-        if fault != crate::state::FaultCode::None
-            || !matches!(mode, ControlMode::ManualPower | ControlMode::Temperature)
-        {
-            if run_active {
-                warn!("Run cancelled due to fault or mode change");
-            }
-            run_active = false;
-        }
-
-        let mut power_setpoint = 0.0f32;
-        let mut heating = false;
-        let mut switching_freq = 0.0f32;
-        let mut target_reached = false;
-
-        match mode {
-            ControlMode::Cooldown => {
-                solenoid.set_high();
-                pwm_running = false;
-                pwm_disable(pwm);
-                run_active = false;
-                ls_enable.set_low();
-                hs_enable.set_low();
-            }
-            ControlMode::ManualPower | ControlMode::Temperature => {
-                solenoid.set_low();
-
-                let meas = MEASUREMENTS.lock().await;
-                let measured_power = meas.coil_power_kw;
-                let object_temp = meas.object_temp_c;
-                drop(meas);
-
-                if run_active && fault == crate::state::FaultCode::None {
-                    heating = true;
-                } else {
-                    heating = false;
-                }
-
-                if mode == ControlMode::ManualPower {
-                    power_setpoint = settings.manual_power_kw.clamp(0.0, POWER_LIMIT_KW);
-                } else {
-                    target_reached = object_temp >= settings.target_temp_c - TARGET_TOLERANCE_C;
-                    power_setpoint = temp_ctrl
-                        .update(settings.target_temp_c, object_temp, CONTROL_DT_S)
-                        .clamp(0.0, POWER_LIMIT_KW);
-                }
-
-                if heating & !target_reached {
-                    switching_freq =
-                        power_ctrl.update(power_setpoint, measured_power, CONTROL_DT_S);
-                    pwm_enable(pwm, DEADTIME_NS, switching_freq as u32);
-                    pwm_running = true;
-                    ls_enable.set_high();
-                    hs_enable.set_high();
-                } else {
-                    if pwm_running {
-                        pwm_disable(pwm);
-                        pwm_running = false;
-                    }
-                    ls_enable.set_low();
-                    hs_enable.set_low();
-                }
-                switching_freq = power_ctrl.freq_hz;
-            }
-            ControlMode::Idle => {
-                solenoid.set_low();
-                pwm_running = false;
-                pwm_disable(pwm);
-                run_active = false;
-                ls_enable.set_low();
-                hs_enable.set_low();
-            }
-        }
-
-        {
-            let mut status = CONTROL_STATUS.lock().await;
-            status.mode = mode;
-            status.heating_enabled = heating && pwm_running;
-            status.run_active = run_active;
-            status.target_reached = target_reached;
-            status.cooldown_active = mode == ControlMode::Cooldown;
-            status.power_setpoint_kw = power_setpoint;
-            status.switching_freq_hz = switching_freq;
-            status.fault = fault;
-        }
-
-        Timer::after(CONTROL_PERIOD).await;
-    }
-}
-
-struct PowerController {
-    freq_hz: f32,
-    integrator: f32,
-}
-
-impl PowerController {
-    fn new(initial_freq: f32) -> Self {
-        Self {
-            freq_hz: initial_freq,
-            integrator: 0.0,
-        }
-    }
-
-    fn reset(&mut self, initial_freq: f32) {
-        self.freq_hz = initial_freq;
-        self.integrator = 0.0;
-    }
-
-    fn update(&mut self, setpoint_kw: f32, measured_kw: f32, dt: f32) -> f32 {
-        const KP: f32 = -60.0;
-        const KI: f32 = -8.0;
-        let error = setpoint_kw - measured_kw;
-        self.integrator = (self.integrator + error * KI * dt).clamp(-2000.0, 2000.0);
-        self.freq_hz =
-            (self.freq_hz + KP * error + self.integrator).clamp(MIN_FREQUENCY_HZ, MAX_FREQUENCY_HZ);
-        self.freq_hz
-    }
-}
-
-struct TemperatureController {
-    integrator: f32,
-}
-
-impl TemperatureController {
-    fn new() -> Self {
-        Self { integrator: 0.0 }
-    }
-
-    fn reset(&mut self) {
-        self.integrator = 0.0;
-    }
-
-    fn update(&mut self, target_c: f32, measured_c: f32, dt: f32) -> f32 {
-        const KP: f32 = -0.08;
-        const KI: f32 = -0.03;
-        let error = (target_c - measured_c).max(-20.0);
-        self.integrator = (self.integrator + error * KI * dt).clamp(0.0, POWER_LIMIT_KW);
-        (KP * error + self.integrator).clamp(0.0, POWER_LIMIT_KW)
-    }
-}
+use defmt::{info, warn};
+use embassy_futures::select::select;
+use embassy_rp::gpio::{Input, Output};
+use embassy_rp::watchdog::Watchdog;
+use embassy_time::{Duration, Instant, Timer};
+use libm::fabsf;
+
+use crate::{
+    board::COOLANT_FLOW_REQUIRED,
+    buzzer::chirp,
+    estop::GateDrive,
+    safety::{current_fault, fault_watcher},
+    state::{
+        measurements, ControlDiagnostics, ControlMode, DrivePhase, EnergyStats, FaultCode,
+        FaultSeverity, IdleRunAction, Measurements, RunStats, TuneState, COMMISSIONING,
+        CONTROL_DIAGNOSTICS, CONTROL_SETTINGS, CONTROL_STATUS, ENERGY_STATS, LIMITS, RUN_STATS,
+        USAGE_STATS,
+    },
+    storage::request_save,
+};
+
+/// Hardware watchdog period. Only `control_task` feeds it, once per loop after the PWM and
+/// `CONTROL_STATUS` are up to date, so a control loop stuck on a lock resets the chip (and a
+/// reset leaves the PWM off).
+pub const WATCHDOG_TIMEOUT: Duration = Duration::from_millis(500);
+const DEADTIME_NS: u32 = 512;
+const BASE_FREQUENCY_HZ: f32 = 45_000.0;
+pub(crate) const MIN_FREQUENCY_HZ: f32 = 29_700.0;
+pub(crate) const MAX_FREQUENCY_HZ: f32 = 45_000.0;
+// Per-mode bands inside MIN/MAX_FREQUENCY_HZ, applied to the power loop on every mode change.
+// Manual drive gets the whole band for full power. Temperature mode spends most of a run
+// soaking at low power, so it stays further above resonance, where the tank current and the
+// switching losses are lower.
+const MANUAL_MIN_FREQUENCY_HZ: f32 = MIN_FREQUENCY_HZ;
+const MANUAL_MAX_FREQUENCY_HZ: f32 = MAX_FREQUENCY_HZ;
+const TEMPERATURE_MIN_FREQUENCY_HZ: f32 = 33_000.0;
+const TEMPERATURE_MAX_FREQUENCY_HZ: f32 = 43_000.0;
+const CONTROL_PERIOD: Duration = Duration::from_millis(10);
+const CONTROL_DT_S: f32 = 0.010;
+// Largest frequency change the power loop may command in one control period, regardless of
+// gains; keeps big power errors from turning into current transients.
+const MAX_FREQ_STEP_HZ: f32 = 250.0;
+// Fastest the power setpoint handed to the power loop may move, so a saturating temperature
+// loop or a big manual step ramps the drive instead of thumping it. Dropping to zero when the
+// drive stops, and cuts from the derated limit, are not slewed.
+const POWER_SLEW_KW_PER_S: f32 = 5.0;
+// Low-pass time constant on the power loop's derivative term; coil_power_kw is noisy enough
+// that an unfiltered derivative mostly amplifies ADC noise.
+const POWER_DERIVATIVE_TAU_S: f32 = 0.05;
+// Back-calculation anti-windup for the power loop, in 1/s: while the frequency is clamped at
+// the edge of the mode's band, the amount clipped off is fed back into the integrator at this rate,
+// so it stays near what the output can actually deliver and the loop responds as soon as the
+// output comes off the rail. About 1/(0.5 s), between the derivative and integral times.
+const KAW: f32 = 2.0;
+// Backstop on the power loop integrator, in Hz; back-calculation normally keeps it far inside.
+const POWER_INTEGRATOR_LIMIT_HZ: f32 = 2_000.0;
+// On each start the switching frequency sweeps down from MAX_FREQUENCY_HZ to the power loop's
+// frequency over this time, so the tank current builds up instead of stepping.
+const SOFT_START_RAMP: Duration = Duration::from_millis(300);
+// After the soft start the frequency is walked to hold the coil current at STARTUP_CURRENT_A
+// (never more than STARTUP_CURRENT_MAX_FRACTION of the current limit) instead of letting the
+// power loop chase a setpoint it is still far from. The power loop takes over once the current
+// has stayed within STARTUP_CURRENT_BAND_A of the target for STARTUP_SETTLE, once the power
+// asked for is already reached, or after STARTUP_TIMEOUT on a load that never draws it.
+const STARTUP_CURRENT_A: f32 = 40.0;
+const STARTUP_CURRENT_MAX_FRACTION: f32 = 0.5;
+const STARTUP_CURRENT_BAND_A: f32 = 5.0;
+const STARTUP_SETTLE: Duration = Duration::from_millis(100);
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(1);
+// Frequency step per amp of current error in the constant-current phase, each control period.
+const STARTUP_CURRENT_GAIN_HZ_PER_A: f32 = 10.0;
+// A soft fault (see FaultSeverity) sweeps the frequency up to MAX_FREQUENCY_HZ over this time,
+// away from resonance, before the gates go off. Hard faults cut the gates at once.
+const FAULT_RAMP_DOWN: Duration = Duration::from_millis(40);
+// Once the power loop has the drive, the frequency it settles on is swept in a triangle of
+// ±FREQ_DITHER_DEPTH_HZ once every FREQ_DITHER_PERIOD, still inside the mode's band, to spread
+// the switching harmonics for EMC. The loop itself keeps working on the undithered centre.
+// A depth of 0 turns dithering off, which it is until EMC testing on the unit shows it is
+// needed; around 200 Hz is a starting point.
+const FREQ_DITHER_DEPTH_HZ: f32 = 0.0;
+const FREQ_DITHER_PERIOD: Duration = Duration::from_millis(100);
+// Duty-cycle protection for the coil and power stage: after this much heating the run is cut
+// and the head goes to cooldown. The count only starts over once heating has been off for
+// MAX_RUNTIME_REST.
+pub(crate) const MAX_RUNTIME: Duration = Duration::from_secs(120);
+const MAX_RUNTIME_REST: Duration = Duration::from_secs(60);
+// Allowed power falls linearly from the power limit to this fraction of it across the band
+// below the module temperature limit; the ModuleOverTemp trip stays as the backstop.
+const MODULE_DERATE_BAND_C: f32 = 15.0;
+const MODULE_DERATE_MIN_FRACTION: f32 = 0.3;
+// Cooldown counts as done once the object and the coil are both below these, after running
+// for at least COOLDOWN_MIN_TIME so the coolant gets through the head even when entered cold.
+const COOLDOWN_COMPLETE_OBJECT_C: f32 = 50.0;
+const COOLDOWN_COMPLETE_COIL_C: f32 = 40.0;
+const COOLDOWN_MIN_TIME: Duration = Duration::from_secs(10);
+const RUN_DEBOUNCE: Duration = Duration::from_millis(80);
+// Target-reached latches once the object is within TARGET_TOLERANCE_C of the target and only
+// lets go if it then falls TARGET_RELEASE_BAND_C below it; after that it stays off until the
+// next run, so the menu does not flip back and forth around the threshold.
+const TARGET_TOLERANCE_C: f32 = 2.0;
+const TARGET_RELEASE_BAND_C: f32 = 8.0;
+// Temperature-mode feed-forward: steady power to hold the work this far above ambient. A rough
+// linear loss model for the usual parts; the PI trims whatever it gets wrong.
+const TEMP_FEED_FORWARD_KW_PER_C: f32 = 0.004;
+const TEMP_LOG_INTERVAL: Duration = Duration::from_secs(1);
+// Measured coil-current frequency must track the commanded one while heating.
+const FREQ_CHECK_TOLERANCE_HZ: f32 = 3_000.0;
+const FREQ_CHECK_SETTLE: Duration = Duration::from_millis(150);
+const FREQ_CHECK_MISMATCH_LIMIT: u8 = 10;
+// With no measurable current we only complain if enough power was actually asked for.
+const FREQ_CHECK_MIN_SETPOINT_KW: f32 = 1.0;
+// Resonance sweep: MIN_FREQUENCY_HZ up to MAX_FREQUENCY_HZ at a duty low enough that even the
+// peak stays well inside the current limit, pausing at each step for the RMS filter to settle.
+const SWEEP_STEP_HZ: f32 = 250.0;
+const SWEEP_SETTLE: Duration = Duration::from_millis(60);
+const SWEEP_DUTY_PERCENT: u8 = 10;
+// The cycle counter costs a flash erase to persist, so a run of back-to-back cycles saves it
+// every CYCLES_PER_SAVE, and otherwise once the unit has sat idle for USAGE_SAVE_IDLE. A power
+// cut loses at most the cycles since.
+const CYCLES_PER_SAVE: u32 = 10;
+const USAGE_SAVE_IDLE: Duration = Duration::from_secs(60);
+
+#[embassy_executor::task]
+pub async fn control_task(
+    gate_drive: &'static GateDrive,
+    solenoid: &'static mut Output<'static>,
+    run_button: &'static mut Input<'static>,
+    coolant_flow: &'static mut Input<'static>,
+    mut watchdog: Watchdog,
+) {
+    let mut power_ctrl = PowerController::new(BASE_FREQUENCY_HZ, frequency_band(ControlMode::Idle));
+    let mut temp_ctrl = TemperatureController::new();
+    let mut run_active = false;
+    let mut was_run_active = false;
+    let mut unsaved_cycles = 0u32;
+    let mut last_cycle_end = Instant::now();
+    let mut last_button_low = false;
+    let mut last_toggle = Instant::now() - RUN_DEBOUNCE;
+    let mut pwm_running = false;
+    let mut soft_start: Option<Instant> = None;
+    let mut current_startup: Option<CurrentStartup> = None;
+    let mut ramp_down: Option<(Instant, f32)> = None;
+    let mut last_mode = ControlMode::Idle;
+    let mut freq_monitor = FrequencyMonitor::new();
+    let mut pwm_freq_mismatch = false;
+    let mut part_removed = false;
+    let mut coolant_flow_lost = false;
+    let mut power_limit_hits = 0u32;
+    let mut energy_kwh = 0.0f32;
+    let mut run_stats = RunStats::new();
+    let mut target_latch = TargetLatch::Armed;
+    let mut soak_started: Option<Instant> = None;
+    let mut soak_complete = false;
+    let mut run_started = Instant::now();
+    let mut start_on_mode_entry = false;
+    let mut sweep: Option<ResonanceSweep> = None;
+    let mut tune = TuneState::Idle;
+    let mut heated_for = Duration::from_ticks(0);
+    let mut last_heat_tick: Option<Instant> = None;
+    let mut rest_since: Option<Instant> = None;
+    let mut runtime_limited = false;
+    let mut slewed_setpoint_kw = 0.0f32;
+    let mut cooldown_started = Instant::now();
+    let mut fault_rx = fault_watcher();
+
+    gate_drive.disable();
+    solenoid.set_low();
+
+    loop {
+        let settings = *CONTROL_SETTINGS.lock().await;
+        let mode = settings.mode;
+        let fault = current_fault();
+
+        if mode != last_mode {
+            // The power loop starts from the resonance found by the last auto-tune, if any.
+            let base_freq_hz = settings.resonant_freq_hz.unwrap_or(BASE_FREQUENCY_HZ);
+            power_ctrl.reset(base_freq_hz, frequency_band(mode));
+            temp_ctrl.reset();
+            run_active = start_on_mode_entry && mode.is_heating();
+            start_on_mode_entry = false;
+            pwm_running = false;
+            pwm_freq_mismatch = false;
+            part_removed = false;
+            coolant_flow_lost = false;
+            if mode != ControlMode::Cooldown {
+                runtime_limited = false;
+            }
+            cooldown_started = Instant::now();
+            target_latch = TargetLatch::Armed;
+            soak_started = None;
+            soak_complete = false;
+            sweep = None;
+            if mode == ControlMode::AutoTune {
+                // The sweep waits for the run button, like a heating run.
+                tune = TuneState::Idle;
+            }
+            gate_drive.disable();
+            last_mode = mode;
+            if settings.armed {
+                disarm().await;
+            }
+        }
+        if fault != crate::state::FaultCode::None && settings.armed {
+            info!("Fault present, disarming");
+            disarm().await;
+        }
+
+        let button_low = run_button.is_low();
+        if button_low != last_button_low {
+            if button_low && Instant::now().saturating_duration_since(last_toggle) >= RUN_DEBOUNCE {
+                pwm_freq_mismatch = false;
+                part_removed = false;
+                coolant_flow_lost = false;
+                let heating_mode = mode.is_heating();
+                let starting = if heating_mode {
+                    !run_active
+                } else if mode == ControlMode::AutoTune {
+                    sweep.is_none()
+                } else {
+                    mode == ControlMode::Idle
+                        && settings.idle_run_action == IdleRunAction::StartLastMode
+                };
+                if starting && !COMMISSIONING.lock().await.commissioned {
+                    warn!("Run refused: unit not commissioned");
+                    chirp();
+                } else if starting && !coolant_flowing(coolant_flow) {
+                    warn!("Run refused: no coolant flow");
+                    chirp();
+                } else if starting && heated_for >= MAX_RUNTIME {
+                    warn!("Run refused: rest period after max runtime not over");
+                    chirp();
+                } else if starting && !settings.armed {
+                    warn!("Run refused: not armed");
+                    chirp();
+                } else if heating_mode {
+                    run_active = !run_active;
+                    info!("Run button toggled -> {}", run_active);
+                    if run_active {
+                        disarm().await;
+                        power_ctrl.clear_counters();
+                        temp_ctrl.clear_counters();
+                        power_limit_hits = 0;
+                        energy_kwh = 0.0;
+                        run_stats = RunStats::new();
+                        target_latch = TargetLatch::Armed;
+                        soak_started = None;
+                        soak_complete = false;
+                        run_started = Instant::now();
+                    }
+                } else if mode == ControlMode::AutoTune {
+                    if starting {
+                        info!("Starting resonance sweep");
+                        disarm().await;
+                        let started = ResonanceSweep::new();
+                        tune = TuneState::Sweeping {
+                            freq_hz: started.freq_hz,
+                        };
+                        sweep = Some(started);
+                    } else {
+                        info!("Resonance sweep stopped by the run button");
+                        sweep = None;
+                        tune = TuneState::Aborted;
+                    }
+                } else if mode == ControlMode::Idle
+                    && settings.idle_run_action == IdleRunAction::StartLastMode
+                {
+                    info!("Run button in Idle -> starting last heating mode");
+                    CONTROL_SETTINGS.lock().await.mode = settings.last_run_mode;
+                    start_on_mode_entry = true;
+                    power_ctrl.clear_counters();
+                    temp_ctrl.clear_counters();
+                    power_limit_hits = 0;
+                    energy_kwh = 0.0;
+                    run_stats = RunStats::new();
+                    target_latch = TargetLatch::Armed;
+                    soak_started = None;
+                    soak_complete = false;
+                    run_started = Instant::now();
+                } else {
+                    info!("Run button ignored outside a heating mode");
+                    chirp();
+                }
+                last_toggle = Instant::now();
+            }
+            last_button_low = button_low;
+        }
+
+        if fault != crate::state::FaultCode::None || !mode.is_heating() {
+            if run_active {
+                warn!("Run cancelled due to fault or mode change");
+            }
+            run_active = false;
+        }
+
+        if run_active && !coolant_flowing(coolant_flow) {
+            warn!("Coolant flow lost, cutting heat");
+            coolant_flow_lost = true;
+            run_active = false;
+        }
+
+        let mut power_setpoint = 0.0f32;
+        let mut heating = false;
+        let mut switching_freq = 0.0f32;
+        let mut drive_phase = DrivePhase::Off;
+        let mut center_freq: Option<f32> = None;
+        let mut target_reached = false;
+        let mut power_derate = 1.0f32;
+        let mut cooldown_complete = false;
+        let mut soak_remaining_s = None;
+
+        match mode {
+            ControlMode::Cooldown => {
+                solenoid.set_high();
+                pwm_running = false;
+                gate_drive.disable();
+                run_active = false;
+
+                let meas = measurements();
+                cooldown_complete = Instant::now().saturating_duration_since(cooldown_started)
+                    >= COOLDOWN_MIN_TIME
+                    && meas.object_temp_c < COOLDOWN_COMPLETE_OBJECT_C
+                    && meas.coil_temp_c < COOLDOWN_COMPLETE_COIL_C
+                    && !meas.coil_temp_disconnected
+                    && meas.object_temp_valid
+                    && meas.ads_healthy;
+            }
+            ControlMode::ManualPower | ControlMode::Temperature => {
+                solenoid.set_low();
+
+                let meas = measurements();
+                let measured_power = meas.coil_power_kw;
+                let measured_freq = meas.measured_freq_hz;
+                let object_temp = meas.object_temp_c;
+
+                if mode == ControlMode::Temperature && run_active && meas.object_removed {
+                    warn!("Object temperature collapsed, part removed? Pausing heating");
+                    part_removed = true;
+                    run_active = false;
+                    temp_ctrl.reset();
+                }
+
+                // Temperature mode holds off until there is an object temperature to servo on.
+                let awaiting_object_temp =
+                    mode == ControlMode::Temperature && !meas.object_temp_valid;
+                if run_active && fault == crate::state::FaultCode::None && !awaiting_object_temp {
+                    heating = true;
+                } else {
+                    heating = false;
+                }
+
+                let limits = *LIMITS.lock().await;
+                power_derate = module_derate(meas.module_temp_c, limits.module_temp_c());
+                let power_limit = limits.power_kw() * power_derate;
+
+                if mode == ControlMode::ManualPower {
+                    power_setpoint = settings.manual_power_kw.clamp(0.0, power_limit);
+                } else {
+                    target_latch = target_latch.update(object_temp, settings.target_temp_c);
+                    target_reached = target_latch == TargetLatch::Reached;
+                    if !target_reached || !run_active {
+                        // The dwell has to be one continuous stretch at target.
+                        soak_started = None;
+                    } else if settings.soak_s > 0 && !soak_complete {
+                        let started = *soak_started.get_or_insert_with(Instant::now);
+                        let soak = Duration::from_secs(settings.soak_s as u64);
+                        let soaked = Instant::now().saturating_duration_since(started);
+                        if soaked >= soak {
+                            info!("Soak of {} s at target complete", settings.soak_s);
+                            soak_complete = true;
+                        } else {
+                            soak_remaining_s = Some((soak - soaked).as_secs() as u16 + 1);
+                        }
+                    }
+                    power_setpoint = temp_ctrl
+                        .update(
+                            settings.target_temp_c,
+                            object_temp,
+                            meas.ambient_temp_c,
+                            limits.power_kw(),
+                            CONTROL_DT_S,
+                        )
+                        .clamp(0.0, power_limit);
+                }
+
+                let hold = mode == ControlMode::Temperature && settings.hold_at_target;
+                let soaking = soak_remaining_s.is_some();
+                // While holding or soaking at target, a non-zero floor keeps the tank lightly
+                // driven instead of dropping out and restarting from BASE_FREQUENCY_HZ. It never
+                // keeps a run heating past the target on its own.
+                if (hold || soaking) && settings.power_floor_kw > 0.0 {
+                    power_setpoint = power_setpoint.max(settings.power_floor_kw.min(power_limit));
+                }
+
+                if run_active {
+                    run_stats.record(&meas);
+                    if target_reached && run_stats.time_to_target.is_none() {
+                        run_stats.time_to_target =
+                            Some(Instant::now().saturating_duration_since(run_started));
+                    }
+                }
+
+                let requested_kw = power_setpoint;
+                if heating && (!target_reached || hold || soaking) {
+                    let max_step = POWER_SLEW_KW_PER_S * CONTROL_DT_S;
+                    slewed_setpoint_kw = (slewed_setpoint_kw
+                        + (power_setpoint - slewed_setpoint_kw).clamp(-max_step, max_step))
+                    .min(power_limit);
+                    power_setpoint = slewed_setpoint_kw;
+                    if power_setpoint >= power_limit {
+                        power_limit_hits = power_limit_hits.saturating_add(1);
+                    }
+                    if !pwm_running {
+                        soft_start = Some(Instant::now());
+                    }
+                    let ramp_freq =
+                        soft_start.and_then(|started| soft_start_freq(started, power_ctrl.freq_hz));
+                    switching_freq = match ramp_freq {
+                        Some(freq) => {
+                            drive_phase = DrivePhase::SoftStart;
+                            freq
+                        }
+                        None => {
+                            if soft_start.take().is_some() {
+                                current_startup = Some(CurrentStartup::new());
+                            }
+                            let startup_current_a = STARTUP_CURRENT_A
+                                .min(limits.current_a() * STARTUP_CURRENT_MAX_FRACTION);
+                            let holding_current = match current_startup.as_mut() {
+                                Some(startup) => {
+                                    !startup.handed_over(&meas, startup_current_a, requested_kw)
+                                }
+                                None => false,
+                            };
+                            if holding_current {
+                                drive_phase = DrivePhase::ConstantCurrent;
+                                power_ctrl.hold_current(startup_current_a, &meas)
+                            } else {
+                                current_startup = None;
+                                drive_phase = DrivePhase::PowerLoop;
+                                let center =
+                                    power_ctrl.update(power_setpoint, measured_power, CONTROL_DT_S);
+                                center_freq = Some(center);
+                                power_ctrl.dithered(center)
+                            }
+                        }
+                    };
+                    // Refused while an e-stop input is active; the fault follows shortly.
+                    let driving = gate_drive.enable(DEADTIME_NS, switching_freq as u32);
+                    if driving && !pwm_running {
+                        freq_monitor.restart();
+                    }
+                    pwm_running = driving;
+
+                    // An invalid snapshot may be stale; better to under-count than integrate it.
+                    if pwm_running && meas.valid {
+                        energy_kwh += measured_power * CONTROL_DT_S / 3600.0;
+                    }
+
+                    if pwm_running
+                        && freq_monitor.update(switching_freq, measured_freq, power_setpoint)
+                    {
+                        warn!(
+                            "Switching frequency mismatch: commanded {} Hz, measured {} Hz",
+                            switching_freq, measured_freq
+                        );
+                        pwm_freq_mismatch = true;
+                    }
+                } else if let Some(freq) =
+                    fault_ramp_freq(&mut ramp_down, fault, pwm_running, power_ctrl.freq_hz)
+                {
+                    pwm_running = gate_drive.enable(DEADTIME_NS, freq as u32);
+                    drive_phase = DrivePhase::RampDown;
+                    switching_freq = freq;
+                } else {
+                    gate_drive.disable();
+                    pwm_running = false;
+                    soft_start = None;
+                    current_startup = None;
+                    switching_freq = power_ctrl.freq_hz;
+                }
+            }
+            ControlMode::ManualFrequency => {
+                solenoid.set_low();
+                let meas = measurements();
+                heating = run_active && fault == crate::state::FaultCode::None;
+                let fixed_freq = settings
+                    .manual_freq_hz
+                    .clamp(MIN_FREQUENCY_HZ, MAX_FREQUENCY_HZ);
+                if heating {
+                    if !pwm_running {
+                        soft_start = Some(Instant::now());
+                    }
+                    let ramp_freq =
+                        soft_start.and_then(|started| soft_start_freq(started, fixed_freq));
+                    if ramp_freq.is_none() {
+                        soft_start = None;
+                    }
+                    switching_freq = ramp_freq.unwrap_or(fixed_freq);
+                    drive_phase = if ramp_freq.is_some() {
+                        DrivePhase::SoftStart
+                    } else {
+                        DrivePhase::FixedFrequency
+                    };
+                    // Same gate, e-stop and safety trips as the power modes; only the power
+                    // loop (and with it the module derating) is out of the picture.
+                    let driving = gate_drive.enable(DEADTIME_NS, switching_freq as u32);
+                    if driving && !pwm_running {
+                        freq_monitor.restart();
+                    }
+                    pwm_running = driving;
+                    if pwm_running && meas.valid {
+                        energy_kwh += meas.coil_power_kw * CONTROL_DT_S / 3600.0;
+                        run_stats.record(&meas);
+                    }
+                    // Far off resonance there may be too little current to measure, so only a
+                    // measured frequency that disagrees counts here.
+                    if pwm_running
+                        && freq_monitor.update(switching_freq, meas.measured_freq_hz, 0.0)
+                    {
+                        warn!(
+                            "Switching frequency mismatch: commanded {} Hz, measured {} Hz",
+                            switching_freq, meas.measured_freq_hz
+                        );
+                        pwm_freq_mismatch = true;
+                    }
+                } else if let Some(freq) =
+                    fault_ramp_freq(&mut ramp_down, fault, pwm_running, fixed_freq)
+                {
+                    pwm_running = gate_drive.enable(DEADTIME_NS, freq as u32);
+                    drive_phase = DrivePhase::RampDown;
+                    switching_freq = freq;
+                } else {
+                    gate_drive.disable();
+                    pwm_running = false;
+                    soft_start = None;
+                    switching_freq = fixed_freq;
+                }
+            }
+            ControlMode::AutoTune => {
+                solenoid.set_low();
+                if let Some(active) = sweep.as_mut() {
+                    if fault != crate::state::FaultCode::None || !coolant_flowing(coolant_flow) {
+                        warn!("Resonance sweep aborted");
+                        sweep = None;
+                        tune = TuneState::Aborted;
+                    } else if let Some((resonant_freq_hz, peak_current_a)) =
+                        active.update(measurements().coil_current_rms_a)
+                    {
+                        info!(
+                            "Resonance at {} Hz ({} A), power loop now starts there",
+                            resonant_freq_hz, peak_current_a
+                        );
+                        CONTROL_SETTINGS.lock().await.resonant_freq_hz = Some(resonant_freq_hz);
+                        request_save();
+                        sweep = None;
+                        tune = TuneState::Done {
+                            resonant_freq_hz,
+                            peak_current_a,
+                        };
+                    } else {
+                        switching_freq = active.freq_hz;
+                        pwm_running = gate_drive.enable_with_duty(
+                            DEADTIME_NS,
+                            switching_freq as u32,
+                            SWEEP_DUTY_PERCENT,
+                        );
+                        heating = pwm_running;
+                        drive_phase = DrivePhase::FixedFrequency;
+                        tune = TuneState::Sweeping {
+                            freq_hz: switching_freq,
+                        };
+                    }
+                }
+                if sweep.is_none() {
+                    gate_drive.disable();
+                    pwm_running = false;
+                }
+            }
+            ControlMode::Idle => {
+                solenoid.set_low();
+                pwm_running = false;
+                gate_drive.disable();
+                run_active = false;
+            }
+        }
+
+        if !pwm_running {
+            slewed_setpoint_kw = 0.0;
+        }
+
+        let now = Instant::now();
+        if run_active && pwm_running {
+            if let Some(last) = last_heat_tick {
+                heated_for += now.saturating_duration_since(last);
+            }
+            last_heat_tick = Some(now);
+            rest_since = None;
+        } else {
+            last_heat_tick = None;
+            let resting = *rest_since.get_or_insert(now);
+            if now.saturating_duration_since(resting) >= MAX_RUNTIME_REST {
+                heated_for = Duration::from_ticks(0);
+            }
+        }
+        if run_active && heated_for >= MAX_RUNTIME {
+            warn!(
+                "Max runtime of {} s reached, cooling down",
+                MAX_RUNTIME.as_secs()
+            );
+            run_active = false;
+            runtime_limited = true;
+            CONTROL_SETTINGS.lock().await.mode = ControlMode::Cooldown;
+        }
+        if run_active
+            && settings.run_time_limit_s > 0
+            && now.saturating_duration_since(run_started)
+                >= Duration::from_secs(settings.run_time_limit_s as u64)
+        {
+            info!(
+                "Run time limit of {} s reached, cooling down",
+                settings.run_time_limit_s
+            );
+            run_active = false;
+            runtime_limited = true;
+            CONTROL_SETTINGS.lock().await.mode = ControlMode::Cooldown;
+        }
+
+        {
+            let mut status = CONTROL_STATUS.lock().await;
+            status.mode = mode;
+            status.heating_enabled = heating && pwm_running;
+            status.run_active = run_active;
+            status.target_reached = target_reached;
+            status.cooldown_active = mode == ControlMode::Cooldown;
+            status.cooldown_complete = cooldown_complete;
+            status.power_setpoint_kw = power_setpoint;
+            status.switching_freq_hz = switching_freq;
+            status.center_freq_hz = center_freq.unwrap_or(switching_freq);
+            status.drive_phase = if pwm_running {
+                drive_phase
+            } else {
+                DrivePhase::Off
+            };
+            status.pwm_freq_mismatch = pwm_freq_mismatch;
+            status.part_removed = part_removed;
+            status.coolant_flow_lost = coolant_flow_lost;
+            status.tune = tune;
+            status.runtime_limited = runtime_limited;
+            status.power_derate = power_derate;
+            status.soak_remaining_s = soak_remaining_s;
+            status.soak_complete = soak_complete;
+            status.fault = fault;
+        }
+        if was_run_active && !run_active {
+            let cycles = {
+                let mut usage = USAGE_STATS.lock().await;
+                usage.heating_cycles = usage.heating_cycles.saturating_add(1);
+                usage.heating_cycles
+            };
+            info!("Heating cycle {} complete", cycles);
+            unsaved_cycles += 1;
+            last_cycle_end = Instant::now();
+        }
+        // The save waits for heating to be off, which it is here.
+        if unsaved_cycles >= CYCLES_PER_SAVE
+            || (unsaved_cycles > 0
+                && !run_active
+                && Instant::now().saturating_duration_since(last_cycle_end) >= USAGE_SAVE_IDLE)
+        {
+            unsaved_cycles = 0;
+            request_save();
+        }
+        was_run_active = run_active;
+
+        *CONTROL_DIAGNOSTICS.lock().await = ControlDiagnostics {
+            freq_min_clamps: power_ctrl.min_clamps,
+            freq_max_clamps: power_ctrl.max_clamps,
+            temp_integrator_saturations: temp_ctrl.integrator_saturations,
+            power_limit_hits,
+        };
+        *ENERGY_STATS.lock().await = EnergyStats {
+            delivered_energy_kwh: energy_kwh,
+        };
+        *RUN_STATS.lock().await = run_stats;
+        watchdog.feed();
+
+        // A fault transition starts the next pass straight away. The controllers still step by
+        // CONTROL_DT_S; the extra pass only happens when a fault turns the drive off anyway.
+        select(Timer::after(CONTROL_PERIOD), fault_rx.changed()).await;
+    }
+}
+
+/// Fraction of the power limit allowed at `module_temp_c`.
+fn module_derate(module_temp_c: f32, module_limit_c: f32) -> f32 {
+    let band_start = module_limit_c - MODULE_DERATE_BAND_C;
+    let progress = ((module_temp_c - band_start) / MODULE_DERATE_BAND_C).clamp(0.0, 1.0);
+    1.0 - (1.0 - MODULE_DERATE_MIN_FRACTION) * progress
+}
+
+/// Switching-frequency limits (min, max) for the power loop in `mode`.
+fn frequency_band(mode: ControlMode) -> (f32, f32) {
+    match mode {
+        ControlMode::Temperature => (TEMPERATURE_MIN_FREQUENCY_HZ, TEMPERATURE_MAX_FREQUENCY_HZ),
+        _ => (MANUAL_MIN_FREQUENCY_HZ, MANUAL_MAX_FREQUENCY_HZ),
+    }
+}
+
+/// Frequency to command while soft-starting, or `None` once the ramp is over.
+fn soft_start_freq(started: Instant, target_hz: f32) -> Option<f32> {
+    let elapsed = Instant::now().saturating_duration_since(started);
+    if elapsed >= SOFT_START_RAMP {
+        return None;
+    }
+    let progress = elapsed.as_micros() as f32 / SOFT_START_RAMP.as_micros() as f32;
+    Some(MAX_FREQUENCY_HZ - (MAX_FREQUENCY_HZ - target_hz) * progress)
+}
+
+/// Triangle dither at `now`, from -FREQ_DITHER_DEPTH_HZ up to +FREQ_DITHER_DEPTH_HZ and back
+/// once per FREQ_DITHER_PERIOD.
+fn dither_offset_hz(now: Instant) -> f32 {
+    let period_us = FREQ_DITHER_PERIOD.as_micros();
+    let phase = (now.as_micros() % period_us) as f32 / period_us as f32;
+    FREQ_DITHER_DEPTH_HZ * (1.0 - 4.0 * fabsf(phase - 0.5))
+}
+
+/// While a soft fault is stopping a running drive, the frequency to command on the way up to
+/// `MAX_FREQUENCY_HZ` from `from_hz`; `None` once the ramp is over, or at once for a hard fault.
+fn fault_ramp_freq(
+    ramp_down: &mut Option<(Instant, f32)>,
+    fault: FaultCode,
+    pwm_running: bool,
+    from_hz: f32,
+) -> Option<f32> {
+    if !pwm_running || fault == FaultCode::None || fault.severity() == FaultSeverity::Hard {
+        *ramp_down = None;
+        return None;
+    }
+    let (started, from_hz) = *ramp_down.get_or_insert_with(|| {
+        info!("{}: ramping the drive down", fault.message());
+        (Instant::now(), from_hz)
+    });
+    let elapsed = Instant::now().saturating_duration_since(started);
+    if elapsed >= FAULT_RAMP_DOWN {
+        return None;
+    }
+    let progress = elapsed.as_micros() as f32 / FAULT_RAMP_DOWN.as_micros() as f32;
+    Some(from_hz + (MAX_FREQUENCY_HZ - from_hz) * progress)
+}
+
+async fn disarm() {
+    CONTROL_SETTINGS.lock().await.armed = false;
+}
+
+fn coolant_flowing(flow_switch: &Input<'static>) -> bool {
+    !COOLANT_FLOW_REQUIRED || flow_switch.is_low()
+}
+
+/// Hysteresis on target-reached, see `TARGET_RELEASE_BAND_C`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TargetLatch {
+    Armed,
+    Reached,
+    /// Fell out of the release band; waits for the next run.
+    Released,
+}
+
+impl TargetLatch {
+    fn update(self, object_c: f32, target_c: f32) -> Self {
+        match self {
+            TargetLatch::Armed if object_c >= target_c - TARGET_TOLERANCE_C => TargetLatch::Reached,
+            TargetLatch::Reached if object_c < target_c - TARGET_RELEASE_BAND_C => {
+                TargetLatch::Released
+            }
+            latch => latch,
+        }
+    }
+}
+
+/// Steps the switching frequency across the allowed band and remembers where the coil
+/// current peaked.
+struct ResonanceSweep {
+    freq_hz: f32,
+    step_started: Instant,
+    best_freq_hz: f32,
+    best_current_a: f32,
+}
+
+impl ResonanceSweep {
+    fn new() -> Self {
+        Self {
+            freq_hz: MIN_FREQUENCY_HZ,
+            step_started: Instant::now(),
+            best_freq_hz: BASE_FREQUENCY_HZ,
+            best_current_a: 0.0,
+        }
+    }
+
+    /// Moves to the next step once the current at this one has settled. Returns the peak
+    /// frequency and current after the last step.
+    fn update(&mut self, current_a: f32) -> Option<(f32, f32)> {
+        if Instant::now().saturating_duration_since(self.step_started) < SWEEP_SETTLE {
+            return None;
+        }
+        if current_a > self.best_current_a {
+            self.best_current_a = current_a;
+            self.best_freq_hz = self.freq_hz;
+        }
+        if self.freq_hz >= MAX_FREQUENCY_HZ {
+            return Some((self.best_freq_hz, self.best_current_a));
+        }
+        self.freq_hz = (self.freq_hz + SWEEP_STEP_HZ).min(MAX_FREQUENCY_HZ);
+        self.step_started = Instant::now();
+        None
+    }
+}
+
+/// The constant-current phase of a start, see `STARTUP_CURRENT_A`.
+struct CurrentStartup {
+    started: Instant,
+    settled_since: Option<Instant>,
+}
+
+impl CurrentStartup {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            settled_since: None,
+        }
+    }
+
+    /// Whether the power loop should take over now.
+    fn handed_over(&mut self, meas: &Measurements, target_a: f32, requested_kw: f32) -> bool {
+        let now = Instant::now();
+        if meas.valid && meas.coil_power_kw >= requested_kw {
+            info!("Startup: requested power reached, power loop taking over");
+            return true;
+        }
+        if meas.valid && fabsf(meas.coil_current_rms_a - target_a) <= STARTUP_CURRENT_BAND_A {
+            let settled = *self.settled_since.get_or_insert(now);
+            if now.saturating_duration_since(settled) >= STARTUP_SETTLE {
+                info!(
+                    "Startup: current settled at {} A, power loop taking over",
+                    meas.coil_current_rms_a
+                );
+                return true;
+            }
+        } else {
+            self.settled_since = None;
+        }
+        if now.saturating_duration_since(self.started) >= STARTUP_TIMEOUT {
+            warn!(
+                "Startup: current only reached {} A, power loop taking over",
+                meas.coil_current_rms_a
+            );
+            return true;
+        }
+        false
+    }
+}
+
+struct PowerController {
+    freq_hz: f32,
+    min_freq_hz: f32,
+    max_freq_hz: f32,
+    integrator: f32,
+    prev_error: Option<f32>,
+    derivative: f32,
+    min_clamps: u32,
+    max_clamps: u32,
+}
+
+impl PowerController {
+    fn new(initial_freq: f32, (min_freq_hz, max_freq_hz): (f32, f32)) -> Self {
+        Self {
+            freq_hz: initial_freq.clamp(min_freq_hz, max_freq_hz),
+            min_freq_hz,
+            max_freq_hz,
+            integrator: 0.0,
+            prev_error: None,
+            derivative: 0.0,
+            min_clamps: 0,
+            max_clamps: 0,
+        }
+    }
+
+    /// Starts over from `initial_freq`, limited to the band (min, max) from now on.
+    fn reset(&mut self, initial_freq: f32, (min_freq_hz, max_freq_hz): (f32, f32)) {
+        self.min_freq_hz = min_freq_hz;
+        self.max_freq_hz = max_freq_hz;
+        self.freq_hz = initial_freq.clamp(min_freq_hz, max_freq_hz);
+        self.integrator = 0.0;
+        self.prev_error = None;
+        self.derivative = 0.0;
+    }
+
+    fn clear_counters(&mut self) {
+        self.min_clamps = 0;
+        self.max_clamps = 0;
+    }
+
+    /// Steps the frequency towards holding `target_a` of coil current: down towards resonance
+    /// for more, up for less. The power loop's memory is cleared so it takes over from here
+    /// without a bump.
+    fn hold_current(&mut self, target_a: f32, meas: &Measurements) -> f32 {
+        self.integrator = 0.0;
+        self.prev_error = None;
+        self.derivative = 0.0;
+        if !meas.valid {
+            return self.freq_hz;
+        }
+        let step = ((meas.coil_current_rms_a - target_a) * STARTUP_CURRENT_GAIN_HZ_PER_A)
+            .clamp(-MAX_FREQ_STEP_HZ, MAX_FREQ_STEP_HZ);
+        self.freq_hz = (self.freq_hz + step).clamp(self.min_freq_hz, self.max_freq_hz);
+        self.freq_hz
+    }
+
+    /// `center_hz` with the dither applied, kept inside the band.
+    fn dithered(&self, center_hz: f32) -> f32 {
+        (center_hz + dither_offset_hz(Instant::now())).clamp(self.min_freq_hz, self.max_freq_hz)
+    }
+
+    fn update(&mut self, setpoint_kw: f32, measured_kw: f32, dt: f32) -> f32 {
+        const KP: f32 = -60.0;
+        const KI: f32 = -8.0;
+        const KD: f32 = -2.0;
+        let error = setpoint_kw - measured_kw;
+        self.integrator += error * KI * dt;
+        if let Some(prev_error) = self.prev_error {
+            let raw_derivative = (error - prev_error) / dt;
+            let alpha = dt / (POWER_DERIVATIVE_TAU_S + dt);
+            self.derivative += alpha * (raw_derivative - self.derivative);
+        }
+        self.prev_error = Some(error);
+        let raw_hz = self.freq_hz + KP * error + self.integrator + KD * self.derivative;
+        if raw_hz <= self.min_freq_hz {
+            self.min_clamps = self.min_clamps.saturating_add(1);
+        } else if raw_hz >= self.max_freq_hz {
+            self.max_clamps = self.max_clamps.saturating_add(1);
+        }
+        let target_hz = raw_hz.clamp(self.min_freq_hz, self.max_freq_hz);
+        // Zero unless clamped; unwinds the integrator towards the rail instead of letting it
+        // pile up behind it.
+        self.integrator = (self.integrator + KAW * (target_hz - raw_hz) * dt)
+            .clamp(-POWER_INTEGRATOR_LIMIT_HZ, POWER_INTEGRATOR_LIMIT_HZ);
+        self.freq_hz += (target_hz - self.freq_hz).clamp(-MAX_FREQ_STEP_HZ, MAX_FREQ_STEP_HZ);
+        self.freq_hz
+    }
+}
+
+/// Cross-checks the commanded switching frequency against the one seen in the coil current.
+struct FrequencyMonitor {
+    started: Instant,
+    mismatches: u8,
+}
+
+impl FrequencyMonitor {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            mismatches: 0,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.started = Instant::now();
+        self.mismatches = 0;
+    }
+
+    /// Returns true once the mismatch has persisted for `FREQ_CHECK_MISMATCH_LIMIT` loops.
+    fn update(&mut self, commanded_hz: f32, measured_hz: f32, setpoint_kw: f32) -> bool {
+        if Instant::now().saturating_duration_since(self.started) < FREQ_CHECK_SETTLE {
+            return false;
+        }
+
+        let mismatch = if measured_hz > 0.0 {
+            fabsf(measured_hz - commanded_hz) > FREQ_CHECK_TOLERANCE_HZ
+        } else {
+            setpoint_kw >= FREQ_CHECK_MIN_SETPOINT_KW
+        };
+
+        if mismatch {
+            self.mismatches = self.mismatches.saturating_add(1);
+        } else {
+            self.mismatches = 0;
+        }
+        self.mismatches >= FREQ_CHECK_MISMATCH_LIMIT
+    }
+}
+
+/// PI on object temperature on top of a feed-forward estimate of the power that holds the
+/// target, so a run starts near the operating point instead of winding the integrator up from
+/// zero.
+struct TemperatureController {
+    integrator: f32,
+    integrator_saturations: u32,
+    feed_forward_kw: f32,
+    next_log: Instant,
+}
+
+impl TemperatureController {
+    fn new() -> Self {
+        Self {
+            integrator: 0.0,
+            integrator_saturations: 0,
+            feed_forward_kw: 0.0,
+            next_log: Instant::now(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.integrator = 0.0;
+    }
+
+    fn clear_counters(&mut self) {
+        self.integrator_saturations = 0;
+    }
+
+    fn update(
+        &mut self,
+        target_c: f32,
+        measured_c: f32,
+        ambient_c: f32,
+        power_limit_kw: f32,
+        dt: f32,
+    ) -> f32 {
+        const KP: f32 = 0.08;
+        const KI: f32 = 0.03;
+        self.feed_forward_kw =
+            (TEMP_FEED_FORWARD_KW_PER_C * (target_c - ambient_c)).clamp(0.0, power_limit_kw);
+
+        let error = (target_c - measured_c).max(-20.0);
+        let raw_integrator = self.integrator + error * KI * dt;
+        // Anti-windup: the integrator only covers what the feed-forward leaves of the range.
+        let integrator_min = -self.feed_forward_kw;
+        let integrator_max = power_limit_kw - self.feed_forward_kw;
+        if !(integrator_min..=integrator_max).contains(&raw_integrator) {
+            self.integrator_saturations = self.integrator_saturations.saturating_add(1);
+        }
+        self.integrator = raw_integrator.clamp(integrator_min, integrator_max);
+        let proportional = KP * error;
+
+        if Instant::now() >= self.next_log {
+            info!(
+                "Temp loop: ff {} kW, p {} kW, i {} kW",
+                self.feed_forward_kw, proportional, self.integrator
+            );
+            self.next_log = Instant::now() + TEMP_LOG_INTERVAL;
+        }
+
+        (self.feed_forward_kw + proportional + self.integrator).clamp(0.0, power_limit_kw)
+    }
+}
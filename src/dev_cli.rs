@@ -0,0 +1,140 @@
+//! Engineering command port (`dev-cli` feature): a second USB CDC-ACM interface next to the
+//! telemetry one.
+//!
+//! One command per line:
+//!
+//! - `set power <kW>`: manual power, 0 to the current power limit
+//! - `set temp <°C>`: temperature target, `TARGET_TEMP_MIN_C` to `TARGET_TEMP_MAX_C`
+//! - `mode idle|manual|temp|cooldown`
+//! - `capture`: log every ADC batch for a couple of seconds, see `sensors::start_capture`
+//!
+//! Each line gets an `ok ...` or `err ...` reply on the port, also logged over defmt. Values are
+//! range-checked, not clamped. Changes are not saved to flash unless the menu saves them later.
+//! Selecting a heating mode does not start heating: the operator still has to arm and press Run
+//! like after any mode change.
+
+use core::fmt::Write;
+use defmt::{info, warn};
+use embassy_usb::{
+    class::cdc_acm::{CdcAcmClass, State},
+    Builder,
+};
+use heapless::String;
+use static_cell::StaticCell;
+
+use crate::{
+    sensors::start_capture,
+    state::{ControlMode, CONTROL_SETTINGS, LIMITS, TARGET_TEMP_MAX_C, TARGET_TEMP_MIN_C},
+    telemetry::{UsbDriver, MAX_PACKET_SIZE},
+};
+
+const LINE_LEN: usize = 48;
+
+static CLI_STATE: StaticCell<State<'static>> = StaticCell::new();
+
+/// Adds the command port's CDC-ACM interface to the USB device being built.
+pub fn add_port(builder: &mut Builder<'static, UsbDriver>) -> CdcAcmClass<'static, UsbDriver> {
+    CdcAcmClass::new(builder, CLI_STATE.init(State::new()), MAX_PACKET_SIZE)
+}
+
+#[embassy_executor::task]
+pub async fn dev_cli_task(mut class: CdcAcmClass<'static, UsbDriver>) {
+    let mut packet = [0u8; MAX_PACKET_SIZE as usize];
+    loop {
+        class.wait_connection().await;
+        info!("Dev CLI host connected");
+        let mut line = String::<LINE_LEN>::new();
+        let mut overflow = false;
+
+        while let Ok(len) = class.read_packet(&mut packet).await {
+            for &byte in &packet[..len] {
+                match byte {
+                    b'\r' | b'\n' => {
+                        if overflow {
+                            reply(&mut class, "err line too long").await;
+                        } else if !line.trim().is_empty() {
+                            let response = execute(line.trim()).await;
+                            reply(&mut class, response.as_str()).await;
+                        }
+                        line.clear();
+                        overflow = false;
+                    }
+                    _ => {
+                        if line.push(byte as char).is_err() {
+                            overflow = true;
+                        }
+                    }
+                }
+            }
+        }
+        info!("Dev CLI host disconnected");
+    }
+}
+
+async fn execute(command: &str) -> String<48> {
+    let mut response = String::<48>::new();
+    let mut words = command.split_whitespace();
+    let result = match (words.next(), words.next(), words.next(), words.next()) {
+        (Some("set"), Some("power"), Some(value), None) => {
+            let power_limit_kw = LIMITS.lock().await.power_kw();
+            match value.parse::<f32>() {
+                Ok(kw) if (0.0..=power_limit_kw).contains(&kw) => {
+                    CONTROL_SETTINGS.lock().await.manual_power_kw = kw;
+                    write!(response, "ok power {:.2} kW", kw)
+                }
+                _ => write!(response, "err power 0..{:.1} kW", power_limit_kw),
+            }
+        }
+        (Some("set"), Some("temp"), Some(value), None) => match value.parse::<f32>() {
+            Ok(c) if (TARGET_TEMP_MIN_C..=TARGET_TEMP_MAX_C).contains(&c) => {
+                CONTROL_SETTINGS.lock().await.target_temp_c = c;
+                write!(response, "ok temp {:.0} C", c)
+            }
+            _ => write!(
+                response,
+                "err temp {:.0}..{:.0} C",
+                TARGET_TEMP_MIN_C, TARGET_TEMP_MAX_C
+            ),
+        },
+        (Some("mode"), Some(name), None, None) => match parse_mode(name) {
+            Some(mode) => {
+                let mut settings = CONTROL_SETTINGS.lock().await;
+                settings.mode = mode;
+                if matches!(mode, ControlMode::ManualPower | ControlMode::Temperature) {
+                    settings.last_run_mode = mode;
+                }
+                write!(response, "ok mode {}", name)
+            }
+            None => write!(response, "err mode idle|manual|temp|cooldown"),
+        },
+        (Some("capture"), None, None, None) => {
+            start_capture();
+            write!(response, "ok capture started")
+        }
+        _ => write!(response, "err unknown command"),
+    };
+    result.ok();
+    response
+}
+
+fn parse_mode(name: &str) -> Option<ControlMode> {
+    match name {
+        "idle" => Some(ControlMode::Idle),
+        "manual" => Some(ControlMode::ManualPower),
+        "temp" => Some(ControlMode::Temperature),
+        "cooldown" => Some(ControlMode::Cooldown),
+        _ => None,
+    }
+}
+
+/// Logs `text` and sends it back to the host with a line ending.
+async fn reply(class: &mut CdcAcmClass<'static, UsbDriver>, text: &str) {
+    if text.starts_with("ok") {
+        info!("Dev CLI: {}", text);
+    } else {
+        warn!("Dev CLI: {}", text);
+    }
+    let mut line = String::<50>::new();
+    let _ = write!(line, "{}\r\n", text);
+    let _ = class.write_packet(line.as_bytes()).await;
+}
@@ -0,0 +1,54 @@
+//! Rotary encoder in place of the Up/Down buttons (`rotary-encoder` feature).
+//!
+//! The menu polls its buttons' levels, so instead of teaching every screen about detents,
+//! `encoder_task` decodes the A/B lines and `encoder_press_task` replays each detent as a short
+//! virtual press of Up or Down that `menu::MenuButton::detent` reads like a pin. The encoder's
+//! push switch is an ordinary pin and is wired as Enter.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_futures::select::select;
+use embassy_rp::gpio::Input;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, Timer};
+
+use induction_shrink_fit::quadrature::{Detent, QuadratureDecoder};
+
+use crate::board::ENCODER_CLOCKWISE_UP;
+
+/// Held longer than a status screen's refresh so a poll cannot miss it, and well short of the
+/// value screens' auto-repeat delay.
+const VIRTUAL_PRESS: Duration = Duration::from_millis(80);
+const VIRTUAL_RELEASE: Duration = Duration::from_millis(40);
+/// Detents not yet replayed; spinning faster than the presses play back drops the excess.
+const DETENT_QUEUE_LEN: usize = 4;
+
+static DETENTS: Channel<CriticalSectionRawMutex, Detent, DETENT_QUEUE_LEN> = Channel::new();
+pub static UP_PRESSED: AtomicBool = AtomicBool::new(false);
+pub static DOWN_PRESSED: AtomicBool = AtomicBool::new(false);
+
+#[embassy_executor::task]
+pub async fn encoder_task(mut a: Input<'static>, mut b: Input<'static>) {
+    let mut decoder = QuadratureDecoder::new(a.is_high(), b.is_high());
+    loop {
+        select(a.wait_for_any_edge(), b.wait_for_any_edge()).await;
+        if let Some(detent) = decoder.update(a.is_high(), b.is_high()) {
+            DETENTS.try_send(detent).ok();
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn encoder_press_task() {
+    loop {
+        let detent = DETENTS.receive().await;
+        let pressed = match (detent, ENCODER_CLOCKWISE_UP) {
+            (Detent::Clockwise, true) | (Detent::CounterClockwise, false) => &UP_PRESSED,
+            _ => &DOWN_PRESSED,
+        };
+        pressed.store(true, Ordering::Relaxed);
+        Timer::after(VIRTUAL_PRESS).await;
+        pressed.store(false, Ordering::Relaxed);
+        Timer::after(VIRTUAL_RELEASE).await;
+    }
+}
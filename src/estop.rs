@@ -0,0 +1,228 @@
+//! Fast shutdown path for the interlock and gate-driver fault inputs.
+//!
+//! `safety_task` polls every 25 ms and `control_task` only acts on its verdict on its next
+//! 10 ms pass, so on its own a trip takes up to ~35 ms to reach the gates. `estop_task` waits
+//! on the two inputs' edge interrupts instead and runs on a higher-priority interrupt executor,
+//! preempting whatever thread-mode task is busy. Interlock-open to PWM-off is the GPIO IRQ, one
+//! task poll and the register writes in `GateDrive::trip`: tens of microseconds at 125 MHz.
+//!
+//! The polling path is unchanged and still raises and latches the fault.
+//!
+//! With the `pio-overcurrent` feature a PIO state machine also watches a current comparator
+//! (see `sensors::load_overcurrent_program`) and wakes this task through its RX FIFO. An
+//! overcurrent trip is sticky: the drive stays off, and `safety_task` keeps reporting
+//! `CurrentLimit`, until the operator clears the fault, even if the comparator has long let go.
+
+use core::cell::RefCell;
+use core::future::pending;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::{info, warn};
+use embassy_futures::select::{select4, Either4};
+use embassy_rp::{
+    gpio::{Input, Output},
+    peripherals::PIO0,
+    pio::StateMachine,
+    pwm::Pwm,
+};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    signal::Signal,
+};
+
+use crate::utils::{pwm_disable, pwm_enable, pwm_enable_with_duty};
+
+// Read as tripped until `estop_task` has looked at the pins.
+static INTERLOCK_OPEN: AtomicBool = AtomicBool::new(true);
+static GATE_FAULT_ACTIVE: AtomicBool = AtomicBool::new(true);
+// Set by the comparator, cleared only by `reset_overcurrent`.
+static OVERCURRENT_TRIPPED: AtomicBool = AtomicBool::new(false);
+// Wakes `estop_task` to look at `OVERCURRENT_TRIPPED` again after a reset.
+static OVERCURRENT_RESET: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// State machine watching the overcurrent comparator, see `sensors::init_overcurrent_capture`.
+pub type OvercurrentCapture = StateMachine<'static, PIO0, 3>;
+
+/// The inverter PWM and gate-driver enables, shared between `control_task` and `estop_task`.
+pub struct GateDrive {
+    inner: Mutex<CriticalSectionRawMutex, RefCell<GateDriveInner>>,
+}
+
+struct GateDriveInner {
+    pwm: &'static mut Pwm<'static>,
+    hs_enable: &'static mut Output<'static>,
+    ls_enable: &'static mut Output<'static>,
+    tripped: bool,
+}
+
+impl GateDriveInner {
+    fn off(&mut self) {
+        self.ls_enable.set_low();
+        self.hs_enable.set_low();
+        pwm_disable(self.pwm);
+    }
+}
+
+impl GateDrive {
+    pub fn new(
+        pwm: &'static mut Pwm<'static>,
+        hs_enable: &'static mut Output<'static>,
+        ls_enable: &'static mut Output<'static>,
+    ) -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(GateDriveInner {
+                pwm,
+                hs_enable,
+                ls_enable,
+                tripped: true,
+            })),
+        }
+    }
+
+    /// Switches at `freq_hz` with both gate drivers enabled. Returns false, leaving the drive
+    /// off, while an e-stop input is active.
+    pub fn enable(&self, dt_ns: u32, freq_hz: u32) -> bool {
+        self.drive(|pwm| pwm_enable(pwm, dt_ns, freq_hz))
+    }
+
+    /// Like [`GateDrive::enable`] with the high side on for `duty_percent` of the period.
+    pub fn enable_with_duty(&self, dt_ns: u32, freq_hz: u32, duty_percent: u8) -> bool {
+        self.drive(|pwm| pwm_enable_with_duty(pwm, dt_ns, freq_hz, duty_percent))
+    }
+
+    #[cfg(not(feature = "sim"))]
+    fn drive(&self, configure: impl FnOnce(&mut Pwm<'static>)) -> bool {
+        self.inner.lock(|cell| {
+            let inner = &mut *cell.borrow_mut();
+            if inner.tripped {
+                return false;
+            }
+            configure(inner.pwm);
+            inner.ls_enable.set_high();
+            inner.hs_enable.set_high();
+            true
+        })
+    }
+
+    /// Bench simulation: the switching is only pretended. The PWM stays off and both gate
+    /// drivers, the high side above all, stay disabled whatever is connected to the bridge;
+    /// the return value still follows the e-stop inputs so the control flow runs as usual.
+    #[cfg(feature = "sim")]
+    fn drive(&self, _configure: impl FnOnce(&mut Pwm<'static>)) -> bool {
+        self.inner.lock(|cell| {
+            let inner = &mut *cell.borrow_mut();
+            inner.off();
+            !inner.tripped
+        })
+    }
+
+    pub fn disable(&self) {
+        self.inner.lock(|cell| cell.borrow_mut().off());
+    }
+
+    fn trip(&self) {
+        self.inner.lock(|cell| {
+            let inner = &mut *cell.borrow_mut();
+            inner.tripped = true;
+            inner.off();
+        });
+    }
+
+    fn release(&self) {
+        self.inner.lock(|cell| cell.borrow_mut().tripped = false);
+    }
+}
+
+/// Interlock input as last seen by `estop_task`; low means open.
+pub fn interlock_open() -> bool {
+    INTERLOCK_OPEN.load(Ordering::Relaxed)
+}
+
+/// Gate-driver fault input as last seen by `estop_task`; low means faulted.
+pub fn gate_fault_active() -> bool {
+    GATE_FAULT_ACTIVE.load(Ordering::Relaxed)
+}
+
+/// The comparator has tripped since the last [`reset_overcurrent`].
+pub fn overcurrent_tripped() -> bool {
+    OVERCURRENT_TRIPPED.load(Ordering::Relaxed)
+}
+
+/// Operator acknowledgement of an overcurrent trip. Trips again straight away if the
+/// comparator is still asserted.
+pub fn reset_overcurrent() {
+    OVERCURRENT_TRIPPED.store(false, Ordering::Relaxed);
+    OVERCURRENT_RESET.signal(());
+}
+
+/// Spawn on the high-priority interrupt executor; on the thread executor it is no faster than
+/// the polling path. `overcurrent` is `None` without the comparator.
+#[embassy_executor::task]
+pub async fn estop_task(
+    gate_drive: &'static GateDrive,
+    interlock: &'static mut Input<'static>,
+    gate_fault: &'static mut Input<'static>,
+    mut overcurrent: Option<OvercurrentCapture>,
+) {
+    let mut tripped = false;
+    let mut comparator_high = false;
+    if let Some(sm) = overcurrent.as_mut() {
+        sm.set_enable(true);
+    }
+    loop {
+        let interlock_low = interlock.is_low();
+        let gate_fault_low = gate_fault.is_low();
+        INTERLOCK_OPEN.store(interlock_low, Ordering::Relaxed);
+        GATE_FAULT_ACTIVE.store(gate_fault_low, Ordering::Relaxed);
+        if comparator_high {
+            OVERCURRENT_TRIPPED.store(true, Ordering::Relaxed);
+        }
+        let overcurrent_tripped = overcurrent_tripped();
+
+        if interlock_low || gate_fault_low || overcurrent_tripped {
+            gate_drive.trip();
+            if !tripped {
+                warn!(
+                    "E-stop: gate drive off (interlock open: {}, gate fault: {}, overcurrent: {})",
+                    interlock_low, gate_fault_low, overcurrent_tripped
+                );
+            }
+            tripped = true;
+        } else {
+            gate_drive.release();
+            if tripped {
+                info!("E-stop inputs clear");
+            }
+            tripped = false;
+        }
+
+        // Waiting for the opposite level rather than an edge cannot miss a change that
+        // happened since the pins were read.
+        let change = select4(
+            wait_for_change(interlock, interlock_low),
+            wait_for_change(gate_fault, gate_fault_low),
+            comparator_edge(&mut overcurrent),
+            OVERCURRENT_RESET.wait(),
+        )
+        .await;
+        if let Either4::Third(high) = change {
+            comparator_high = high;
+        }
+    }
+}
+
+/// Next comparator level pushed by the state machine; never returns without one.
+async fn comparator_edge(overcurrent: &mut Option<OvercurrentCapture>) -> bool {
+    match overcurrent {
+        Some(sm) => sm.rx().wait_pull().await != 0,
+        None => pending().await,
+    }
+}
+
+async fn wait_for_change(input: &mut Input<'static>, was_low: bool) {
+    if was_low {
+        input.wait_for_high().await;
+    } else {
+        input.wait_for_low().await;
+    }
+}
@@ -0,0 +1,114 @@
+//! Sample filters for the sensor tasks.
+
+/// Single-pole exponential smoothing that starts from the first sample it is given.
+///
+/// "No sample yet" is tracked separately, so a channel that genuinely sits at 0 (coil current
+/// at idle) keeps being smoothed instead of snapping to every new reading.
+pub struct Ema {
+    factor: f32,
+    value: Option<f32>,
+}
+
+impl Ema {
+    /// `factor` is the weight of each new sample, as in `previous + factor * (new - previous)`.
+    pub const fn new(factor: f32) -> Self {
+        Self {
+            factor,
+            value: None,
+        }
+    }
+
+    pub fn update(&mut self, sample: f32) -> f32 {
+        let value = match self.value {
+            // A NaN/inf that got in would otherwise stick forever.
+            Some(previous) if previous.is_finite() => previous + self.factor * (sample - previous),
+            _ => sample,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    /// Last filtered value, or 0 before the first sample.
+    pub fn value(&self) -> f32 {
+        self.value.unwrap_or(0.0)
+    }
+
+    /// Restarts from `sample`.
+    pub fn reset(&mut self, sample: f32) -> f32 {
+        self.value = Some(sample);
+        sample
+    }
+}
+
+/// Median of the last `N` samples, then exponential smoothing.
+///
+/// The median throws away a single-sample spike (an I2C glitch, a switching transient on an
+/// NTC line) that a plain exponential filter would only spread out, so it never shows up as a
+/// brief over-temperature. Odd `N` keep the median an actual sample.
+pub struct MedianEma<const N: usize> {
+    window: [f32; N],
+    len: usize,
+    next: usize,
+    ema: Ema,
+}
+
+impl<const N: usize> MedianEma<N> {
+    /// `factor` is the weight of each new median, as in `previous + factor * (new - previous)`.
+    pub const fn new(factor: f32) -> Self {
+        Self {
+            window: [0.0; N],
+            len: 0,
+            next: 0,
+            ema: Ema::new(factor),
+        }
+    }
+
+    /// Adds `sample` and returns the filtered value. Until the window has filled, the median
+    /// is taken over the samples seen so far.
+    pub fn update(&mut self, sample: f32) -> f32 {
+        self.window[self.next] = sample;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+
+        let mut sorted = self.window;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+        self.ema.update(sorted[self.len / 2])
+    }
+
+    /// Discards the history and restarts from `sample`.
+    pub fn reset(&mut self, sample: f32) -> f32 {
+        self.window = [sample; N];
+        self.len = N;
+        self.next = 0;
+        self.ema.reset(sample)
+    }
+
+    pub fn value(&self) -> f32 {
+        self.ema.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_rejects_single_spike() {
+        // A factor of 1 passes the median straight through.
+        let mut filter = MedianEma::<3>::new(1.0);
+        for _ in 0..3 {
+            filter.update(25.0);
+        }
+        assert_eq!(filter.update(500.0), 25.0);
+        assert_eq!(filter.update(25.0), 25.0);
+    }
+
+    #[test]
+    fn median_follows_a_real_step() {
+        let mut filter = MedianEma::<3>::new(1.0);
+        filter.reset(25.0);
+        assert_eq!(filter.update(60.0), 25.0);
+        assert_eq!(filter.update(60.0), 60.0);
+    }
+}
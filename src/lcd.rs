@@ -1,8 +1,12 @@
+use core::cell::RefCell;
 use core::future::Future;
 use embassy_executor::Spawner;
-use embassy_rp::gpio::{Level, Output, Pin, Pull};
+use embassy_rp::gpio::{Flex, Level, Output, Pin, Pull};
+use embassy_rp::pwm::{Config as PwmConfig, Pwm};
 use embassy_rp::Peripherals;
-use embassy_time::{Duration, Timer};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_1::i2c::I2c;
 use {defmt_rtt as _, panic_probe as _}; // Example panicking/logging; adjust to your project.
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -40,65 +44,376 @@ const E_PULSE_US: u32 = 50; // 500us
 const E_DELAY_US: u32 = 50; // 500us
 const HOMEDELAY_MS: u64 = 50; // 50ms
 
+const INIT_DELAY_MS: u64 = 5; // 4.1ms minimum after the 8-bit wake-up commands
+
+// With RW wired the controller tells us when it is ready, so EN only needs its minimum width.
+const BUSY_PULSE_US: u64 = 1;
+// Upper bound on busy-flag polls (~4 us each) before writing anyway.
+const BUSY_POLL_LIMIT: u32 = 1_000;
+
+// Rows the controller can address; `set_cursor` clamps to the same range.
+const MAX_ROWS: usize = 4;
+// Default time between single-column steps of [`Lcd::scroll_line`].
+const SCROLL_INTERVAL_MS: u64 = 400;
+// Blank columns between the end of a scrolling text and its next pass.
+const SCROLL_GAP: usize = 3;
+
+// PCF8574 backpack wiring: P0 = RS, P1 = RW, P2 = EN, P3 = backlight, P4..P7 = D4..D7.
+pub const PCF8574_LCD_ADDR: u8 = 0x27;
+const PCF_RS: u8 = 0x01;
+const PCF_EN: u8 = 0x04;
+const PCF_BACKLIGHT: u8 = 0x08;
+
+// PWM backlight: 125 MHz / 16 / 255 is about 31 kHz, above hearing and any visible beat with
+// the LCD's own refresh. With TOP = 254 a compare value equal to the 0-255 level runs from
+// always off to always on.
+const BACKLIGHT_PWM_DIVIDER: u8 = 16;
+const BACKLIGHT_PWM_TOP: u16 = 254;
+
 ///////////////////////////////////////////////////////////////////////////////
-// LCD Driver
+// Bus abstraction
 ///////////////////////////////////////////////////////////////////////////////
-pub struct Lcd<'a> {
+
+/// How the HD44780 is physically wired. The command logic in [`Lcd`] only ever sends
+/// 4-bit nibbles through this.
+#[allow(async_fn_in_trait)]
+pub trait LcdBus {
+    /// Put `nibble` (lower 4 bits) on D4..D7 with RS = `rs` and latch it with an EN pulse.
+    async fn send_nibble(&mut self, nibble: u8, rs: bool);
+
+    /// Switch the backlight, if the bus controls one.
+    fn set_backlight(&mut self, on: bool);
+
+    /// Whether [`LcdBus::set_backlight`] actually switches anything.
+    fn has_backlight(&self) -> bool;
+
+    /// Whether [`LcdBus::wait_ready`] really polls the busy flag.
+    fn reads_busy_flag(&self) -> bool {
+        false
+    }
+
+    /// Wait for the controller to finish the previous command.
+    async fn wait_ready(&mut self) {}
+}
+
+/// Six (or seven, with RW) GPIOs straight to the controller.
+pub struct ParallelBus<'a> {
     rs: Output<'a>,
     en: Output<'a>,
+    rw: Option<Output<'a>>,
     bl: Option<Output<'a>>,
-    d4: Output<'a>,
-    d5: Output<'a>,
-    d6: Output<'a>,
-    d7: Output<'a>,
-
-    rows: u8,
-    cols: u8,
-
-    // Holds the current display-control flags: display on/off, cursor on/off, blink on/off.
-    display_control: u8,
+    d4: Flex<'a>,
+    d5: Flex<'a>,
+    d6: Flex<'a>,
+    d7: Flex<'a>,
 }
 
-impl<'a> Lcd<'a> {
-    /// Creates a new `Lcd` struct with uninitialized pins.
-    ///
+impl<'a> ParallelBus<'a> {
     /// * `rs_pin` – Register Select pin
     /// * `en_pin` – Enable pin
+    /// * `rw_pin` – Optional Read/Write pin; when present the busy flag is polled instead of
+    ///   waiting fixed delays. Only wire it with a 3.3 V module or level shifting on D4..D7,
+    ///   since the controller drives the data lines while it is read.
     /// * `backlight_pin` – Optional backlight pin
     /// * `d4_pin`, `d5_pin`, `d6_pin`, `d7_pin` – 4 data pins
-    /// * `cols` – Number of columns
-    /// * `rows` – Number of rows
     pub fn new(
         rs_pin: Output<'a>,
         en_pin: Output<'a>,
+        rw_pin: Option<Output<'a>>,
         backlight_pin: Option<Output<'a>>,
-        d4_pin: Output<'a>,
-        d5_pin: Output<'a>,
-        d6_pin: Output<'a>,
-        d7_pin: Output<'a>,
-        cols: u8,
-        rows: u8,
+        mut d4_pin: Flex<'a>,
+        mut d5_pin: Flex<'a>,
+        mut d6_pin: Flex<'a>,
+        mut d7_pin: Flex<'a>,
     ) -> Self {
+        for pin in [&mut d4_pin, &mut d5_pin, &mut d6_pin, &mut d7_pin] {
+            pin.set_low();
+            pin.set_as_output();
+        }
         Self {
             rs: rs_pin,
             en: en_pin,
+            rw: rw_pin,
             bl: backlight_pin,
             d4: d4_pin,
             d5: d5_pin,
             d6: d6_pin,
             d7: d7_pin,
+        }
+    }
+
+    /// Reads the HD44780 busy flag (DB7). Always `false` without an RW pin.
+    pub async fn read_busy_flag(&mut self) -> bool {
+        let Some(rw) = self.rw.as_mut() else {
+            return false;
+        };
+
+        for pin in [&mut self.d4, &mut self.d5, &mut self.d6, &mut self.d7] {
+            pin.set_as_input();
+        }
+        self.rs.set_low();
+        rw.set_high();
+
+        // High nibble carries BF in DB7; the low nibble (address counter) still has to be clocked.
+        self.en.set_high();
+        Timer::after(Duration::from_micros(BUSY_PULSE_US)).await;
+        let busy = self.d7.is_high();
+        self.en.set_low();
+        Timer::after(Duration::from_micros(BUSY_PULSE_US)).await;
+        self.en.set_high();
+        Timer::after(Duration::from_micros(BUSY_PULSE_US)).await;
+        self.en.set_low();
+
+        rw.set_low();
+        for pin in [&mut self.d4, &mut self.d5, &mut self.d6, &mut self.d7] {
+            pin.set_as_output();
+        }
+        busy
+    }
+
+    /// Set D4..D7 pins according to the nibble (lower 4 bits).
+    fn set_data_pins(&mut self, nibble: u8) {
+        self.d4.set_level(if (nibble & 0x01) != 0 {
+            Level::High
+        } else {
+            Level::Low
+        });
+        self.d5.set_level(if (nibble & 0x02) != 0 {
+            Level::High
+        } else {
+            Level::Low
+        });
+        self.d6.set_level(if (nibble & 0x04) != 0 {
+            Level::High
+        } else {
+            Level::Low
+        });
+        self.d7.set_level(if (nibble & 0x08) != 0 {
+            Level::High
+        } else {
+            Level::Low
+        });
+    }
+
+    /// Toggle the EN (enable) pin to latch command/data.
+    async fn toggle_enable(&mut self) {
+        if self.rw.is_some() {
+            self.en.set_high();
+            Timer::after(Duration::from_micros(BUSY_PULSE_US)).await;
+            self.en.set_low();
+            Timer::after(Duration::from_micros(BUSY_PULSE_US)).await;
+            return;
+        }
+
+        // Pulse EN pin high
+        self.en.set_high();
+        Timer::after(Duration::from_micros(E_PULSE_US.into())).await;
+        self.en.set_low();
+        Timer::after(Duration::from_micros(E_DELAY_US.into())).await;
+    }
+}
+
+impl LcdBus for ParallelBus<'_> {
+    async fn send_nibble(&mut self, nibble: u8, rs: bool) {
+        let level = if rs { Level::High } else { Level::Low };
+        if self.rs.get_output_level() != level {
+            self.rs.set_level(level);
+            // A short delay after RS changes
+            if self.rw.is_none() {
+                Timer::after(Duration::from_micros(E_DELAY_US.into())).await;
+            }
+        }
+        self.set_data_pins(nibble);
+        self.toggle_enable().await;
+    }
+
+    fn set_backlight(&mut self, on: bool) {
+        if let Some(ref mut bl_pin) = self.bl {
+            bl_pin.set_level(if on { Level::High } else { Level::Low });
+        }
+    }
+
+    fn has_backlight(&self) -> bool {
+        self.bl.is_some()
+    }
+
+    fn reads_busy_flag(&self) -> bool {
+        self.rw.is_some()
+    }
+
+    async fn wait_ready(&mut self) {
+        for _ in 0..BUSY_POLL_LIMIT {
+            if !self.read_busy_flag().await {
+                return;
+            }
+        }
+    }
+}
+
+/// PCF8574 I2C "backpack". Each I2C byte takes ~90 us at 100 kHz, which already covers the
+/// controller's execution time for everything except clear/home.
+pub struct Pcf8574Bus<I: I2c> {
+    i2c: I,
+    address: u8,
+    backlight: u8,
+}
+
+impl<I: I2c> Pcf8574Bus<I> {
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self {
+            i2c,
+            address,
+            backlight: PCF_BACKLIGHT,
+        }
+    }
+
+    fn write_port(&mut self, value: u8) {
+        // A lost nibble only garbles the display; the periodic redraw repairs it.
+        let _ = self.i2c.write(self.address, &[value]);
+    }
+}
+
+impl<I: I2c> LcdBus for Pcf8574Bus<I> {
+    async fn send_nibble(&mut self, nibble: u8, rs: bool) {
+        let port = (nibble << 4) | if rs { PCF_RS } else { 0 } | self.backlight;
+        self.write_port(port | PCF_EN);
+        self.write_port(port);
+    }
+
+    fn set_backlight(&mut self, on: bool) {
+        self.backlight = if on { PCF_BACKLIGHT } else { 0 };
+        let backlight = self.backlight;
+        self.write_port(backlight);
+    }
+
+    fn has_backlight(&self) -> bool {
+        true
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// PWM backlight
+///////////////////////////////////////////////////////////////////////////////
+
+/// Backlight driven from a PWM-capable pin instead of a bus's on/off pin. `&'static` and
+/// internally locked so the menu can dim it while a screen is holding the [`Lcd`].
+pub struct PwmBacklight {
+    pwm: Mutex<CriticalSectionRawMutex, RefCell<(Pwm<'static>, PwmConfig)>>,
+}
+
+impl PwmBacklight {
+    /// Takes a slice opened with `Pwm::new_output_a` or `Pwm::new_output_b` on the backlight
+    /// pin; both compare values are set, so either channel works. Starts at full brightness.
+    pub fn new(pwm: Pwm<'static>) -> Self {
+        let mut config = PwmConfig::default();
+        config.divider = BACKLIGHT_PWM_DIVIDER.into();
+        config.top = BACKLIGHT_PWM_TOP;
+        let backlight = Self {
+            pwm: Mutex::new(RefCell::new((pwm, config))),
+        };
+        backlight.set_level(u8::MAX);
+        backlight
+    }
+
+    /// 0 is off, 255 full brightness.
+    pub fn set_level(&self, level: u8) {
+        self.pwm.lock(|cell| {
+            let (pwm, config) = &mut *cell.borrow_mut();
+            config.compare_a = level as u16;
+            config.compare_b = level as u16;
+            pwm.set_config(config);
+        });
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// LCD Driver
+///////////////////////////////////////////////////////////////////////////////
+pub struct Lcd<B: LcdBus> {
+    bus: B,
+
+    rows: u8,
+    cols: u8,
+
+    // Holds the current display-control flags: display on/off, cursor on/off, blink on/off.
+    display_control: u8,
+    // The busy flag means nothing until the controller is in 4-bit mode.
+    busy_flag_valid: bool,
+
+    scroll: [RowScroll; MAX_ROWS],
+    scroll_interval: Duration,
+
+    // Replaces the bus's on/off backlight control when present.
+    backlight_pwm: Option<&'static PwmBacklight>,
+}
+
+/// Where [`Lcd::scroll_line`] left off on one row.
+#[derive(Clone, Copy)]
+struct RowScroll {
+    // Hash of the text last drawn; `None` after a clear, so the next call redraws.
+    text: Option<u32>,
+    offset: usize,
+    next_step: Instant,
+}
+
+impl RowScroll {
+    const fn new() -> Self {
+        Self {
+            text: None,
+            offset: 0,
+            next_step: Instant::from_ticks(0),
+        }
+    }
+}
+
+/// FNV-1a, enough to notice that a row's text changed without keeping a copy of it.
+fn text_hash(text: &[u8]) -> u32 {
+    text.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+impl<B: LcdBus> Lcd<B> {
+    /// Creates a new `Lcd` on an uninitialized bus.
+    ///
+    /// * `bus` – How the controller is wired, see [`ParallelBus`] and [`Pcf8574Bus`]
+    /// * `cols` – Number of columns
+    /// * `rows` – Number of rows
+    pub fn new(bus: B, cols: u8, rows: u8) -> Self {
+        Self {
+            bus,
             rows,
             cols,
             display_control: LCD_DISPLAYON | LCD_CURSOROFF | LCD_BLINKOFF,
+            busy_flag_valid: false,
+            scroll: [RowScroll::new(); MAX_ROWS],
+            scroll_interval: Duration::from_millis(SCROLL_INTERVAL_MS),
+            backlight_pwm: None,
         }
     }
 
+    /// Drives the backlight through `backlight` rather than the bus, making its brightness
+    /// adjustable with [`Lcd::set_backlight_level`]. Leave the bus's own backlight pin unset.
+    pub fn with_backlight_pwm(mut self, backlight: &'static PwmBacklight) -> Self {
+        self.backlight_pwm = Some(backlight);
+        self
+    }
+
+    /// The PWM backlight, if there is one, for dimming without going through the `Lcd`.
+    pub fn backlight_pwm(&self) -> Option<&'static PwmBacklight> {
+        self.backlight_pwm
+    }
+
     /// Initializes the LCD in 4-bit mode and clears it.
     pub async fn init(&mut self) {
+        self.busy_flag_valid = false;
         // Following the standard HD44780 4-bit init procedure:
         self.write_byte(0x33, LCD_CMD).await; // Initialize
+        Timer::after(Duration::from_millis(INIT_DELAY_MS)).await;
         self.write_byte(0x32, LCD_CMD).await; // Set to 4-bit mode
+        Timer::after(Duration::from_millis(1)).await;
         self.write_byte(0x28, LCD_CMD).await; // 2 line, 5x8 font
+        self.busy_flag_valid = true;
         self.write_byte(0x0C, LCD_CMD).await; // Turn on display, cursor off, no blink
         self.write_byte(0x06, LCD_CMD).await; // Left to right entry
         self.clear().await;
@@ -108,14 +423,19 @@ impl<'a> Lcd<'a> {
 
     /// Clears display and moves cursor to home position.
     pub async fn clear(&mut self) {
+        self.scroll = [RowScroll::new(); MAX_ROWS];
         self.write_byte(LCD_CLEAR, LCD_CMD).await;
-        Timer::after(Duration::from_millis(HOMEDELAY_MS)).await;
+        if !self.polls_busy_flag() {
+            Timer::after(Duration::from_millis(HOMEDELAY_MS)).await;
+        }
     }
 
     /// Returns cursor to home position (without clearing).
     pub async fn home(&mut self) {
         self.write_byte(LCD_HOME, LCD_CMD).await;
-        Timer::after(Duration::from_millis(HOMEDELAY_MS)).await;
+        if !self.polls_busy_flag() {
+            Timer::after(Duration::from_millis(HOMEDELAY_MS)).await;
+        }
     }
 
     /// Write a string to the LCD.
@@ -125,6 +445,83 @@ impl<'a> Lcd<'a> {
         }
     }
 
+    /// Replaces `previous`, the text currently shown from the start of `row`, with `next`,
+    /// rewriting only the runs of characters that differ. An empty `previous` draws all of
+    /// `next`. Characters past the end of `next` are left alone; pad it to clear them.
+    pub async fn update_line(&mut self, row: u8, previous: &str, next: &str) {
+        let (old, new) = (previous.as_bytes(), next.as_bytes());
+        let cols = (self.cols as usize).min(new.len());
+        let mut col = 0;
+        while col < cols {
+            if old.get(col) == Some(&new[col]) {
+                col += 1;
+                continue;
+            }
+            let start = col;
+            while col < cols && old.get(col) != Some(&new[col]) {
+                col += 1;
+            }
+            self.set_cursor(start as u8, row).await;
+            for &byte in &new[start..col] {
+                self.write_byte(byte, LCD_CHR).await;
+            }
+        }
+    }
+
+    /// Shows `text` on `row`, scrolling it one column to the left per `scroll_interval` if it
+    /// is wider than the display. Never waits: each call either draws the next step or, when
+    /// it is not due yet, returns without touching the bus, so it can sit in a polling loop.
+    /// Text that fits is padded and drawn once, like a plain line. New text starts again from
+    /// its first column.
+    pub async fn scroll_line(&mut self, row: u8, text: &str) {
+        let row = row.min(self.rows - 1);
+        let cols = self.cols as usize;
+        let bytes = text.as_bytes();
+        let hash = text_hash(bytes);
+        let now = Instant::now();
+        let state = &mut self.scroll[row as usize];
+
+        if state.text != Some(hash) {
+            *state = RowScroll {
+                text: Some(hash),
+                offset: 0,
+                next_step: now + self.scroll_interval,
+            };
+        } else if bytes.len() <= cols || now < state.next_step {
+            return;
+        } else {
+            state.offset = (state.offset + 1) % (bytes.len() + SCROLL_GAP);
+            state.next_step = now + self.scroll_interval;
+        }
+        let offset = state.offset;
+
+        self.set_cursor(0, row).await;
+        if bytes.len() <= cols {
+            self.message(text).await;
+            for _ in bytes.len()..cols {
+                self.write_byte(b' ', LCD_CHR).await;
+            }
+        } else {
+            // The text wraps around with a short gap, like a marquee.
+            for i in 0..cols {
+                let index = (offset + i) % (bytes.len() + SCROLL_GAP);
+                let byte = bytes.get(index).copied().unwrap_or(b' ');
+                self.write_byte(byte, LCD_CHR).await;
+            }
+        }
+    }
+
+    /// Sets how long [`Lcd::scroll_line`] holds each step.
+    pub fn set_scroll_interval(&mut self, interval: Duration) {
+        self.scroll_interval = interval;
+    }
+
+    /// Writes one character code at the cursor: a CGRAM slot (0-7) or any ROM glyph, including
+    /// the ones above 0x7F that `message` cannot express.
+    pub async fn write_char(&mut self, code: u8) {
+        self.write_byte(code, LCD_CHR).await;
+    }
+
     /// Move display left by one position.
     pub async fn move_left(&mut self) {
         self.write_byte(LCD_CURSORSHIFT | LCD_DISPLAYMOVE | LCD_MOVELEFT, LCD_CMD)
@@ -155,10 +552,23 @@ impl<'a> Lcd<'a> {
             .await;
     }
 
+    /// Whether there is a backlight to switch, through the bus or a [`PwmBacklight`]. Without
+    /// one, [`Lcd::backlight`] and [`Lcd::set_backlight_level`] do nothing.
+    pub fn has_backlight(&self) -> bool {
+        self.backlight_pwm.is_some() || self.bus.has_backlight()
+    }
+
     /// Enables or disables the backlight (if present).
     pub fn backlight(&mut self, enable: bool) {
-        if let Some(ref mut bl_pin) = self.bl {
-            bl_pin.set_level(if enable { Level::High } else { Level::Low });
+        self.set_backlight_level(if enable { u8::MAX } else { 0 });
+    }
+
+    /// Sets the backlight brightness, 0 (off) to 255. Without a PWM backlight anything above 0
+    /// is simply on.
+    pub fn set_backlight_level(&mut self, level: u8) {
+        match self.backlight_pwm {
+            Some(backlight) => backlight.set_level(level),
+            None => self.bus.set_backlight(level > 0),
         }
     }
 
@@ -210,57 +620,18 @@ impl<'a> Lcd<'a> {
 
     /// Write a single byte (command or data) to the LCD in 4-bit mode.
     async fn write_byte(&mut self, bits: u8, mode: u8) {
-        // Set RS line for command or data
-        self.rs.set_level(if mode == LCD_CHR {
-            Level::High
-        } else {
-            Level::Low
-        });
-
-        // A short delay after RS changes
-        Timer::after(Duration::from_micros(E_DELAY_US.into())).await;
+        if self.busy_flag_valid {
+            self.bus.wait_ready().await;
+        }
 
+        let rs = mode == LCD_CHR;
         // High nibble
-        let high_nibble = (bits & 0xF0) >> 4;
-        self.set_data_pins(high_nibble);
-        self.toggle_enable().await;
-
+        self.bus.send_nibble((bits & 0xF0) >> 4, rs).await;
         // Low nibble
-        let low_nibble = bits & 0x0F;
-        self.set_data_pins(low_nibble);
-        self.toggle_enable().await;
+        self.bus.send_nibble(bits & 0x0F, rs).await;
     }
 
-    /// Set D4..D7 pins according to the nibble (lower 4 bits).
-    fn set_data_pins(&mut self, nibble: u8) {
-        self.d4.set_level(if (nibble & 0x01) != 0 {
-            Level::High
-        } else {
-            Level::Low
-        });
-        self.d5.set_level(if (nibble & 0x02) != 0 {
-            Level::High
-        } else {
-            Level::Low
-        });
-        self.d6.set_level(if (nibble & 0x04) != 0 {
-            Level::High
-        } else {
-            Level::Low
-        });
-        self.d7.set_level(if (nibble & 0x08) != 0 {
-            Level::High
-        } else {
-            Level::Low
-        });
-    }
-
-    /// Toggle the EN (enable) pin to latch command/data.
-    async fn toggle_enable(&mut self) {
-        // Pulse EN pin high
-        self.en.set_high();
-        Timer::after(Duration::from_micros(E_PULSE_US.into())).await;
-        self.en.set_low();
-        Timer::after(Duration::from_micros(E_DELAY_US.into())).await;
+    fn polls_busy_flag(&self) -> bool {
+        self.busy_flag_valid && self.bus.reads_busy_flag()
     }
 }
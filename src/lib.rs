@@ -0,0 +1,17 @@
+//! The parts of the firmware that do not touch the hardware: settings and their storage format,
+//! filters, and the arithmetic behind the sensor and drive code.
+//!
+//! They live in a library so their unit tests build and run on the development machine. The
+//! default build target is the RP2040, so name the host explicitly, e.g.
+//! `cargo test --lib --target x86_64-unknown-linux-gnu`, or `cargo test-host` on Linux.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod ads7828_protocol;
+pub mod config;
+pub mod filter;
+pub mod ntc;
+pub mod pwm_timing;
+pub mod sampling;
+pub mod quadrature;
+pub mod settings;
@@ -1,16 +1,23 @@
 #![no_std]
 #![no_main]
 
-use embassy_executor::Spawner;
+use defmt::{info, warn};
+use embassy_executor::{InterruptExecutor, Spawner};
+#[cfg(not(feature = "sim"))]
 use embassy_hal_internal::Peripheral;
+#[cfg(not(feature = "sim"))]
+use embassy_rp::adc::{Adc, Async, Channel, Config as AdcConfig, InterruptHandler};
 use embassy_rp::{
-    adc::{Adc, Async, Channel, Config as AdcConfig, InterruptHandler},
     bind_interrupts,
-    gpio::{Drive, Input, Level, Output, Pull},
-    i2c::{Config as I2cConfig, I2c},
-    peripherals::PIO0,
+    flash::{Blocking as FlashBlocking, Flash},
+    gpio::{Drive, Flex, Input, Level, Output, Pull},
+    i2c::{self, Config as I2cConfig, I2c},
+    interrupt,
+    interrupt::{InterruptExt, Priority},
+    peripherals::{I2C0, I2C1, PIO0},
     pio::{self, Pio},
     pwm::{Config as PwmConfig, Pwm},
+    watchdog::Watchdog,
     Peripherals,
 };
 use embassy_time::{Duration, Timer};
@@ -18,43 +25,98 @@ use static_cell::StaticCell;
 
 use {defmt_rtt as _, panic_probe as _};
 
+#[cfg(all(feature = "modbus", feature = "pio-overcurrent"))]
+compile_error!(
+    "`modbus` and `pio-overcurrent` both need GPIO2 (and more PIO0 memory than there is)"
+);
+
 mod ads7828;
+mod big_digits;
+mod board;
+mod buzzer;
+mod channel_buffers;
 mod control;
+#[cfg(feature = "dev-cli")]
+mod dev_cli;
+#[cfg(feature = "rotary-encoder")]
+mod encoder;
+mod estop;
 mod lcd;
 mod menu;
 mod mlx90614;
+#[cfg(feature = "modbus")]
+mod modbus;
 mod safety;
+mod selftest;
 mod sensors;
+#[cfg(feature = "sim")]
+mod sim;
 mod state;
+mod storage;
+#[cfg(feature = "usb-telemetry")]
+mod telemetry;
 mod utils;
+mod version;
+
+use induction_shrink_fit::{ads7828_protocol, config, filter, ntc, pwm_timing, sampling};
 
-use ads7828::Ads7828;
-use control::control_task;
-use lcd::Lcd;
-use menu::menu_task;
-use mlx90614::Mlx90614;
+use buzzer::buzzer_task;
+use control::{control_task, WATCHDOG_TIMEOUT};
+use estop::{estop_task, GateDrive};
+use lcd::{Lcd, ParallelBus};
+use menu::{menu_task, MenuButton};
 use safety::safety_task;
-use sensors::{
-    adc_task, ads_task, init_sic_temp_capture, load_sic_temp_program, mlx_task, sic_temp_task,
+#[cfg(not(feature = "sim"))]
+use sensors::{adc_task, ads_task, mlx_task, sic_temp_task};
+#[cfg(feature = "pio-overcurrent")]
+use sensors::{init_overcurrent_capture, load_overcurrent_program};
+use sensors::{init_sic_temp_capture, load_sic_temp_program};
+use state::{
+    sensors_started, ControlMode, CALIBRATION, COMMISSIONING, CONTROL_SETTINGS, LIMITS, PROFILES,
+    USAGE_STATS,
 };
+use storage::{load_settings, storage_task};
 use utils::pwm_disable;
 
+/// How long the splash shows the firmware version before moving on to "System init".
+const SPLASH_VERSION_TIME: Duration = Duration::from_millis(1_500);
+/// Reads tried at the configured I2C rate before a bus drops to standard mode.
+const I2C_PROBE_ATTEMPTS: usize = 3;
+
 static PWM_DRIVE_CELL: StaticCell<Pwm<'static>> = StaticCell::new();
+static GATE_DRIVE_CELL: StaticCell<GateDrive> = StaticCell::new();
 static HS_ENABLE_CELL: StaticCell<Output<'static>> = StaticCell::new();
 static LS_ENABLE_CELL: StaticCell<Output<'static>> = StaticCell::new();
 static SOLENOID_CELL: StaticCell<Output<'static>> = StaticCell::new();
 static RUN_BUTTON_CELL: StaticCell<Input<'static>> = StaticCell::new();
+static COOLANT_FLOW_CELL: StaticCell<Input<'static>> = StaticCell::new();
 static INTERLOCK_CELL: StaticCell<Input<'static>> = StaticCell::new();
 static GATE_FAULT_CELL: StaticCell<Input<'static>> = StaticCell::new();
 static GATE_READY_CELL: StaticCell<Input<'static>> = StaticCell::new();
+#[cfg(not(feature = "sim"))]
 static ADC_CELL: StaticCell<Adc<'static, Async>> = StaticCell::new();
+#[cfg(not(feature = "sim"))]
 static ADC_CHANNELS_CELL: StaticCell<[Channel<'static>; 2]> = StaticCell::new();
-static ADS_CELL: StaticCell<Ads7828<'static>> = StaticCell::new();
+static ADS_CELL: StaticCell<board::SensorAdc> = StaticCell::new();
+
+// Runs only `estop_task`, preempting the thread-mode tasks.
+static ESTOP_EXECUTOR: InterruptExecutor = InterruptExecutor::new();
+
+#[interrupt]
+unsafe fn SWI_IRQ_1() {
+    ESTOP_EXECUTOR.on_interrupt()
+}
 
+#[cfg(not(feature = "sim"))]
 bind_interrupts!(struct AdcIrqs {
     ADC_IRQ_FIFO => InterruptHandler;
 });
 
+bind_interrupts!(struct I2cIrqs {
+    I2C0_IRQ => i2c::InterruptHandler<I2C0>;
+    I2C1_IRQ => i2c::InterruptHandler<I2C1>;
+});
+
 bind_interrupts!(struct PioIrqs {
     PIO0_IRQ_0 => pio::InterruptHandler<PIO0>;
 });
@@ -63,15 +125,22 @@ bind_interrupts!(struct PioIrqs {
 async fn main(spawner: Spawner) {
     let p: Peripherals = embassy_rp::init(Default::default());
 
-    let Pio {
-        common: mut sic_pio_common,
-        sm0: sic_temp_sm,
-        ..
-    } = Pio::new(p.PIO0, PioIrqs);
+    let pio0 = Pio::new(p.PIO0, PioIrqs);
+    let mut sic_pio_common = pio0.common;
+    let sic_temp_sm = pio0.sm0;
     let sic_temp_program = load_sic_temp_program(&mut sic_pio_common);
     let sic_temp_pin = sic_pio_common.make_pio_pin(p.PIN_4);
     let sic_temp_sm = init_sic_temp_capture(&sic_temp_program, sic_temp_sm, sic_temp_pin);
 
+    #[cfg(feature = "pio-overcurrent")]
+    let overcurrent = {
+        let program = load_overcurrent_program(&mut sic_pio_common);
+        let pin = sic_pio_common.make_pio_pin(p.PIN_2);
+        Some(init_overcurrent_capture(&program, pio0.sm3, pin))
+    };
+    #[cfg(not(feature = "pio-overcurrent"))]
+    let overcurrent = None;
+
     // ------------------------------------------------------------------------------------------
     // GPIO setups
     // ------------------------------------------------------------------------------------------
@@ -79,35 +148,39 @@ async fn main(spawner: Spawner) {
     let ls_enable = LS_ENABLE_CELL.init(Output::new(p.PIN_9, Level::Low));
     let solenoid = SOLENOID_CELL.init(Output::new(p.PIN_11, Level::Low));
     let run_button = RUN_BUTTON_CELL.init(Input::new(p.PIN_14, Pull::Up));
+    let coolant_flow = COOLANT_FLOW_CELL.init(Input::new(p.PIN_8, Pull::Up));
     let interlock = INTERLOCK_CELL.init(Input::new(p.PIN_15, Pull::Down));
     let gate_fault = GATE_FAULT_CELL.init(Input::new(p.PIN_6, Pull::Up));
     let gate_ready = GATE_READY_CELL.init(Input::new(p.PIN_7, Pull::Up));
+    let buzzer = Output::new(p.PIN_10, Level::Low);
 
     let down_pin = Input::new(p.PIN_12, Pull::Up);
     let up_pin = Input::new(p.PIN_13, Pull::Up);
-    let enter_pin = Input::new(p.PIN_27, Pull::Up);
+    let enter_button = MenuButton::pin("Enter", Input::new(p.PIN_27, Pull::Up));
 
     // ------------------------------------------------------------------------------------------
     // PWM setup for SiC MOSFET
     // ------------------------------------------------------------------------------------------
+    let inverter_pwm = board::inverter_pwm_resources!(p);
     let mut drive_cfg = PwmConfig::default();
     drive_cfg.phase_correct = true;
-    drive_cfg.invert_b = true;
+    drive_cfg.invert_b = board::INVERTER_INVERT_B;
     drive_cfg.enable = false;
     let pwm_drive = PWM_DRIVE_CELL.init(Pwm::new_output_ab(
-        p.PWM_SLICE0,
-        p.PIN_0,
-        p.PIN_1,
+        inverter_pwm.slice,
+        inverter_pwm.pin_a,
+        inverter_pwm.pin_b,
         drive_cfg,
     ));
     pwm_disable(pwm_drive);
+    let gate_drive = GATE_DRIVE_CELL.init(GateDrive::new(pwm_drive, hs_enable, ls_enable));
 
     // ------------------------------------------------------------------------------------------
     // I2C ADC Setup
     // ------------------------------------------------------------------------------------------
     let mut ads_i2c_cfg = I2cConfig::default();
-    ads_i2c_cfg.frequency = 100_000;
-    let ads_i2c = I2c::new_blocking(p.I2C1, p.PIN_19, p.PIN_18, ads_i2c_cfg);
+    ads_i2c_cfg.frequency = board::ADS_I2C_FREQUENCY_HZ;
+    let ads_i2c = I2c::new_async(p.I2C1, p.PIN_19, p.PIN_18, I2cIrqs, ads_i2c_cfg);
 
     // ------------------------------------------------------------------------------------------
     // LCD Config
@@ -116,92 +189,257 @@ async fn main(spawner: Spawner) {
     rs_pin.set_drive_strength(Drive::_12mA);
     let mut en_pin = Output::new(p.PIN_24, Level::Low);
     en_pin.set_drive_strength(Drive::_12mA);
-    let mut d4_pin = Output::new(p.PIN_23, Level::Low);
+    let mut d4_pin = Flex::new(p.PIN_23);
     d4_pin.set_drive_strength(Drive::_12mA);
-    let mut d5_pin = Output::new(p.PIN_22, Level::Low);
+    let mut d5_pin = Flex::new(p.PIN_22);
     d5_pin.set_drive_strength(Drive::_12mA);
-    let mut d6_pin = Output::new(p.PIN_21, Level::Low);
+    let mut d6_pin = Flex::new(p.PIN_21);
     d6_pin.set_drive_strength(Drive::_12mA);
-    let mut d7_pin = Output::new(p.PIN_20, Level::Low);
+    let mut d7_pin = Flex::new(p.PIN_20);
     d7_pin.set_drive_strength(Drive::_12mA);
 
+    let rw_pin = None;
+    // Boards with the backlight on a PWM-capable pin leave this `None` and pass a
+    // `lcd::PwmBacklight` to `Lcd::with_backlight_pwm` instead; the menu then dims it when idle.
     let backlight_pin = None;
 
-    let mut lcd = Lcd::new(
+    let lcd_bus = ParallelBus::new(
         rs_pin,
         en_pin,
+        rw_pin,
         backlight_pin,
         d4_pin,
         d5_pin,
         d6_pin,
         d7_pin,
-        16,
-        2,
     );
+    let mut lcd: board::DisplayLcd = Lcd::new(lcd_bus, 16, 2);
 
     lcd.init().await;
-    lcd.backlight(true);
+    if lcd.has_backlight() {
+        lcd.backlight(true);
+    }
     lcd.set_cursor(0, 0).await;
     lcd.message("Induction Shrink").await;
     lcd.set_cursor(0, 1).await;
-    lcd.message("System init...").await;
+    lcd.message("FW v").await;
+    lcd.message(version::FIRMWARE_VERSION).await;
     lcd.show_blink(false).await;
+    info!(
+        "Firmware v{} built {}",
+        version::FIRMWARE_VERSION,
+        version::BUILD_DATE
+    );
+    Timer::after(SPLASH_VERSION_TIME).await;
+    lcd.set_cursor(0, 1).await;
+    lcd.message("System init...  ").await;
 
     // ------------------------------------------------------------------------------------------
-    // Menu
+    // Stored settings
     // ------------------------------------------------------------------------------------------
-    spawner
-        .spawn(menu_task(lcd, up_pin, down_pin, enter_pin))
-        .unwrap();
+    let mut flash = Flash::<_, FlashBlocking, { storage::FLASH_SIZE }>::new_blocking(p.FLASH);
+    match load_settings(&mut flash) {
+        Some(stored) => {
+            let mut settings = stored.settings;
+            // Never come out of reset in a heating mode.
+            settings.mode = ControlMode::Idle;
+            *CONTROL_SETTINGS.lock().await = settings;
+            *COMMISSIONING.lock().await = stored.commissioning;
+            *PROFILES.lock().await = stored.profiles;
+            *LIMITS.lock().await = stored.limits;
+            *USAGE_STATS.lock().await = stored.usage;
+            *CALIBRATION.lock().await = stored.calibration;
+            info!("Settings loaded from flash");
+        }
+        None => {
+            *CALIBRATION.lock().await = board::SENSOR_CALIBRATION;
+            info!("No valid stored settings, using defaults");
+        }
+    }
+    spawner.spawn(storage_task(flash)).unwrap();
 
     // ------------------------------------------------------------------------------------------
     // MLX90614 setup
     // ------------------------------------------------------------------------------------------
     let mut mlx_i2c_cfg = I2cConfig::default();
-    mlx_i2c_cfg.frequency = 100_000;
-    let mlx_i2c = I2c::new_blocking(p.I2C0, p.PIN_17, p.PIN_16, mlx_i2c_cfg);
-    let mlx = Mlx90614::new(mlx_i2c);
-    spawner.spawn(mlx_task(mlx)).unwrap();
+    mlx_i2c_cfg.frequency = board::MLX_I2C_FREQUENCY_HZ;
+    let mlx_i2c = I2c::new_async(p.I2C0, p.PIN_17, p.PIN_16, I2cIrqs, mlx_i2c_cfg);
+    let mut mlx = board::IrThermometer::new(mlx_i2c);
+
+    // ------------------------------------------------------------------------------------------
+    // ADS7828
+    // ------------------------------------------------------------------------------------------
+    let ads = ADS_CELL.init(
+        board::SensorAdc::new(
+            ads_i2c,
+            board::SensorAdc::address(false, false),
+            board::ADS7828_INTERNAL_REF,
+        )
+        .unwrap(),
+    );
+
+    // ------------------------------------------------------------------------------------------
+    // I2C rate check: fall back to standard mode on a bus that cannot hold fast mode
+    // ------------------------------------------------------------------------------------------
+    if ads_i2c_cfg.frequency > board::I2C_STANDARD_MODE_HZ {
+        let mut answered = false;
+        for _ in 0..I2C_PROBE_ATTEMPTS {
+            if ads.get_channel_wr(0).await.is_ok() {
+                answered = true;
+                break;
+            }
+        }
+        if answered {
+            info!("ADS7828 I2C at {} Hz", ads_i2c_cfg.frequency);
+        } else {
+            warn!(
+                "ADS7828 silent at {} Hz, falling back to standard mode",
+                ads_i2c_cfg.frequency
+            );
+            ads_i2c_cfg.frequency = board::I2C_STANDARD_MODE_HZ;
+            ads.set_bus_config(&ads_i2c_cfg).await;
+        }
+    }
+    if mlx_i2c_cfg.frequency > board::I2C_STANDARD_MODE_HZ {
+        let mut answered = false;
+        for _ in 0..I2C_PROBE_ATTEMPTS {
+            if mlx.read_ambient_temp().await.is_ok() {
+                answered = true;
+                break;
+            }
+        }
+        if answered {
+            info!("MLX90614 I2C at {} Hz", mlx_i2c_cfg.frequency);
+        } else {
+            warn!(
+                "MLX90614 silent at {} Hz, falling back to standard mode",
+                mlx_i2c_cfg.frequency
+            );
+            mlx_i2c_cfg.frequency = board::I2C_STANDARD_MODE_HZ;
+            mlx.recover_bus(&mlx_i2c_cfg).await;
+        }
+    }
 
     // ------------------------------------------------------------------------------------------
-    // ADS7828 task
+    // Power-on self-test
     // ------------------------------------------------------------------------------------------
-    let ads = ADS_CELL.init(Ads7828::new(ads_i2c, 0x48));
-    spawner.spawn(ads_task(ads)).unwrap();
+    selftest::hold_until_passed(&mut lcd, &enter_button, ads, &mut mlx, gate_ready).await;
 
     // ------------------------------------------------------------------------------------------
-    // On-chip ADC sampling task
+    // Menu
     // ------------------------------------------------------------------------------------------
-    let adc = ADC_CELL.init(Adc::new(p.ADC, AdcIrqs, AdcConfig::default()));
-    let channels = ADC_CHANNELS_CELL.init([
-        Channel::new_pin(p.PIN_26, Pull::None),
-        Channel::new_pin(p.PIN_29, Pull::None),
-    ]);
+    #[cfg(not(feature = "rotary-encoder"))]
+    let (up_button, down_button) = (
+        MenuButton::pin("Up", up_pin),
+        MenuButton::pin("Down", down_pin),
+    );
+    #[cfg(feature = "rotary-encoder")]
+    let (up_button, down_button) = {
+        spawner
+            .spawn(encoder::encoder_task(down_pin, up_pin))
+            .unwrap();
+        spawner.spawn(encoder::encoder_press_task()).unwrap();
+        (
+            MenuButton::detent("Up", &encoder::UP_PRESSED),
+            MenuButton::detent("Down", &encoder::DOWN_PRESSED),
+        )
+    };
     spawner
-        .spawn(adc_task(adc, channels, p.DMA_CH0.into_ref()))
+        .spawn(menu_task(lcd, up_button, down_button, enter_button))
         .unwrap();
 
     // ------------------------------------------------------------------------------------------
-    // SiC module temperature duty monitor
+    // Sensor tasks
     // ------------------------------------------------------------------------------------------
-    spawner.spawn(sic_temp_task(sic_temp_sm)).unwrap();
+    sensors_started();
+    #[cfg(not(feature = "sim"))]
+    {
+        spawner
+            .spawn(mlx_task(mlx, board::MLX_OBJECT_CHANNEL, mlx_i2c_cfg))
+            .unwrap();
+        spawner
+            .spawn(ads_task(ads, board::ADS_CHANNELS, board::PCB_SENSOR))
+            .unwrap();
+
+        // On-chip ADC sampling task
+        let adc = ADC_CELL.init(Adc::new(p.ADC, AdcIrqs, AdcConfig::default()));
+        // In `sensors::ADC_VOLTAGE_SLOT` / `ADC_CURRENT_SLOT` order.
+        let sense_adc = board::sense_adc_resources!(p);
+        let channels = ADC_CHANNELS_CELL.init([
+            Channel::new_pin(sense_adc.voltage, Pull::None),
+            Channel::new_pin(sense_adc.current, Pull::None),
+        ]);
+        spawner
+            .spawn(adc_task(adc, channels, p.DMA_CH0.into_ref()))
+            .unwrap();
+
+        // SiC module temperature duty monitor
+        spawner.spawn(sic_temp_task(sic_temp_sm)).unwrap();
+    }
+    // The real sensors stay set up (the self-test still reads them) but nothing polls them.
+    #[cfg(feature = "sim")]
+    {
+        let _ = (mlx, sic_temp_sm);
+        spawner.spawn(sim::sim_task()).unwrap();
+    }
 
     // ------------------------------------------------------------------------------------------
-    // Safety monitor
+    // E-stop path
     // ------------------------------------------------------------------------------------------
-    spawner
-        .spawn(safety_task(interlock, gate_fault, gate_ready))
+    interrupt::SWI_IRQ_1.set_priority(Priority::P2);
+    let estop_spawner = ESTOP_EXECUTOR.start(interrupt::SWI_IRQ_1);
+    estop_spawner
+        .spawn(estop_task(gate_drive, interlock, gate_fault, overcurrent))
         .unwrap();
 
+    // ------------------------------------------------------------------------------------------
+    // Safety monitor
+    // ------------------------------------------------------------------------------------------
+    spawner.spawn(safety_task(gate_ready)).unwrap();
+
+    // ------------------------------------------------------------------------------------------
+    // Buzzer
+    // ------------------------------------------------------------------------------------------
+    spawner.spawn(buzzer_task(buzzer)).unwrap();
+
     // ------------------------------------------------------------------------------------------
     // Control loop
     // ------------------------------------------------------------------------------------------
+    let mut watchdog = Watchdog::new(p.WATCHDOG);
+    if watchdog.reset_reason().is_some() {
+        warn!("Restarted by the watchdog");
+    }
+    watchdog.start(WATCHDOG_TIMEOUT);
     spawner
         .spawn(control_task(
-            pwm_drive, hs_enable, ls_enable, solenoid, run_button,
+            gate_drive,
+            solenoid,
+            run_button,
+            coolant_flow,
+            watchdog,
         ))
         .unwrap();
 
+    // ------------------------------------------------------------------------------------------
+    // USB telemetry
+    // ------------------------------------------------------------------------------------------
+    #[cfg(feature = "usb-telemetry")]
+    telemetry::init(&spawner, p.USB);
+
+    // ------------------------------------------------------------------------------------------
+    // Modbus RTU slave
+    // ------------------------------------------------------------------------------------------
+    #[cfg(feature = "modbus")]
+    modbus::init(
+        &spawner,
+        &mut sic_pio_common,
+        pio0.sm1,
+        pio0.sm2,
+        p.PIN_2,
+        p.PIN_3,
+        p.PIN_28,
+    );
+
     // ------------------------------------------------------------------------------------------
     // Idle loop
     // ------------------------------------------------------------------------------------------
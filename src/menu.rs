@@ -1,74 +1,391 @@
+use core::cell::Cell;
 use core::fmt::Write;
+#[cfg(feature = "rotary-encoder")]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicU32, Ordering};
+use defmt::{info, warn};
+use embassy_futures::{
+    join::join,
+    select::{select, Either},
+};
 use embassy_rp::gpio::Input;
-use embassy_time::{Duration, Timer};
-use heapless::String;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::{String, Vec};
+use libm::{fabsf, roundf};
 
 use crate::{
-    lcd::Lcd,
-    safety::current_fault,
+    big_digits::{draw_big_number, load_big_digits},
+    board::{DisplayLcd, DC_UNDERVOLTAGE_V},
+    buzzer::chirp,
+    control::{MAX_FREQUENCY_HZ, MAX_RUNTIME, MIN_FREQUENCY_HZ},
+    estop::{gate_fault_active, interlock_open, overcurrent_tripped},
+    lcd::PwmBacklight,
+    safety::{clear_fault, current_fault, fault_watcher, gate_driver_ready},
+    sensors::{capture_active, start_capture, CURRENT_CENTER_V},
     state::{
-        ControlMode, FaultCode, Measurements, COIL_TEMP_LIMIT_C, CONTROL_SETTINGS, CONTROL_STATUS,
-        CURRENT_LIMIT_A, MEASUREMENTS, MODULE_TEMP_LIMIT_C, PCB_TEMP_LIMIT_C, POWER_LIMIT_KW,
+        fault_history, measurements, menu_heartbeat, stale_source, ControlMode, FaultCode,
+        IdleRunAction, LimitKind, Limits, Measurements, Profile, Profiles, TempUnit, TuneState,
+        COMMISSIONING, CONTROL_DIAGNOSTICS, CONTROL_SETTINGS, CONTROL_STATUS, ENERGY_STATS,
+        FAULT_STATE, LIMITS, PROFILES, PROFILE_COUNT, PROFILE_NAME_LEN, RUN_STATS,
+        TARGET_TEMP_MAX_C, TARGET_TEMP_MIN_C, USAGE_STATS,
     },
+    storage::request_save,
+    version::{BUILD_DATE, FIRMWARE_VERSION},
 };
 
 const MANUAL_STEP_KW: f32 = 0.5;
 const TEMP_STEP_C: f32 = 10.0;
-const TEMP_MIN_C: f32 = 40.0;
-const TEMP_MAX_C: f32 = 350.0;
+/// Target step while temperatures are shown in °F; the limits stay the °C ones in `state`.
+const TEMP_STEP_F: f32 = 20.0;
 const STATUS_REFRESH_MS: u64 = 50;
+/// Status lines are only rewritten when their rendered text changes; this forces a full
+/// rewrite anyway so a glitched LCD (switching noise on the bus) recovers on its own.
+const STATUS_FORCE_REDRAW_MS: u64 = 2_000;
+/// Enter has to be held this long on the fault screen to reset a latched fault.
+const FAULT_RESET_HOLD_MS: u64 = 2_000;
+/// Status screens with more to say than fits swap their second line at this period.
+const STATUS_ALTERNATE_MS: u64 = 2_000;
+/// Enter held this long on a status screen arms the run button. On the start screen it does
+/// too when the run button starts the last heating mode from Idle.
+const ARM_HOLD_MS: u64 = 1_000;
+/// Up/Down held this long on a value screen start auto-repeating.
+const REPEAT_DELAY_MS: u64 = 500;
+const REPEAT_INTERVAL_MS: u64 = 150;
+/// Repeats step by these multiples of the normal step, moving one entry along for every
+/// `REPEAT_ACCEL_MS` the button stays held after repeating starts.
+const REPEAT_ACCEL_MS: u64 = 1_500;
+const REPEAT_ACCEL_MULTIPLIERS: [i32; 4] = [1, 2, 4, 8];
+const PCB_TRIM_STEP_C: f32 = 0.5;
+/// Soak at target is set in these steps. The heat-up and the soak both count towards the
+/// control task's max runtime, which cuts the run, so the soak is capped to leave
+/// `SOAK_HEAT_UP_S` of it for reaching the target.
+const SOAK_STEP_S: u16 = 5;
+const SOAK_HEAT_UP_S: u64 = 30;
+const SOAK_MAX_S: u16 = (MAX_RUNTIME.as_secs() - SOAK_HEAT_UP_S) as u16;
+/// Power floor kept on while holding or soaking at target, set in these steps.
+const POWER_FLOOR_STEP_KW: f32 = 0.1;
+const POWER_FLOOR_MAX_KW: f32 = 2.0;
+/// Step of the bench frequency screen.
+const MANUAL_FREQ_STEP_HZ: f32 = 100.0;
+/// Down held this long on a status screen switches to the big readout.
+const BIG_READOUT_HOLD_MS: u64 = 1_000;
+/// First column of the unit and run-state labels next to the big digits.
+const BIG_LABEL_COL: u8 = 12;
+/// With a PWM backlight, the display dims to `BACKLIGHT_DIM_LEVEL` (of 255) after this long
+/// without a button press.
+const BACKLIGHT_IDLE_MS: u32 = 60_000;
+const BACKLIGHT_DIM_LEVEL: u8 = 40;
+/// Without a button press for this long, a settings screen (or a status screen with nothing
+/// running) drops back to Idle and the start screen. Longer than `BACKLIGHT_IDLE_MS`, so the
+/// display dims first.
+const MENU_IDLE_TIMEOUT_MS: u32 = 120_000;
+/// A button held down longer than this is taken to be stuck and reads as released until it
+/// actually lets go, so one jammed switch cannot freeze the menu. Well above every deliberate
+/// hold on the screens.
+const BUTTON_STUCK_MS: u64 = 10_000;
+/// Enter held this long on the start screen opens the About screen, past arming.
+const ABOUT_HOLD_MS: u64 = 2_000;
+/// A profile's run-time limit is set in these steps, up to the control task's own max runtime.
+const PROFILE_RUN_TIME_STEP_S: u16 = 5;
+const PROFILE_RUN_TIME_MAX_S: u16 = 120;
+/// Characters a profile name can be spelled with, in the order Up steps through them.
+const PROFILE_NAME_CHARS: &[u8] =
+    b" ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-";
+// Readings a freshly powered, cold unit should be showing before it is allowed to heat.
+const COMMISSION_AMBIENT_MIN_C: f32 = 0.0;
+const COMMISSION_AMBIENT_MAX_C: f32 = 50.0;
+// The hall zero has to sit this close to its nominal level to be accepted (~25 A of offset).
+const COMMISSION_ZERO_TOLERANCE_V: f32 = 0.02;
+
+/// A menu input: a button pin, or with the `rotary-encoder` feature one direction of the
+/// encoder, pressed while `encoder_press_task` replays a detent.
+pub struct MenuButton {
+    name: &'static str,
+    input: ButtonInput,
+    /// When the current press was first seen, for the stuck check.
+    pressed_since: Cell<Option<Instant>>,
+    /// Held past `BUTTON_STUCK_MS`; ignored until released.
+    stuck: Cell<bool>,
+}
+
+enum ButtonInput {
+    Pin(Input<'static>),
+    #[cfg(feature = "rotary-encoder")]
+    Detent(&'static AtomicBool),
+}
+
+/// Uptime in milliseconds (wrapping) when any menu button was last seen pressed.
+static LAST_PRESS_MS: AtomicU32 = AtomicU32::new(0);
+
+impl MenuButton {
+    pub fn pin(name: &'static str, pin: Input<'static>) -> Self {
+        Self::new(name, ButtonInput::Pin(pin))
+    }
+
+    #[cfg(feature = "rotary-encoder")]
+    pub fn detent(name: &'static str, pressed: &'static AtomicBool) -> Self {
+        Self::new(name, ButtonInput::Detent(pressed))
+    }
+
+    fn new(name: &'static str, input: ButtonInput) -> Self {
+        Self {
+            name,
+            input,
+            pressed_since: Cell::new(None),
+            stuck: Cell::new(false),
+        }
+    }
+
+    /// Whether the button is pressed. A button held past `BUTTON_STUCK_MS` reads as released
+    /// until it really is.
+    ///
+    /// Every screen polls its buttons while it waits, so this is also where the menu proves to
+    /// `safety_task` that it is alive. Loops that skip the poll on some passes beat themselves.
+    pub fn is_low(&self) -> bool {
+        menu_heartbeat();
+        let pressed = match &self.input {
+            ButtonInput::Pin(pin) => pin.is_low(),
+            #[cfg(feature = "rotary-encoder")]
+            ButtonInput::Detent(pressed) => pressed.load(Ordering::Relaxed),
+        };
+        if !pressed {
+            self.pressed_since.set(None);
+            if self.stuck.replace(false) {
+                info!("{} button released, no longer ignored", self.name);
+            }
+            return false;
+        }
+        if self.stuck.get() {
+            return false;
+        }
+
+        let now = Instant::now();
+        let since = self.pressed_since.get().unwrap_or(now);
+        self.pressed_since.set(Some(since));
+        if now.saturating_duration_since(since) >= Duration::from_millis(BUTTON_STUCK_MS) {
+            warn!(
+                "{} button held for {} ms, ignoring it as stuck",
+                self.name, BUTTON_STUCK_MS
+            );
+            self.stuck.set(true);
+            return false;
+        }
+        LAST_PRESS_MS.store(now.as_millis() as u32, Ordering::Relaxed);
+        true
+    }
+
+    pub fn is_high(&self) -> bool {
+        !self.is_low()
+    }
+}
 
 #[embassy_executor::task]
 pub async fn menu_task(
-    mut lcd: Lcd<'static>,
-    mut up: Input<'static>,
-    mut down: Input<'static>,
-    mut enter: Input<'static>,
+    mut lcd: DisplayLcd,
+    mut up: MenuButton,
+    mut down: MenuButton,
+    mut enter: MenuButton,
 ) {
-    lcd.backlight(true);
+    if lcd.has_backlight() {
+        lcd.backlight(true);
+    } else {
+        info!("LCD backlight not controllable, idle dimming off");
+    }
     lcd.clear().await;
     lcd.home().await;
 
-    let mut screen = Screen::ModeSelect;
+    let mut screen = if up.is_low() && down.is_low() {
+        // Up+Down held through boot opens the limits menu.
+        display_line(&mut lcd, 0, "Engineering").await;
+        while up.is_low() || down.is_low() {
+            Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+        }
+        Screen::Engineering
+    } else {
+        home_screen().await
+    };
     let mut selected_mode = ControlMode::ManualPower;
+    let backlight = lcd.backlight_pwm();
+
+    let screens = async {
+        loop {
+            // The raw readings stay up through a fault; they are what it gets diagnosed with.
+            if current_fault() != FaultCode::None && !matches!(screen, Screen::RawSensors) {
+                screen = fault_screen(&mut lcd, &mut enter, screen).await;
+                continue;
+            }
+
+            let current = screen;
+            let show = async {
+                match current {
+                    Screen::ModeSelect => {
+                        set_mode(ControlMode::Idle).await;
+                        mode_select_screen(&mut lcd, &mut up, &mut down, &mut enter, selected_mode)
+                            .await
+                    }
+                    Screen::ManualConfig => {
+                        selected_mode = ControlMode::ManualPower;
+                        set_mode(ControlMode::ManualPower).await;
+                        manual_config_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::ManualStatus => {
+                        selected_mode = ControlMode::ManualPower;
+                        set_mode(ControlMode::ManualPower).await;
+                        manual_status_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::TemperatureConfig => {
+                        selected_mode = ControlMode::Temperature;
+                        set_mode(ControlMode::Temperature).await;
+                        temperature_config_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::TemperatureHoldConfig => {
+                        selected_mode = ControlMode::Temperature;
+                        set_mode(ControlMode::Temperature).await;
+                        temperature_hold_config_screen(&mut lcd, &mut up, &mut down, &mut enter)
+                            .await
+                    }
+                    Screen::SoakConfig => {
+                        selected_mode = ControlMode::Temperature;
+                        set_mode(ControlMode::Temperature).await;
+                        soak_config_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::PowerFloorConfig => {
+                        selected_mode = ControlMode::Temperature;
+                        set_mode(ControlMode::Temperature).await;
+                        power_floor_config_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::TemperatureStatus => {
+                        selected_mode = ControlMode::Temperature;
+                        set_mode(ControlMode::Temperature).await;
+                        temperature_status_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::BigReadout => {
+                        set_mode(selected_mode).await;
+                        big_readout_screen(&mut lcd, &mut up, &mut down, &mut enter, selected_mode)
+                            .await
+                    }
+                    Screen::Cooldown => {
+                        set_mode(ControlMode::Cooldown).await;
+                        cooldown_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::RunStats => {
+                        set_mode(ControlMode::Cooldown).await;
+                        run_stats_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::Diagnostics => {
+                        set_mode(ControlMode::Idle).await;
+                        diagnostics_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::RawSensors => {
+                        set_mode(ControlMode::Idle).await;
+                        raw_sensors_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::AutoTune => {
+                        set_mode(ControlMode::AutoTune).await;
+                        auto_tune_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::FaultHistory => {
+                        set_mode(ControlMode::Idle).await;
+                        fault_history_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::Units => {
+                        set_mode(ControlMode::Idle).await;
+                        units_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::IdleRunConfig => {
+                        set_mode(ControlMode::Idle).await;
+                        idle_run_config_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::Profiles => {
+                        set_mode(ControlMode::Idle).await;
+                        profiles_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::Commissioning => {
+                        set_mode(ControlMode::Idle).await;
+                        commissioning_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::Engineering => {
+                        set_mode(ControlMode::Idle).await;
+                        engineering_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::FrequencyConfig => {
+                        set_mode(ControlMode::ManualFrequency).await;
+                        frequency_config_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::FrequencyStatus => {
+                        set_mode(ControlMode::ManualFrequency).await;
+                        frequency_status_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                    Screen::About => {
+                        set_mode(ControlMode::Idle).await;
+                        about_screen(&mut lcd, &mut up, &mut down, &mut enter).await
+                    }
+                }
+            };
+            screen = match select(show, idle_timeout(current.idle_timeout())).await {
+                Either::First(next) => next,
+                Either::Second(()) => {
+                    // The screen was dropped at whatever it was awaiting, which can be halfway
+                    // through a byte on the LCD bus; re-initialising puts the controller back in
+                    // step before anything else is drawn.
+                    lcd.init().await;
+                    set_mode(ControlMode::Idle).await;
+                    home_screen().await
+                }
+            };
+        }
+    };
+
+    match backlight {
+        Some(backlight) => {
+            join(screens, dim_when_idle(backlight)).await;
+        }
+        None => screens.await,
+    }
+}
+
+/// Which screens [`idle_timeout`] may leave.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum IdlePolicy {
+    Never,
+    WhenNotRunning,
+    Always,
+}
 
+/// Returns once no button has been pressed for `MENU_IDLE_TIMEOUT_MS`, as allowed by `policy`.
+/// Never returns while a fault is active, so a fault screen is only left by the operator.
+async fn idle_timeout(policy: IdlePolicy) {
+    if policy == IdlePolicy::Never {
+        return core::future::pending().await;
+    }
     loop {
-        if let FaultCode::None = current_fault().await {
-        } else {
-            screen = fault_screen(&mut lcd, screen).await;
+        Timer::after(Duration::from_millis(STATUS_ALTERNATE_MS)).await;
+        if current_fault() != FaultCode::None {
+            continue;
+        }
+        if policy == IdlePolicy::WhenNotRunning && CONTROL_STATUS.lock().await.run_active {
             continue;
         }
+        let now_ms = Instant::now().as_millis() as u32;
+        if now_ms.wrapping_sub(LAST_PRESS_MS.load(Ordering::Relaxed)) >= MENU_IDLE_TIMEOUT_MS {
+            return;
+        }
+    }
+}
 
-        screen = match screen {
-            Screen::ModeSelect => {
-                set_mode(ControlMode::Idle).await;
-                mode_select_screen(&mut lcd, &mut up, &mut down, &mut enter, selected_mode).await
-            }
-            Screen::ManualConfig => {
-                selected_mode = ControlMode::ManualPower;
-                set_mode(ControlMode::ManualPower).await;
-                manual_config_screen(&mut lcd, &mut up, &mut down, &mut enter).await
-            }
-            Screen::ManualStatus => {
-                selected_mode = ControlMode::ManualPower;
-                set_mode(ControlMode::ManualPower).await;
-                manual_status_screen(&mut lcd, &mut up, &mut down, &mut enter).await
-            }
-            Screen::TemperatureConfig => {
-                selected_mode = ControlMode::Temperature;
-                set_mode(ControlMode::Temperature).await;
-                temperature_config_screen(&mut lcd, &mut up, &mut down, &mut enter).await
-            }
-            Screen::TemperatureStatus => {
-                selected_mode = ControlMode::Temperature;
-                set_mode(ControlMode::Temperature).await;
-                temperature_status_screen(&mut lcd, &mut up, &mut down, &mut enter).await
-            }
-            Screen::Cooldown => {
-                set_mode(ControlMode::Cooldown).await;
-                cooldown_screen(&mut lcd, &mut up, &mut down, &mut enter).await
-            }
-        };
+/// Dims a PWM backlight after `BACKLIGHT_IDLE_MS` without a button press or an active fault,
+/// and brings it back to full brightness on the next press.
+async fn dim_when_idle(backlight: &'static PwmBacklight) {
+    let mut dimmed = false;
+    loop {
+        let now_ms = Instant::now().as_millis() as u32;
+        let idle_ms = now_ms.wrapping_sub(LAST_PRESS_MS.load(Ordering::Relaxed));
+        let idle = idle_ms >= BACKLIGHT_IDLE_MS && current_fault() == FaultCode::None;
+        if idle != dimmed {
+            backlight.set_level(if idle { BACKLIGHT_DIM_LEVEL } else { u8::MAX });
+            dimmed = idle;
+        }
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
     }
 }
 
@@ -78,17 +395,85 @@ enum Screen {
     ManualConfig,
     ManualStatus,
     TemperatureConfig,
+    TemperatureHoldConfig,
+    SoakConfig,
+    PowerFloorConfig,
     TemperatureStatus,
+    BigReadout,
     Cooldown,
+    RunStats,
+    Diagnostics,
+    RawSensors,
+    AutoTune,
+    FaultHistory,
+    Units,
+    IdleRunConfig,
+    Profiles,
+    Commissioning,
+    Engineering,
+    FrequencyConfig,
+    FrequencyStatus,
+    About,
+}
+
+impl Screen {
+    fn idle_timeout(self) -> IdlePolicy {
+        match self {
+            // Already idle, or running something that must not be cut short.
+            Screen::ModeSelect | Screen::Cooldown | Screen::RunStats | Screen::AutoTune => {
+                IdlePolicy::Never
+            }
+            Screen::ManualStatus
+            | Screen::TemperatureStatus
+            | Screen::BigReadout
+            | Screen::FrequencyStatus => IdlePolicy::WhenNotRunning,
+            Screen::ManualConfig
+            | Screen::TemperatureConfig
+            | Screen::TemperatureHoldConfig
+            | Screen::SoakConfig
+            | Screen::PowerFloorConfig
+            | Screen::Diagnostics
+            | Screen::RawSensors
+            | Screen::FaultHistory
+            | Screen::Units
+            | Screen::IdleRunConfig
+            | Screen::Profiles
+            | Screen::Commissioning
+            | Screen::Engineering
+            | Screen::FrequencyConfig
+            | Screen::About => IdlePolicy::Always,
+        }
+    }
+}
+
+/// Where the menu starts: mode selection, or commissioning on a unit not yet commissioned.
+async fn home_screen() -> Screen {
+    if COMMISSIONING.lock().await.commissioned {
+        Screen::ModeSelect
+    } else {
+        Screen::Commissioning
+    }
 }
 
 async fn mode_select_screen(
-    lcd: &mut Lcd<'static>,
-    up: &mut Input<'static>,
-    down: &mut Input<'static>,
-    enter: &mut Input<'static>,
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
     current_mode: ControlMode,
 ) -> Screen {
+    const ITEMS: &[(&str, Screen)] = &[
+        ("Manual Power", Screen::ManualConfig),
+        ("Temperature", Screen::TemperatureConfig),
+        ("Profiles", Screen::Profiles),
+        ("Diagnostics", Screen::Diagnostics),
+        ("Raw sensors", Screen::RawSensors),
+        ("Auto-tune", Screen::AutoTune),
+        ("Fault history", Screen::FaultHistory),
+        ("Units", Screen::Units),
+        ("Run in Idle", Screen::IdleRunConfig),
+    ];
+
     let mut index = if current_mode == ControlMode::Temperature {
         1
     } else {
@@ -96,56 +481,61 @@ async fn mode_select_screen(
     };
     loop {
         lcd.clear().await;
-        display_line(
-            lcd,
-            0,
-            if index == 0 {
-                "> Manual Power"
-            } else {
-                "  Manual Power"
-            },
-        )
-        .await;
-        display_line(
-            lcd,
-            1,
-            if index == 1 {
-                "> Temperature "
-            } else {
-                "  Temperature "
-            },
+        for row in 0..2u8 {
+            let item = (index + row as usize) % ITEMS.len();
+            let mut line = String::<16>::new();
+            let _ = write!(
+                line,
+                "{}{}",
+                if row == 0 { "> " } else { "  " },
+                ITEMS[item].0
+            );
+            display_line(lcd, row, line.as_str()).await;
+        }
+
+        // The run button can start the last heating mode from Idle; follow it if it does. It
+        // needs arming first, like on the status screens.
+        let arm_on_hold =
+            CONTROL_SETTINGS.lock().await.idle_run_action == IdleRunAction::StartLastMode;
+        let outcome = match select(
+            wait_for_press_or_enter_hold(up, down, enter, arm_on_hold),
+            wait_for_heating_mode(),
         )
-        .await;
+        .await
+        {
+            Either::First(Some(outcome)) => outcome,
+            Either::First(None) => return Screen::About,
+            Either::Second(ControlMode::Temperature) => return Screen::TemperatureStatus,
+            Either::Second(_) => return Screen::ManualStatus,
+        };
 
-        match wait_for_press(up, down, enter).await {
+        match outcome {
             WaitOutcome::Button(ButtonPressed::Up) => {
-                index = (index + 1) % 2;
+                index = (index + ITEMS.len() - 1) % ITEMS.len();
             }
             WaitOutcome::Button(ButtonPressed::Down) => {
-                index = (index + 1) % 2;
+                index = (index + 1) % ITEMS.len();
             }
             WaitOutcome::Button(ButtonPressed::Enter) => {
-                return if index == 0 {
-                    Screen::ManualConfig
-                } else {
-                    Screen::TemperatureConfig
-                };
+                request_save();
+                return ITEMS[index].1;
             }
             WaitOutcome::Fault => {
-                return fault_screen(lcd, Screen::ModeSelect).await;
+                return fault_screen(lcd, enter, Screen::ModeSelect).await;
             }
         }
     }
 }
 
 async fn manual_config_screen(
-    lcd: &mut Lcd<'static>,
-    up: &mut Input<'static>,
-    down: &mut Input<'static>,
-    enter: &mut Input<'static>,
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
 ) -> Screen {
     lcd.clear().await;
     display_line(lcd, 0, "Manual power set").await;
+    let mut held_since = None;
 
     loop {
         let value = {
@@ -157,72 +547,1304 @@ async fn manual_config_screen(
         write!(&mut line, "Target: {:>4.1}kW", value).ok();
         display_line(lcd, 1, line.as_str()).await;
 
-        match wait_for_press(up, down, enter).await {
-            WaitOutcome::Button(ButtonPressed::Up) => {
-                let next = (value + MANUAL_STEP_KW).clamp(0.0, POWER_LIMIT_KW);
-                set_manual_power(next).await;
-            }
-            WaitOutcome::Button(ButtonPressed::Down) => {
-                let next = (value - MANUAL_STEP_KW).clamp(0.0, POWER_LIMIT_KW);
-                set_manual_power(next).await;
+        match wait_for_adjust(up, down, enter, &mut held_since).await {
+            Adjust::Steps(steps) => {
+                let power_limit_kw = LIMITS.lock().await.power_kw();
+                let next = (value + steps as f32 * MANUAL_STEP_KW).clamp(0.0, power_limit_kw);
+                set_manual_power(next).await;
+            }
+            Adjust::Enter => {
+                request_save();
+                return Screen::ManualStatus;
+            }
+            Adjust::Fault => {
+                return fault_screen(lcd, enter, Screen::ManualConfig).await;
+            }
+        }
+    }
+}
+
+async fn manual_status_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    let mut lines = StatusLines::new();
+    loop {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::ManualStatus).await {
+            return next;
+        }
+
+        let status = CONTROL_STATUS.lock().await.clone();
+        if status.runtime_limited {
+            return Screen::Cooldown;
+        }
+        let meas = measurements();
+        let v_display = meas.dc_voltage_v.clamp(0.0, 999.0);
+        let i_display = meas.coil_current_rms_a.clamp(0.0, 999.0);
+
+        let mut line1 = String::<16>::new();
+        if status.power_derate < 1.0 {
+            write!(
+                &mut line1,
+                "P {:>4.1}k D{:>3.0}%",
+                meas.coil_power_kw,
+                status.power_derate * 100.0
+            )
+            .ok();
+        } else {
+            write!(
+                &mut line1,
+                "P {:>4.1}k T {:>4.1}k",
+                meas.coil_power_kw, status.power_setpoint_kw
+            )
+            .ok();
+        }
+        lines.update(lcd, 0, line1.as_str()).await;
+
+        let armed = CONTROL_SETTINGS.lock().await.armed;
+        let mut line2 = String::<16>::new();
+        let run_label = if status.run_active { "R:ON" } else { "R:OFF" };
+        if !status.run_active && show_alternate() {
+            line2.push_str(arm_prompt(armed)).ok();
+        } else if show_alternate() {
+            let energy_kwh = ENERGY_STATS.lock().await.delivered_energy_kwh;
+            write!(&mut line2, "{} E{:>5.3}kWh", run_label, energy_kwh).ok();
+        } else {
+            write!(
+                &mut line2,
+                "{} V{:>3.0} I{:>3.0}",
+                run_label, v_display, i_display
+            )
+            .ok();
+        }
+        lines.update(lcd, 1, line2.as_str()).await;
+
+        if enter.is_low() {
+            if !status.run_active && held_for(enter, ARM_HOLD_MS).await {
+                arm(enter).await;
+                continue;
+            }
+            wait_for_release(enter).await;
+            return Screen::ModeSelect;
+        }
+        if up.is_low() {
+            wait_for_release(up).await;
+            return Screen::ManualConfig;
+        }
+        if down.is_low() {
+            let big = held_for(down, BIG_READOUT_HOLD_MS).await;
+            wait_for_release(down).await;
+            return if big {
+                Screen::BigReadout
+            } else {
+                Screen::ModeSelect
+            };
+        }
+
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+    }
+}
+
+async fn temperature_config_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    display_line(lcd, 0, "Target temp").await;
+    let mut held_since = None;
+
+    loop {
+        let (value, unit) = {
+            let settings = CONTROL_SETTINGS.lock().await;
+            (settings.target_temp_c, settings.temp_unit)
+        };
+        let shown = unit.from_celsius(value);
+
+        let mut line = String::<16>::new();
+        write!(&mut line, "Target: {:>4.0}{}", shown, unit.symbol()).ok();
+        display_line(lcd, 1, line.as_str()).await;
+
+        // Step on the grid of the unit being shown, so °F targets stay round numbers.
+        let step = match unit {
+            TempUnit::Celsius => TEMP_STEP_C,
+            TempUnit::Fahrenheit => TEMP_STEP_F,
+        };
+        let on_grid = roundf(shown / step) * step;
+        match wait_for_adjust(up, down, enter, &mut held_since).await {
+            Adjust::Steps(steps) => {
+                let next = unit
+                    .to_celsius(on_grid + steps as f32 * step)
+                    .clamp(TARGET_TEMP_MIN_C, TARGET_TEMP_MAX_C);
+                set_temperature_target(next).await;
+            }
+            Adjust::Enter => {
+                request_save();
+                return Screen::TemperatureHoldConfig;
+            }
+            Adjust::Fault => {
+                return fault_screen(lcd, enter, Screen::TemperatureConfig).await;
+            }
+        }
+    }
+}
+
+async fn temperature_hold_config_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    display_line(lcd, 0, "At target:").await;
+
+    loop {
+        let hold = CONTROL_SETTINGS.lock().await.hold_at_target;
+        display_line(
+            lcd,
+            1,
+            if hold {
+                "> Hold temp"
+            } else {
+                "> Prompt to cool"
+            },
+        )
+        .await;
+
+        match wait_for_press(up, down, enter).await {
+            WaitOutcome::Button(ButtonPressed::Up) | WaitOutcome::Button(ButtonPressed::Down) => {
+                set_hold_at_target(!hold).await;
+            }
+            WaitOutcome::Button(ButtonPressed::Enter) => {
+                request_save();
+                return Screen::SoakConfig;
+            }
+            WaitOutcome::Fault => {
+                return fault_screen(lcd, enter, Screen::TemperatureHoldConfig).await;
+            }
+        }
+    }
+}
+
+async fn soak_config_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    display_line(lcd, 0, "Soak at target:").await;
+    let mut held_since = None;
+
+    loop {
+        let soak_s = CONTROL_SETTINGS.lock().await.soak_s;
+        let mut line = String::<16>::new();
+        if soak_s == 0 {
+            line.push_str("> Off").ok();
+        } else {
+            write!(&mut line, "> {}:{:02}", soak_s / 60, soak_s % 60).ok();
+        }
+        display_line(lcd, 1, line.as_str()).await;
+
+        match wait_for_adjust(up, down, enter, &mut held_since).await {
+            Adjust::Steps(steps) => {
+                let next = (soak_s as i32 + steps * SOAK_STEP_S as i32).clamp(0, SOAK_MAX_S as i32);
+                CONTROL_SETTINGS.lock().await.soak_s = next as u16;
+            }
+            Adjust::Enter => {
+                request_save();
+                return Screen::PowerFloorConfig;
+            }
+            Adjust::Fault => {
+                return fault_screen(lcd, enter, Screen::SoakConfig).await;
+            }
+        }
+    }
+}
+
+async fn power_floor_config_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    display_line(lcd, 0, "Floor at target:").await;
+    let mut held_since = None;
+
+    loop {
+        let floor_kw = CONTROL_SETTINGS.lock().await.power_floor_kw;
+        let mut line = String::<16>::new();
+        if floor_kw <= 0.0 {
+            line.push_str("> Off").ok();
+        } else {
+            write!(&mut line, "> {:.1}kW", floor_kw).ok();
+        }
+        display_line(lcd, 1, line.as_str()).await;
+
+        match wait_for_adjust(up, down, enter, &mut held_since).await {
+            Adjust::Steps(steps) => {
+                let on_grid = roundf(floor_kw / POWER_FLOOR_STEP_KW);
+                let next =
+                    ((on_grid + steps as f32) * POWER_FLOOR_STEP_KW).clamp(0.0, POWER_FLOOR_MAX_KW);
+                CONTROL_SETTINGS.lock().await.power_floor_kw = next;
+            }
+            Adjust::Enter => {
+                request_save();
+                return Screen::TemperatureStatus;
+            }
+            Adjust::Fault => {
+                return fault_screen(lcd, enter, Screen::PowerFloorConfig).await;
+            }
+        }
+    }
+}
+
+async fn units_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    display_line(lcd, 0, "Temp units:").await;
+
+    loop {
+        let unit = CONTROL_SETTINGS.lock().await.temp_unit;
+        display_line(
+            lcd,
+            1,
+            match unit {
+                TempUnit::Celsius => "> Celsius",
+                TempUnit::Fahrenheit => "> Fahrenheit",
+            },
+        )
+        .await;
+
+        match wait_for_press(up, down, enter).await {
+            WaitOutcome::Button(ButtonPressed::Up) | WaitOutcome::Button(ButtonPressed::Down) => {
+                set_temp_unit(match unit {
+                    TempUnit::Celsius => TempUnit::Fahrenheit,
+                    TempUnit::Fahrenheit => TempUnit::Celsius,
+                })
+                .await;
+            }
+            WaitOutcome::Button(ButtonPressed::Enter) => {
+                request_save();
+                return Screen::ModeSelect;
+            }
+            WaitOutcome::Fault => {
+                return fault_screen(lcd, enter, Screen::Units).await;
+            }
+        }
+    }
+}
+
+/// What the run button does from the start screen, with no heating mode selected.
+async fn idle_run_config_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    display_line(lcd, 0, "Run in Idle:").await;
+
+    loop {
+        let action = CONTROL_SETTINGS.lock().await.idle_run_action;
+        display_line(
+            lcd,
+            1,
+            match action {
+                IdleRunAction::Ignore => "> Ignore",
+                IdleRunAction::StartLastMode => "> Start last",
+            },
+        )
+        .await;
+
+        match wait_for_press(up, down, enter).await {
+            WaitOutcome::Button(ButtonPressed::Up) | WaitOutcome::Button(ButtonPressed::Down) => {
+                CONTROL_SETTINGS.lock().await.idle_run_action = match action {
+                    IdleRunAction::Ignore => IdleRunAction::StartLastMode,
+                    IdleRunAction::StartLastMode => IdleRunAction::Ignore,
+                };
+            }
+            WaitOutcome::Button(ButtonPressed::Enter) => {
+                request_save();
+                return Screen::ModeSelect;
+            }
+            WaitOutcome::Fault => {
+                return fault_screen(lcd, enter, Screen::IdleRunConfig).await;
+            }
+        }
+    }
+}
+
+/// Lists the profile slots and a final "Back". Enter on a slot offers to load it, save the
+/// current settings into it, or edit it.
+async fn profiles_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    let entries = PROFILE_COUNT + 1;
+    let mut index = PROFILES.lock().await.selected;
+    loop {
+        let profiles = *PROFILES.lock().await;
+        lcd.clear().await;
+        for row in 0..2u8 {
+            let item = (index + row as usize) % entries;
+            let line = profile_list_line(&profiles, item, row == 0);
+            display_line(lcd, row, line.as_str()).await;
+        }
+
+        match wait_for_press(up, down, enter).await {
+            WaitOutcome::Button(ButtonPressed::Up) => index = (index + entries - 1) % entries,
+            WaitOutcome::Button(ButtonPressed::Down) => index = (index + 1) % entries,
+            WaitOutcome::Button(ButtonPressed::Enter) if index == PROFILE_COUNT => {
+                return Screen::ModeSelect;
+            }
+            WaitOutcome::Button(ButtonPressed::Enter) => {
+                if let Some(next) = profile_actions(lcd, up, down, enter, index).await {
+                    return next;
+                }
+            }
+            WaitOutcome::Fault => return fault_screen(lcd, enter, Screen::Profiles).await,
+        }
+    }
+}
+
+fn profile_list_line(profiles: &Profiles, item: usize, cursor: bool) -> String<16> {
+    let mut line = String::<16>::new();
+    let marker = if cursor { "> " } else { "  " };
+    match profiles.slots.get(item) {
+        Some(Some(profile)) => write!(line, "{}{} {}", marker, item + 1, profile.name()).ok(),
+        Some(None) => write!(line, "{}{} (empty)", marker, item + 1).ok(),
+        None => write!(line, "{}Back", marker).ok(),
+    };
+    line
+}
+
+/// Actions on one slot. Returns the screen to go to, or `None` to go back to the slot list.
+async fn profile_actions(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+    slot: usize,
+) -> Option<Screen> {
+    const ACTIONS: [&str; 4] = ["Load", "Save current", "Edit", "Back"];
+    let mut action = 0;
+    loop {
+        let profiles = *PROFILES.lock().await;
+        lcd.clear().await;
+        let header = profile_list_line(&profiles, slot, false);
+        display_line(lcd, 0, header.trim_start()).await;
+        let mut line = String::<16>::new();
+        write!(line, "> {}", ACTIONS[action]).ok();
+        display_line(lcd, 1, line.as_str()).await;
+
+        match wait_for_press(up, down, enter).await {
+            WaitOutcome::Button(ButtonPressed::Up) => {
+                action = (action + ACTIONS.len() - 1) % ACTIONS.len();
+            }
+            WaitOutcome::Button(ButtonPressed::Down) => action = (action + 1) % ACTIONS.len(),
+            WaitOutcome::Button(ButtonPressed::Enter) => match action {
+                0 => {
+                    let mode = {
+                        let mut settings = CONTROL_SETTINGS.lock().await;
+                        PROFILES.lock().await.load(slot, &mut settings);
+                        settings.last_run_mode
+                    };
+                    request_save();
+                    return Some(match mode {
+                        ControlMode::Temperature => Screen::TemperatureConfig,
+                        _ => Screen::ManualConfig,
+                    });
+                }
+                1 => {
+                    let settings = *CONTROL_SETTINGS.lock().await;
+                    let name = profiles.slots[slot]
+                        .map(|profile| profile.name)
+                        .unwrap_or_else(|| default_profile_name(slot));
+                    PROFILES.lock().await.slots[slot] =
+                        Some(Profile::from_settings(name, &settings));
+                    request_save();
+                    return None;
+                }
+                2 => return edit_profile(lcd, up, down, enter, slot).await,
+                _ => return None,
+            },
+            WaitOutcome::Fault => return Some(fault_screen(lcd, enter, Screen::Profiles).await),
+        }
+    }
+}
+
+fn default_profile_name(slot: usize) -> [u8; PROFILE_NAME_LEN] {
+    let mut name = *b"Prof    ";
+    name[5] = b'1' + slot as u8;
+    name
+}
+
+/// Walks through the slot's name, mode, setpoint and run-time limit, then stores it. An empty
+/// slot starts from the current settings.
+async fn edit_profile(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+    slot: usize,
+) -> Option<Screen> {
+    let stored = PROFILES.lock().await.slots[slot];
+    let (mut profile, unit) = {
+        let settings = CONTROL_SETTINGS.lock().await;
+        let profile =
+            stored.unwrap_or_else(|| Profile::from_settings(default_profile_name(slot), &settings));
+        (profile, settings.temp_unit)
+    };
+    if !edit_profile_name(lcd, up, down, enter, &mut profile.name).await {
+        return Some(fault_screen(lcd, enter, Screen::Profiles).await);
+    }
+
+    lcd.clear().await;
+    display_line(lcd, 0, "Profile mode:").await;
+    loop {
+        display_line(
+            lcd,
+            1,
+            match profile.mode {
+                ControlMode::Temperature => "> Temperature",
+                _ => "> Manual power",
+            },
+        )
+        .await;
+        match wait_for_press(up, down, enter).await {
+            WaitOutcome::Button(ButtonPressed::Enter) => break,
+            WaitOutcome::Button(_) => {
+                profile.mode = match profile.mode {
+                    ControlMode::Temperature => ControlMode::ManualPower,
+                    _ => ControlMode::Temperature,
+                };
+            }
+            WaitOutcome::Fault => return Some(fault_screen(lcd, enter, Screen::Profiles).await),
+        }
+    }
+
+    let mut held_since = None;
+    lcd.clear().await;
+    if profile.mode == ControlMode::Temperature {
+        display_line(lcd, 0, "Profile target").await;
+        let step = match unit {
+            TempUnit::Celsius => TEMP_STEP_C,
+            TempUnit::Fahrenheit => TEMP_STEP_F,
+        };
+        loop {
+            let shown = unit.from_celsius(profile.target_temp_c);
+            let mut line = String::<16>::new();
+            write!(&mut line, "Target: {:>4.0}{}", shown, unit.symbol()).ok();
+            display_line(lcd, 1, line.as_str()).await;
+            match wait_for_adjust(up, down, enter, &mut held_since).await {
+                Adjust::Steps(steps) => {
+                    let on_grid = roundf(shown / step) * step;
+                    profile.target_temp_c = unit
+                        .to_celsius(on_grid + steps as f32 * step)
+                        .clamp(TARGET_TEMP_MIN_C, TARGET_TEMP_MAX_C);
+                }
+                Adjust::Enter => break,
+                Adjust::Fault => return Some(fault_screen(lcd, enter, Screen::Profiles).await),
+            }
+        }
+    } else {
+        display_line(lcd, 0, "Profile power").await;
+        loop {
+            let mut line = String::<16>::new();
+            write!(&mut line, "Target: {:>4.1}kW", profile.manual_power_kw).ok();
+            display_line(lcd, 1, line.as_str()).await;
+            match wait_for_adjust(up, down, enter, &mut held_since).await {
+                Adjust::Steps(steps) => {
+                    let power_limit_kw = LIMITS.lock().await.power_kw();
+                    profile.manual_power_kw = (profile.manual_power_kw
+                        + steps as f32 * MANUAL_STEP_KW)
+                        .clamp(0.0, power_limit_kw);
+                }
+                Adjust::Enter => break,
+                Adjust::Fault => return Some(fault_screen(lcd, enter, Screen::Profiles).await),
+            }
+        }
+    }
+
+    lcd.clear().await;
+    display_line(lcd, 0, "Run time limit").await;
+    loop {
+        let mut line = String::<16>::new();
+        if profile.run_time_limit_s == 0 {
+            write!(&mut line, "Off").ok();
+        } else {
+            write!(&mut line, "{} s", profile.run_time_limit_s).ok();
+        }
+        display_line(lcd, 1, line.as_str()).await;
+        match wait_for_adjust(up, down, enter, &mut held_since).await {
+            Adjust::Steps(steps) => {
+                let next = profile.run_time_limit_s as i32 + steps * PROFILE_RUN_TIME_STEP_S as i32;
+                profile.run_time_limit_s = next.clamp(0, PROFILE_RUN_TIME_MAX_S as i32) as u16;
+            }
+            Adjust::Enter => break,
+            Adjust::Fault => return Some(fault_screen(lcd, enter, Screen::Profiles).await),
+        }
+    }
+
+    PROFILES.lock().await.slots[slot] = Some(profile);
+    request_save();
+    None
+}
+
+/// Edits `name` one character at a time under a blinking cursor: Up/Down change the character,
+/// Enter moves on. Returns false if a fault interrupted it.
+async fn edit_profile_name(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+    name: &mut [u8; PROFILE_NAME_LEN],
+) -> bool {
+    lcd.clear().await;
+    display_line(lcd, 0, "Name, Ent=next").await;
+    let mut held_since = None;
+    for pos in 0..PROFILE_NAME_LEN {
+        loop {
+            display_line(lcd, 1, core::str::from_utf8(name).unwrap_or("")).await;
+            lcd.set_cursor(pos as u8, 1).await;
+            lcd.show_blink(true).await;
+            let outcome = wait_for_adjust(up, down, enter, &mut held_since).await;
+            lcd.show_blink(false).await;
+            match outcome {
+                Adjust::Steps(steps) => {
+                    let current = PROFILE_NAME_CHARS
+                        .iter()
+                        .position(|&c| c == name[pos])
+                        .unwrap_or(0) as i32;
+                    let next = (current + steps).rem_euclid(PROFILE_NAME_CHARS.len() as i32);
+                    name[pos] = PROFILE_NAME_CHARS[next as usize];
+                }
+                Adjust::Enter => break,
+                Adjust::Fault => return false,
+            }
+        }
+    }
+    true
+}
+
+/// Steps through the adjustable limits; Enter moves to the next and saves after the last. Each
+/// value is clamped to its range by `Limits::set`, so it stops at the hard ceiling.
+async fn engineering_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    let mut held_since = None;
+    for kind in LimitKind::ALL {
+        lcd.clear().await;
+        display_line(lcd, 0, kind.label()).await;
+
+        loop {
+            let value = LIMITS.lock().await.get(kind);
+            let (_, max) = kind.range();
+            let mut line = String::<16>::new();
+            write!(&mut line, "{:>5.1}{} max {:.0}", value, kind.unit(), max).ok();
+            display_line(lcd, 1, line.as_str()).await;
+
+            match wait_for_adjust(up, down, enter, &mut held_since).await {
+                Adjust::Steps(steps) => {
+                    LIMITS
+                        .lock()
+                        .await
+                        .set(kind, value + steps as f32 * kind.step());
+                }
+                Adjust::Enter => break,
+                Adjust::Fault => return fault_screen(lcd, enter, Screen::Engineering).await,
+            }
+        }
+    }
+
+    request_save();
+
+    // Bench mode is only offered here, never from the operator's menus.
+    lcd.clear().await;
+    display_line(lcd, 0, "Bench freq mode?").await;
+    display_line(lcd, 1, "Ent:yes Up/Dn:no").await;
+    match wait_for_press(up, down, enter).await {
+        WaitOutcome::Button(ButtonPressed::Enter) => Screen::FrequencyConfig,
+        WaitOutcome::Button(_) => home_screen().await,
+        WaitOutcome::Fault => fault_screen(lcd, enter, Screen::ModeSelect).await,
+    }
+}
+
+/// Bench frequency for `ControlMode::ManualFrequency`, in `MANUAL_FREQ_STEP_HZ` steps.
+async fn frequency_config_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    display_line(lcd, 0, "Bench frequency").await;
+    let mut held_since = None;
+
+    loop {
+        let value = CONTROL_SETTINGS.lock().await.manual_freq_hz;
+
+        let mut line = String::<16>::new();
+        write!(&mut line, "Freq: {:>5.0} Hz", value).ok();
+        display_line(lcd, 1, line.as_str()).await;
+
+        match wait_for_adjust(up, down, enter, &mut held_since).await {
+            Adjust::Steps(steps) => {
+                let next = (value + steps as f32 * MANUAL_FREQ_STEP_HZ)
+                    .clamp(MIN_FREQUENCY_HZ, MAX_FREQUENCY_HZ);
+                CONTROL_SETTINGS.lock().await.manual_freq_hz = next;
+            }
+            Adjust::Enter => return Screen::FrequencyStatus,
+            Adjust::Fault => return fault_screen(lcd, enter, Screen::FrequencyConfig).await,
+        }
+    }
+}
+
+/// Bench run at a fixed frequency. Arms and runs like the manual power status screen; Up
+/// goes back to the frequency, Enter or Down leaves bench mode.
+async fn frequency_status_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    let mut lines = StatusLines::new();
+    loop {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::FrequencyStatus).await {
+            return next;
+        }
+
+        let status = CONTROL_STATUS.lock().await.clone();
+        if status.runtime_limited {
+            return Screen::Cooldown;
+        }
+        let meas = measurements();
+
+        let mut line1 = String::<16>::new();
+        write!(
+            &mut line1,
+            "f{:>5.0} I{:>3.0}A",
+            status.switching_freq_hz,
+            meas.coil_current_rms_a.clamp(0.0, 999.0)
+        )
+        .ok();
+        lines.update(lcd, 0, line1.as_str()).await;
+
+        let armed = CONTROL_SETTINGS.lock().await.armed;
+        let mut line2 = String::<16>::new();
+        let run_label = if status.run_active { "R:ON" } else { "R:OFF" };
+        if !status.run_active && show_alternate() {
+            line2.push_str(arm_prompt(armed)).ok();
+        } else {
+            write!(&mut line2, "{} P{:>4.1}kW", run_label, meas.coil_power_kw).ok();
+        }
+        lines.update(lcd, 1, line2.as_str()).await;
+
+        if enter.is_low() {
+            if !status.run_active && held_for(enter, ARM_HOLD_MS).await {
+                arm(enter).await;
+                continue;
+            }
+            wait_for_release(enter).await;
+            return Screen::ModeSelect;
+        }
+        if up.is_low() {
+            wait_for_release(up).await;
+            return Screen::FrequencyConfig;
+        }
+        if down.is_low() {
+            wait_for_release(down).await;
+            return Screen::ModeSelect;
+        }
+
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+    }
+}
+
+async fn temperature_status_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    let mut lines = StatusLines::new();
+    loop {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::TemperatureStatus).await {
+            return next;
+        }
+
+        let status = CONTROL_STATUS.lock().await.clone();
+        if status.runtime_limited {
+            return Screen::Cooldown;
+        }
+        let meas = measurements();
+        let settings = *CONTROL_SETTINGS.lock().await;
+        let unit = settings.temp_unit;
+        let target_temp = unit.from_celsius(settings.target_temp_c);
+        let idle = !status.run_active && !status.target_reached;
+
+        let mut line1 = String::<16>::new();
+        write!(
+            &mut line1,
+            "Obj {:>4.0}{} T {:>4.0}{}",
+            unit.from_celsius(meas.object_temp_c),
+            unit.symbol(),
+            target_temp,
+            unit.symbol()
+        )
+        .ok();
+        lines.update(lcd, 0, line1.as_str()).await;
+
+        if status.part_removed {
+            lines.update(lcd, 1, "Part removed?").await;
+        } else if let Some(left_s) = status.soak_remaining_s {
+            let mut line2 = String::<16>::new();
+            write!(&mut line2, "Soak {}:{:02} left", left_s / 60, left_s % 60).ok();
+            lines.update(lcd, 1, line2.as_str()).await;
+        } else if status.target_reached && settings.hold_at_target {
+            lines.update(lcd, 1, "Holding Ent=Cool").await;
+        } else if status.target_reached {
+            lines.update(lcd, 1, "Press Enter Cool").await;
+        } else if idle && show_alternate() {
+            lines.update(lcd, 1, arm_prompt(settings.armed)).await;
+        } else if show_alternate() {
+            let energy_kwh = ENERGY_STATS.lock().await.delivered_energy_kwh;
+            let mut line2 = String::<16>::new();
+            write!(&mut line2, "Energy {:>5.3}kWh", energy_kwh).ok();
+            lines.update(lcd, 1, line2.as_str()).await;
+        } else if status.power_derate < 1.0 {
+            let mut line2 = String::<16>::new();
+            write!(
+                &mut line2,
+                "Derate{:>3.0}% M{:>3.0}",
+                status.power_derate * 100.0,
+                unit.from_celsius(meas.module_temp_c)
+            )
+            .ok();
+            lines.update(lcd, 1, line2.as_str()).await;
+        } else {
+            let mut line2 = String::<16>::new();
+            write!(
+                &mut line2,
+                "Coil{:>3.0}{} Mod{:>3.0}",
+                unit.from_celsius(meas.coil_temp_c),
+                unit.symbol(),
+                unit.from_celsius(meas.module_temp_c)
+            )
+            .ok();
+            lines.update(lcd, 1, line2.as_str()).await;
+        }
+
+        if enter.is_low() {
+            if idle && held_for(enter, ARM_HOLD_MS).await {
+                arm(enter).await;
+                continue;
+            }
+            wait_for_release(enter).await;
+
+            if status.target_reached {
+                return Screen::Cooldown;
+            } else {
+                return Screen::TemperatureConfig;
+            }
+        }
+        if up.is_low() {
+            wait_for_release(up).await;
+            return Screen::TemperatureConfig;
+        }
+        if down.is_low() {
+            let big = held_for(down, BIG_READOUT_HOLD_MS).await;
+            wait_for_release(down).await;
+            return if big {
+                Screen::BigReadout
+            } else {
+                Screen::ModeSelect
+            };
+        }
+
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+    }
+}
+
+/// Coil power (manual power) or object temperature (temperature mode) in double-height
+/// digits, readable from across the bench. Any button goes back to the mode's status screen.
+async fn big_readout_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+    mode: ControlMode,
+) -> Screen {
+    let status_screen = if mode == ControlMode::Temperature {
+        Screen::TemperatureStatus
+    } else {
+        Screen::ManualStatus
+    };
+    lcd.clear().await;
+    load_big_digits(lcd).await;
+    // Value (in tenths) and run state as last drawn.
+    let mut shown: Option<(i32, bool)> = None;
+    let mut next_redraw = Instant::now();
+
+    loop {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::BigReadout).await {
+            return next;
+        }
+
+        let status = CONTROL_STATUS.lock().await.clone();
+        if status.runtime_limited {
+            return Screen::Cooldown;
+        }
+        let meas = measurements();
+        let unit = CONTROL_SETTINGS.lock().await.temp_unit;
+        let (value, decimals, label) = if mode == ControlMode::Temperature {
+            let temp = unit.from_celsius(meas.object_temp_c).clamp(0.0, 999.0);
+            (roundf(temp), 0, unit.symbol())
+        } else {
+            (
+                roundf(meas.coil_power_kw.clamp(0.0, 99.9) * 10.0) / 10.0,
+                1,
+                "kW",
+            )
+        };
+        let drawn = (roundf(value * 10.0) as i32, status.run_active);
+
+        // Same periodic full rewrite as `StatusLines`, for a glitched LCD.
+        let now = Instant::now();
+        if shown != Some(drawn) || now >= next_redraw {
+            let end = draw_big_number(lcd, value, decimals, 0).await;
+            for row in 0..2 {
+                lcd.set_cursor(end, row).await;
+                for _ in end..BIG_LABEL_COL {
+                    lcd.message(" ").await;
+                }
+            }
+            let mut unit_label = String::<4>::new();
+            write!(&mut unit_label, "{:<4}", label).ok();
+            lcd.set_cursor(BIG_LABEL_COL, 0).await;
+            lcd.message(unit_label.as_str()).await;
+            lcd.set_cursor(BIG_LABEL_COL, 1).await;
+            lcd.message(if status.run_active { "ON  " } else { "OFF " })
+                .await;
+            shown = Some(drawn);
+            next_redraw = now + Duration::from_millis(STATUS_FORCE_REDRAW_MS);
+        }
+
+        for button in [&mut *up, &mut *down, &mut *enter] {
+            if button.is_low() {
+                wait_for_release(button).await;
+                return status_screen;
+            }
+        }
+
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+    }
+}
+
+async fn cooldown_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    let mut lines = StatusLines::new();
+
+    loop {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::Cooldown).await {
+            return next;
+        }
+
+        let status = CONTROL_STATUS.lock().await.clone();
+        if status.cooldown_complete {
+            set_mode(ControlMode::Idle).await;
+            lcd.clear().await;
+            display_line(lcd, 0, "Cooldown done").await;
+            Timer::after(Duration::from_millis(800)).await;
+            return Screen::ModeSelect;
+        }
+
+        let (line1, line2) = if status.runtime_limited {
+            ("Max runtime", "cooling Ent=exit")
+        } else {
+            ("Cooling active", "Enter to exit")
+        };
+        lines.update(lcd, 0, line1).await;
+        let line2 = if show_alternate() {
+            "Down: run stats"
+        } else {
+            line2
+        };
+        lines.update(lcd, 1, line2).await;
+
+        if down.is_low() {
+            wait_for_release(down).await;
+            return Screen::RunStats;
+        }
+        if enter.is_low() || up.is_low() {
+            wait_for_release(enter).await;
+            wait_for_release(up).await;
+            set_mode(ControlMode::Idle).await;
+            return Screen::ModeSelect;
+        }
+
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+    }
+}
+
+/// Peaks of the run that just ended, two pages flipped with Up/Down, for the operator to log.
+/// Cooling carries on meanwhile; Enter goes back to the cooldown screen. If cooldown completes
+/// while the stats are up, the coolant stops but the stats stay, and Enter goes to mode select.
+async fn run_stats_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    const PAGES: usize = 2;
+    lcd.clear().await;
+    let mut lines = StatusLines::new();
+    let mut page = 0;
+    let mut cooled = false;
+
+    loop {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::RunStats).await {
+            return next;
+        }
+        if !cooled && CONTROL_STATUS.lock().await.cooldown_complete {
+            set_mode(ControlMode::Idle).await;
+            cooled = true;
+        }
+
+        let stats = *RUN_STATS.lock().await;
+        let unit = CONTROL_SETTINGS.lock().await.temp_unit;
+        let mut line1 = String::<16>::new();
+        let mut line2 = String::<16>::new();
+        if page == 0 {
+            write!(&mut line1, "Peak I {:>6.1}A", stats.peak_current_a).ok();
+            write!(&mut line2, "Peak P {:>5.2}kW", stats.peak_power_kw).ok();
+        } else {
+            write!(
+                &mut line1,
+                "Peak mod {:>4.0}{}",
+                unit.from_celsius(stats.peak_module_temp_c),
+                unit.symbol()
+            )
+            .ok();
+            match stats.time_to_target {
+                Some(elapsed) => write!(&mut line2, "To target {:>4}s", elapsed.as_secs()).ok(),
+                None => write!(&mut line2, "To target   --").ok(),
+            };
+        }
+        lines.update(lcd, 0, line1.as_str()).await;
+        lines.update(lcd, 1, line2.as_str()).await;
+
+        if enter.is_low() {
+            wait_for_release(enter).await;
+            return if cooled {
+                Screen::ModeSelect
+            } else {
+                Screen::Cooldown
+            };
+        }
+        if up.is_low() {
+            wait_for_release(up).await;
+            page = (page + PAGES - 1) % PAGES;
+        }
+        if down.is_low() {
+            wait_for_release(down).await;
+            page = (page + 1) % PAGES;
+        }
+
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+    }
+}
+
+/// Control-loop counters. Enter starts a measurement capture to the log; Up or Down leaves.
+async fn diagnostics_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    const COUNT_MAX: u32 = 999_999;
+
+    lcd.clear().await;
+    let mut lines = StatusLines::new();
+    loop {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::Diagnostics).await {
+            return next;
+        }
+
+        let diag = *CONTROL_DIAGNOSTICS.lock().await;
+
+        let mut line1 = String::<16>::new();
+        write!(
+            &mut line1,
+            "F<{:>6} F>{:>5}",
+            diag.freq_min_clamps.min(COUNT_MAX),
+            diag.freq_max_clamps.min(99_999)
+        )
+        .ok();
+        lines.update(lcd, 0, line1.as_str()).await;
+
+        let mut line2 = String::<16>::new();
+        if capture_active() {
+            line2.push_str("Capturing...").ok();
+        } else {
+            write!(
+                &mut line2,
+                "Ti{:>6} P{:>6}",
+                diag.temp_integrator_saturations.min(COUNT_MAX),
+                diag.power_limit_hits.min(COUNT_MAX)
+            )
+            .ok();
+        }
+        lines.update(lcd, 1, line2.as_str()).await;
+
+        if enter.is_low() {
+            wait_for_release(enter).await;
+            start_capture();
+        }
+        if up.is_low() || down.is_low() {
+            wait_for_release(up).await;
+            wait_for_release(down).await;
+            return Screen::ModeSelect;
+        }
+
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+    }
+}
+
+/// Every measurement and the e-stop and gate-driver lines as read, in °C whatever the display
+/// unit, for checking the wiring while commissioning. Up/Down page through them, Enter leaves.
+/// Runs in Idle, so nothing is driven. A fault does not take over the screen until Enter
+/// leaves it.
+async fn raw_sensors_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    const PAGES: usize = 4;
+
+    lcd.clear().await;
+    let mut lines = StatusLines::new();
+    let mut page = 0;
+    loop {
+        let meas = measurements();
+        let mut line1 = String::<16>::new();
+        let mut line2 = String::<16>::new();
+        match page {
+            0 => {
+                write!(&mut line1, "Vdc {:>6.1} V", meas.dc_voltage_v).ok();
+                write!(
+                    &mut line2,
+                    "I{:>5.1}A P{:>5.2}kW",
+                    meas.coil_current_rms_a, meas.coil_power_kw
+                )
+                .ok();
+            }
+            1 => {
+                write!(
+                    &mut line1,
+                    "Coil{:>4.0} Mod{:>4.0}",
+                    meas.coil_temp_c, meas.module_temp_c
+                )
+                .ok();
+                write!(
+                    &mut line2,
+                    "PCB{:>5.0} Obj{:>4.0}",
+                    meas.pcb_temp_c, meas.object_temp_c
+                )
+                .ok();
+            }
+            2 => {
+                write!(
+                    &mut line1,
+                    "Amb{:>5.1} PF{:>4.2}",
+                    meas.ambient_temp_c, meas.power_factor
+                )
+                .ok();
+                write!(
+                    &mut line2,
+                    "f{:>6.0}Hz z{:.3}",
+                    meas.measured_freq_hz, meas.current_zero_v
+                )
+                .ok();
+            }
+            _ => {
+                write!(
+                    &mut line1,
+                    "ILK:{} GF:{}",
+                    if interlock_open() { "open" } else { "ok" },
+                    if gate_fault_active() { "flt" } else { "ok" }
+                )
+                .ok();
+                write!(
+                    &mut line2,
+                    "RDY:{} OC:{}",
+                    if gate_driver_ready() { "ok" } else { "no" },
+                    if overcurrent_tripped() { "trip" } else { "ok" }
+                )
+                .ok();
+            }
+        }
+        lines.update(lcd, 0, line1.as_str()).await;
+        lines.update(lcd, 1, line2.as_str()).await;
+
+        if up.is_low() {
+            wait_for_release(up).await;
+            page = (page + PAGES - 1) % PAGES;
+        }
+        if down.is_low() {
+            wait_for_release(down).await;
+            page = (page + 1) % PAGES;
+        }
+        if enter.is_low() {
+            wait_for_release(enter).await;
+            return Screen::ModeSelect;
+        }
+
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+    }
+}
+
+/// Firmware version, then the build date, uptime and heating cycle count in turn. Any button
+/// leaves.
+async fn about_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+) -> Screen {
+    lcd.clear().await;
+    let mut lines = StatusLines::new();
+    let mut version = String::<16>::new();
+    write!(&mut version, "FW v{}", FIRMWARE_VERSION).ok();
+    loop {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::ModeSelect).await {
+            return next;
+        }
+
+        lines.update(lcd, 0, version.as_str()).await;
+        let mut line2 = String::<16>::new();
+        match Instant::now().as_millis() / STATUS_ALTERNATE_MS % 3 {
+            0 => {
+                write!(&mut line2, "Built {}", BUILD_DATE).ok();
             }
-            WaitOutcome::Button(ButtonPressed::Enter) => {
-                return Screen::ManualStatus;
+            1 => {
+                let secs = Instant::now().as_secs();
+                write!(
+                    &mut line2,
+                    "Up {}d {:02}:{:02}:{:02}",
+                    secs / 86_400,
+                    secs / 3_600 % 24,
+                    secs / 60 % 60,
+                    secs % 60
+                )
+                .ok();
             }
-            WaitOutcome::Fault => {
-                return fault_screen(lcd, Screen::ManualConfig).await;
+            _ => {
+                let cycles = USAGE_STATS.lock().await.heating_cycles;
+                write!(&mut line2, "Cycles {}", cycles).ok();
             }
         }
+        lines.update(lcd, 1, line2.as_str()).await;
+
+        if enter.is_low() || up.is_low() || down.is_low() {
+            wait_for_release(enter).await;
+            wait_for_release(up).await;
+            wait_for_release(down).await;
+            return Screen::ModeSelect;
+        }
+
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
     }
 }
 
-async fn manual_status_screen(
-    lcd: &mut Lcd<'static>,
-    up: &mut Input<'static>,
-    down: &mut Input<'static>,
-    enter: &mut Input<'static>,
+/// Starts the resonance sweep like a heating run: hold Enter to arm, then press Run. Shows the
+/// sweep while it runs, then the frequency and current it settled on. Any other press leaves;
+/// leaving mid-sweep abandons it.
+async fn auto_tune_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
 ) -> Screen {
     lcd.clear().await;
+    let mut lines = StatusLines::new();
     loop {
-        if let Some(next) = interrupt_for_fault(lcd, Screen::ManualStatus).await {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::ModeSelect).await {
             return next;
         }
 
-        let status = CONTROL_STATUS.lock().await.clone();
-        let meas = MEASUREMENTS.lock().await.clone();
-        let v_display = meas.dc_voltage_v.clamp(0.0, 999.0);
-        let i_display = meas.coil_current_rms_a.clamp(0.0, 999.0);
+        let status = *CONTROL_STATUS.lock().await;
+        // Until the control loop has picked up the mode, `tune` still holds the last result.
+        let tune = if status.mode == ControlMode::AutoTune {
+            status.tune
+        } else {
+            TuneState::Idle
+        };
 
         let mut line1 = String::<16>::new();
-        write!(
-            &mut line1,
-            "P {:>4.1}k T {:>4.1}k",
-            meas.coil_power_kw, status.power_setpoint_kw
-        )
-        .ok();
-        display_line(lcd, 0, line1.as_str()).await;
-
         let mut line2 = String::<16>::new();
-        write!(
-            &mut line2,
-            "{} V{:>3.0} I{:>3.0}",
-            if status.run_active { "R:ON" } else { "R:OFF" },
-            v_display,
-            i_display
-        )
-        .ok();
-        display_line(lcd, 1, line2.as_str()).await;
+        match tune {
+            TuneState::Sweeping { freq_hz } => {
+                write!(&mut line1, "Tuning {:>5.0}Hz", freq_hz).ok();
+                write!(&mut line2, "I {:>3.0}A", measurements().coil_current_rms_a).ok();
+            }
+            TuneState::Done {
+                resonant_freq_hz,
+                peak_current_a,
+            } => {
+                write!(&mut line1, "Res {:>5.0}Hz", resonant_freq_hz).ok();
+                write!(&mut line2, "I {:>3.0}A    Ent>", peak_current_a).ok();
+            }
+            TuneState::Aborted => {
+                line1.push_str("Tune aborted").ok();
+                line2.push_str("Ent to exit").ok();
+            }
+            TuneState::Idle => {
+                line1.push_str("Auto-tune").ok();
+                line2
+                    .push_str(arm_prompt(CONTROL_SETTINGS.lock().await.armed))
+                    .ok();
+            }
+        }
+        lines.update(lcd, 0, line1.as_str()).await;
+        lines.update(lcd, 1, line2.as_str()).await;
 
         if enter.is_low() {
+            if tune == TuneState::Idle && held_for(enter, ARM_HOLD_MS).await {
+                arm(enter).await;
+                continue;
+            }
             wait_for_release(enter).await;
+            set_mode(ControlMode::Idle).await;
             return Screen::ModeSelect;
         }
-        if up.is_low() {
+        if up.is_low() || down.is_low() {
             wait_for_release(up).await;
-            return Screen::ManualConfig;
-        }
-        if down.is_low() {
             wait_for_release(down).await;
+            set_mode(ControlMode::Idle).await;
             return Screen::ModeSelect;
         }
 
@@ -230,179 +1852,372 @@ async fn manual_status_screen(
     }
 }
 
-async fn temperature_config_screen(
-    lcd: &mut Lcd<'static>,
-    up: &mut Input<'static>,
-    down: &mut Input<'static>,
-    enter: &mut Input<'static>,
+/// Pages through recorded fault transitions, newest first.
+async fn fault_history_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
 ) -> Screen {
-    lcd.clear().await;
-    display_line(lcd, 0, "Target temp").await;
+    let history = fault_history().await;
+    let mut index = 0usize;
 
+    lcd.clear().await;
     loop {
-        let value = {
-            let settings = CONTROL_SETTINGS.lock().await;
-            settings.target_temp_c
-        };
+        match history.get(index) {
+            Some(record) => {
+                let mut line1 = String::<16>::new();
+                let label = if record.code == FaultCode::None {
+                    "Cleared"
+                } else {
+                    record.code.lcd_label()
+                };
+                write!(&mut line1, "{:>2} {}", index + 1, label).ok();
+                display_line(lcd, 0, line1.as_str()).await;
 
-        let mut line = String::<16>::new();
-        write!(&mut line, "Target: {:>4.0}C", value).ok();
-        display_line(lcd, 1, line.as_str()).await;
+                let age_s = Instant::now()
+                    .saturating_duration_since(record.at)
+                    .as_secs();
+                let mut line2 = String::<16>::new();
+                write!(&mut line2, "-{}s", age_s.min(999_999)).ok();
+                if record.value != 0.0 {
+                    write!(&mut line2, " {:.1}", record.value).ok();
+                }
+                display_line(lcd, 1, line2.as_str()).await;
+            }
+            None => {
+                display_line(lcd, 0, "No faults logged").await;
+                display_line(lcd, 1, "").await;
+            }
+        }
 
         match wait_for_press(up, down, enter).await {
             WaitOutcome::Button(ButtonPressed::Up) => {
-                let next = (value + TEMP_STEP_C).clamp(TEMP_MIN_C, TEMP_MAX_C);
-                set_temperature_target(next).await;
+                index = index.saturating_sub(1);
             }
             WaitOutcome::Button(ButtonPressed::Down) => {
-                let next = (value - TEMP_STEP_C).clamp(TEMP_MIN_C, TEMP_MAX_C);
-                set_temperature_target(next).await;
-            }
-            WaitOutcome::Button(ButtonPressed::Enter) => {
-                return Screen::TemperatureStatus;
-            }
-            WaitOutcome::Fault => {
-                return fault_screen(lcd, Screen::TemperatureConfig).await;
+                if index + 1 < history.len() {
+                    index += 1;
+                }
             }
+            WaitOutcome::Button(ButtonPressed::Enter) => return Screen::ModeSelect,
+            WaitOutcome::Fault => return fault_screen(lcd, enter, Screen::FaultHistory).await,
         }
     }
 }
 
-async fn temperature_status_screen(
-    lcd: &mut Lcd<'static>,
-    up: &mut Input<'static>,
-    down: &mut Input<'static>,
-    enter: &mut Input<'static>,
+/// First-boot wizard: sensor check, current zero, PCB temperature trim, the profile to start
+/// from and limit confirmation.
+///
+/// The trim, the chosen profile and the commissioned flag are saved with the other settings.
+/// Readings and the trim stay in °C whatever the display unit is.
+async fn commissioning_screen(
+    lcd: &mut DisplayLcd,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
 ) -> Screen {
     lcd.clear().await;
+    display_line(lcd, 0, "Commissioning").await;
+    display_line(lcd, 1, "Enter to start").await;
+    loop {
+        match wait_for_press(up, down, enter).await {
+            WaitOutcome::Button(ButtonPressed::Enter) => break,
+            WaitOutcome::Button(_) => {}
+            WaitOutcome::Fault => return fault_screen(lcd, enter, Screen::Commissioning).await,
+        }
+    }
+
+    // Step 1: every sensor answers and reads something sane for a cold unit.
+    lcd.clear().await;
+    let mut lines = StatusLines::new();
     loop {
-        if let Some(next) = interrupt_for_fault(lcd, Screen::TemperatureStatus).await {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::Commissioning).await {
             return next;
         }
 
-        let status = CONTROL_STATUS.lock().await.clone();
-        let meas = MEASUREMENTS.lock().await.clone();
-        let target_temp = CONTROL_SETTINGS.lock().await.target_temp_c;
+        let meas = measurements();
+        let ambient = COMMISSION_AMBIENT_MIN_C..=COMMISSION_AMBIENT_MAX_C;
+        let sensors_ok = meas.valid
+            && !meas.coil_temp_disconnected
+            && !meas.module_temp_disconnected
+            && !meas.pcb_temp_disconnected
+            && ambient.contains(&meas.coil_temp_c)
+            && ambient.contains(&meas.module_temp_c)
+            && ambient.contains(&meas.pcb_temp_c)
+            && meas.object_temp_valid;
 
         let mut line1 = String::<16>::new();
         write!(
             &mut line1,
-            "Obj {:>4.0}C T {:>4.0}C",
-            meas.object_temp_c, target_temp
+            "C{:>3.0} M{:>3.0} O{:>3.0}",
+            meas.coil_temp_c, meas.module_temp_c, meas.object_temp_c
         )
         .ok();
-        display_line(lcd, 0, line1.as_str()).await;
-
-        if status.target_reached {
-            display_line(lcd, 1, "Press Enter Cool").await;
-        } else {
-            let mut line2 = String::<16>::new();
-            write!(
-                &mut line2,
-                "Coil{:>3.0}C Mod{:>3.0}",
-                meas.coil_temp_c, meas.module_temp_c
+        lines.update(lcd, 0, line1.as_str()).await;
+        lines
+            .update(
+                lcd,
+                1,
+                if sensors_ok {
+                    "Sensors OK Ent>"
+                } else {
+                    "Sensors: waiting"
+                },
             )
-            .ok();
-            display_line(lcd, 1, line2.as_str()).await;
-        }
+            .await;
 
-        if enter.is_low() {
+        menu_heartbeat();
+        if sensors_ok && enter.is_low() {
             wait_for_release(enter).await;
-
-            if status.target_reached {
-                return Screen::Cooldown;
-            } else {
-                return Screen::TemperatureConfig;
-            }
-        }
-        if up.is_low() {
-            wait_for_release(up).await;
-            return Screen::TemperatureConfig;
-        }
-        if down.is_low() {
-            wait_for_release(down).await;
-            return Screen::ModeSelect;
+            break;
         }
 
         Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
     }
-}
 
-async fn cooldown_screen(
-    lcd: &mut Lcd<'static>,
-    up: &mut Input<'static>,
-    down: &mut Input<'static>,
-    enter: &mut Input<'static>,
-) -> Screen {
+    // Step 2: the hall zero is tracked while the inverter is off; accept it once it is in range.
     lcd.clear().await;
-    display_line(lcd, 0, "Cooling active").await;
-    display_line(lcd, 1, "Enter to exit").await;
-
+    let mut lines = StatusLines::new();
     loop {
-        if let Some(next) = interrupt_for_fault(lcd, Screen::Cooldown).await {
+        if let Some(next) = interrupt_for_fault(lcd, enter, Screen::Commissioning).await {
             return next;
         }
 
-        if enter.is_low() || up.is_low() || down.is_low() {
+        let meas = measurements();
+        let zero_ok = !meas.current_zero_drift_fault
+            && fabsf(meas.current_zero_v - CURRENT_CENTER_V) <= COMMISSION_ZERO_TOLERANCE_V;
+        lines
+            .update(lcd, 0, zero_detail_line(meas.current_zero_v).as_str())
+            .await;
+        lines
+            .update(
+                lcd,
+                1,
+                if zero_ok {
+                    "I zero OK Ent>"
+                } else {
+                    "I zero out range"
+                },
+            )
+            .await;
+
+        menu_heartbeat();
+        if zero_ok && enter.is_low() {
             wait_for_release(enter).await;
-            wait_for_release(up).await;
-            wait_for_release(down).await;
-            set_mode(ControlMode::Idle).await;
-            return Screen::ModeSelect;
+            break;
         }
 
         Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
     }
+
+    // Step 3: one-point PCB sensor trim against a reference thermometer.
+    let offset = COMMISSIONING.lock().await.pcb_temp_offset_c;
+    let raw_pcb_c = measurements().pcb_temp_c - offset;
+    let mut reference_c = roundf(raw_pcb_c / PCB_TRIM_STEP_C) * PCB_TRIM_STEP_C;
+    lcd.clear().await;
+    loop {
+        let mut line1 = String::<16>::new();
+        write!(&mut line1, "PCB raw {:>5.1}C", raw_pcb_c).ok();
+        display_line(lcd, 0, line1.as_str()).await;
+        let mut line2 = String::<16>::new();
+        write!(&mut line2, "Ref:   {:>5.1}C", reference_c).ok();
+        display_line(lcd, 1, line2.as_str()).await;
+
+        match wait_for_press(up, down, enter).await {
+            WaitOutcome::Button(ButtonPressed::Up) => {
+                reference_c = (reference_c + PCB_TRIM_STEP_C).min(COMMISSION_AMBIENT_MAX_C);
+            }
+            WaitOutcome::Button(ButtonPressed::Down) => {
+                reference_c = (reference_c - PCB_TRIM_STEP_C).max(COMMISSION_AMBIENT_MIN_C);
+            }
+            WaitOutcome::Button(ButtonPressed::Enter) => break,
+            WaitOutcome::Fault => return fault_screen(lcd, enter, Screen::Commissioning).await,
+        }
+    }
+
+    // Step 4: the profile the unit starts from, among the filled slots.
+    let profiles = *PROFILES.lock().await;
+    let filled: Vec<usize, PROFILE_COUNT> = (0..PROFILE_COUNT)
+        .filter(|&slot| profiles.slots[slot].is_some())
+        .collect();
+    if !filled.is_empty() {
+        let mut choice = filled
+            .iter()
+            .position(|&slot| slot == profiles.selected)
+            .unwrap_or(0);
+        lcd.clear().await;
+        display_line(lcd, 0, "Start profile:").await;
+        loop {
+            let slot = filled[choice];
+            let mut line2 = String::<16>::new();
+            if let Some(profile) = profiles.slots[slot] {
+                write!(&mut line2, "{} {}", slot + 1, profile.name()).ok();
+            }
+            display_line(lcd, 1, line2.as_str()).await;
+
+            match wait_for_press(up, down, enter).await {
+                WaitOutcome::Button(ButtonPressed::Up) => {
+                    choice = (choice + filled.len() - 1) % filled.len();
+                }
+                WaitOutcome::Button(ButtonPressed::Down) => {
+                    choice = (choice + 1) % filled.len();
+                }
+                WaitOutcome::Button(ButtonPressed::Enter) => break,
+                WaitOutcome::Fault => return fault_screen(lcd, enter, Screen::Commissioning).await,
+            }
+        }
+        let mut settings = CONTROL_SETTINGS.lock().await;
+        PROFILES.lock().await.load(filled[choice], &mut settings);
+    }
+
+    // Step 5: the operator acknowledges the trip limits in effect.
+    lcd.clear().await;
+    let limits = *LIMITS.lock().await;
+    let mut line1 = String::<16>::new();
+    write!(
+        &mut line1,
+        "P{:.0}kW I{:.0}A",
+        limits.power_kw(),
+        limits.current_a()
+    )
+    .ok();
+    display_line(lcd, 0, line1.as_str()).await;
+    let mut line2 = String::<16>::new();
+    write!(
+        &mut line2,
+        "C{:.0} M{:.0} P{:.0} Ent",
+        limits.coil_temp_c(),
+        limits.module_temp_c(),
+        limits.pcb_temp_c()
+    )
+    .ok();
+    display_line(lcd, 1, line2.as_str()).await;
+    loop {
+        match wait_for_press(up, down, enter).await {
+            WaitOutcome::Button(ButtonPressed::Enter) => break,
+            WaitOutcome::Button(_) => {}
+            WaitOutcome::Fault => return fault_screen(lcd, enter, Screen::Commissioning).await,
+        }
+    }
+
+    {
+        let mut commissioning = COMMISSIONING.lock().await;
+        commissioning.pcb_temp_offset_c = reference_c - raw_pcb_c;
+        commissioning.commissioned = true;
+    }
+    request_save();
+    lcd.clear().await;
+    display_line(lcd, 0, "Commissioned").await;
+    Timer::after(Duration::from_millis(800)).await;
+    Screen::ModeSelect
 }
 
-async fn fault_screen(lcd: &mut Lcd<'static>, resume: Screen) -> Screen {
+async fn fault_screen(lcd: &mut DisplayLcd, enter: &mut MenuButton, resume: Screen) -> Screen {
     let mut last_code = FaultCode::None;
-    let mut last_header = String::<16>::new();
     let mut last_detail = String::<16>::new();
+    let mut enter_held_since: Option<Instant> = None;
+    let mut fault_rx = fault_watcher();
 
     loop {
-        let code = current_fault().await;
+        let fault = *FAULT_STATE.lock().await;
+        let code = fault.code;
         if code == FaultCode::None {
+            wait_for_release(enter).await;
             lcd.clear().await;
             display_line(lcd, 0, "Fault cleared").await;
             Timer::after(Duration::from_millis(400)).await;
             return resume;
         }
 
-        let meas = MEASUREMENTS.lock().await.clone();
-        let header = fault_header_line(code);
-        let detail = fault_detail_line(code, &meas);
+        menu_heartbeat();
+        if fault.latched && enter.is_low() {
+            let since = *enter_held_since.get_or_insert_with(Instant::now);
+            if Instant::now().saturating_duration_since(since)
+                >= Duration::from_millis(FAULT_RESET_HOLD_MS)
+            {
+                clear_fault().await;
+                enter_held_since = None;
+                continue;
+            }
+        } else {
+            enter_held_since = None;
+        }
+
+        let meas = measurements();
+        let unit = CONTROL_SETTINGS.lock().await.temp_unit;
+        let limits = *LIMITS.lock().await;
+        // Latched faults alternate the detail with how to reset them.
+        let show_reset_hint = fault.latched && (Instant::now().as_millis() / 1_500) % 2 == 1;
+        let detail = if show_reset_hint {
+            fit_to_line("Hold Ent=reset")
+        } else {
+            fault_detail_line(code, &meas, &limits, unit)
+        };
 
         if code != last_code {
             lcd.clear().await;
             last_code = code;
-            last_header.clear();
             last_detail.clear();
         }
 
-        if header != last_header {
-            display_line(lcd, 0, header.as_str()).await;
-            last_header = header;
-        }
+        // The full description, scrolled when it does not fit; the LCD keeps the position.
+        lcd.scroll_line(0, code.message()).await;
 
         if detail != last_detail {
-            display_line(lcd, 1, detail.as_str()).await;
+            lcd.update_line(1, last_detail.as_str(), detail.as_str())
+                .await;
             last_detail = detail;
         }
 
-        Timer::after(Duration::from_millis(200)).await;
+        // A new or cleared fault redraws at once rather than on the next refresh.
+        select(Timer::after(Duration::from_millis(200)), fault_rx.changed()).await;
     }
 }
 
 async fn interrupt_for_fault(
-    lcd: &mut Lcd<'static>,
+    lcd: &mut DisplayLcd,
+    enter: &mut MenuButton,
     resume: Screen,
 ) -> Option<Screen> {
-    if current_fault().await == FaultCode::None {
+    if current_fault() == FaultCode::None {
         None
     } else {
-        Some(fault_screen(lcd, resume).await)
+        Some(fault_screen(lcd, enter, resume).await)
+    }
+}
+
+fn arm_prompt(armed: bool) -> &'static str {
+    if armed {
+        "ARMED: press Run"
+    } else {
+        "Hold Ent to ARM"
+    }
+}
+
+/// Arms the run button, acknowledged with a chirp while Enter is still held.
+async fn arm(enter: &mut MenuButton) {
+    CONTROL_SETTINGS.lock().await.armed = true;
+    chirp();
+    wait_for_release(enter).await;
+}
+
+/// Waits while `button` stays down, up to `hold_ms`. Returns true if it was still down at the
+/// end; the button may still be held then.
+async fn held_for(button: &mut MenuButton, hold_ms: u64) -> bool {
+    let pressed = Instant::now();
+    while button.is_low() {
+        if Instant::now().saturating_duration_since(pressed) >= Duration::from_millis(hold_ms) {
+            return true;
+        }
+        Timer::after(Duration::from_millis(10)).await;
     }
+    false
+}
+
+/// True for every other `STATUS_ALTERNATE_MS` slot.
+fn show_alternate() -> bool {
+    (Instant::now().as_millis() / STATUS_ALTERNATE_MS) % 2 == 1
 }
 
 async fn set_manual_power(value: f32) {
@@ -415,17 +2230,74 @@ async fn set_temperature_target(value: f32) {
     settings.target_temp_c = value;
 }
 
+async fn set_hold_at_target(hold: bool) {
+    let mut settings = CONTROL_SETTINGS.lock().await;
+    settings.hold_at_target = hold;
+}
+
+async fn set_temp_unit(unit: TempUnit) {
+    let mut settings = CONTROL_SETTINGS.lock().await;
+    settings.temp_unit = unit;
+}
+
 async fn set_mode(mode: ControlMode) {
     let mut settings = CONTROL_SETTINGS.lock().await;
     settings.mode = mode;
+    if matches!(mode, ControlMode::ManualPower | ControlMode::Temperature) {
+        settings.last_run_mode = mode;
+    }
+}
+
+async fn wait_for_heating_mode() -> ControlMode {
+    loop {
+        let mode = CONTROL_SETTINGS.lock().await.mode;
+        if matches!(mode, ControlMode::ManualPower | ControlMode::Temperature) {
+            return mode;
+        }
+        Timer::after(Duration::from_millis(STATUS_REFRESH_MS)).await;
+    }
 }
 
-async fn display_line(lcd: &mut Lcd<'static>, row: u8, text: &str) {
+async fn display_line(lcd: &mut DisplayLcd, row: u8, text: &str) {
     let formatted = fit_to_line(text);
     lcd.set_cursor(0, row).await;
     lcd.message(formatted.as_str()).await;
 }
 
+/// Last text written to each row of a status screen, so a refresh only touches the LCD when
+/// the rendered value actually changed (same idea as the detail diffing in `fault_screen`),
+/// and then only rewrites the characters that differ.
+struct StatusLines {
+    rows: [String<16>; 2],
+    next_redraw: Instant,
+}
+
+impl StatusLines {
+    fn new() -> Self {
+        Self {
+            rows: [String::new(), String::new()],
+            next_redraw: Instant::now() + Duration::from_millis(STATUS_FORCE_REDRAW_MS),
+        }
+    }
+
+    async fn update(&mut self, lcd: &mut DisplayLcd, row: u8, text: &str) {
+        let now = Instant::now();
+        if now >= self.next_redraw {
+            for cached in self.rows.iter_mut() {
+                cached.clear();
+            }
+            self.next_redraw = now + Duration::from_millis(STATUS_FORCE_REDRAW_MS);
+        }
+
+        let line = fit_to_line(text);
+        let cached = &mut self.rows[row as usize];
+        if *cached != line {
+            lcd.update_line(row, cached.as_str(), line.as_str()).await;
+            *cached = line;
+        }
+    }
+}
+
 fn fit_to_line(text: &str) -> String<16> {
     let mut buf = String::<16>::new();
     for ch in text.chars().take(16) {
@@ -437,46 +2309,108 @@ fn fit_to_line(text: &str) -> String<16> {
     buf
 }
 
-fn fault_header_line(code: FaultCode) -> String<16> {
-    fit_to_line(code.lcd_label())
-}
-
-fn fault_detail_line(code: FaultCode, meas: &Measurements) -> String<16> {
+fn fault_detail_line(
+    code: FaultCode,
+    meas: &Measurements,
+    limits: &Limits,
+    unit: TempUnit,
+) -> String<16> {
     match code {
-        FaultCode::PowerLimit => power_detail_line(meas.coil_power_kw),
-        FaultCode::CoilOverTemp => temp_detail_line("Coil ", meas.coil_temp_c, COIL_TEMP_LIMIT_C),
+        FaultCode::PowerLimit => power_detail_line(meas.coil_power_kw, limits.power_kw()),
+        FaultCode::CoilOverTemp => {
+            temp_detail_line("Coil ", meas.coil_temp_c, limits.coil_temp_c(), unit)
+        }
         FaultCode::ModuleOverTemp => {
-            temp_detail_line("Mod ", meas.module_temp_c, MODULE_TEMP_LIMIT_C)
+            temp_detail_line("Mod ", meas.module_temp_c, limits.module_temp_c(), unit)
+        }
+        FaultCode::PcbOverTemp => {
+            temp_detail_line("PCB ", meas.pcb_temp_c, limits.pcb_temp_c(), unit)
         }
-        FaultCode::PcbOverTemp => temp_detail_line("PCB ", meas.pcb_temp_c, PCB_TEMP_LIMIT_C),
-        FaultCode::CurrentLimit => current_detail_line(meas.coil_current_rms_a),
+        FaultCode::CurrentLimit => current_detail_line(meas.coil_current_rms_a, limits.current_a()),
         FaultCode::InterlockOpen => fit_to_line("Check E-STOP"),
         FaultCode::GateDriverFault => fit_to_line("Gate drv fault"),
         FaultCode::GateDriverNotReady => fit_to_line("Gate drv wait"),
-        FaultCode::SensorFault => fit_to_line("Coil NTC open"),
+        FaultCode::SensorFault if meas.coil_temp_disconnected => fit_to_line("Coil NTC open"),
+        FaultCode::SensorFault if meas.pcb_temp_disconnected => fit_to_line("PCB NTC fault"),
+        FaultCode::SensorFault if !meas.object_temp_valid => fit_to_line("IR sensor lost"),
+        FaultCode::SensorFault if !meas.ads_healthy => fit_to_line("ADS7828 lost"),
+        FaultCode::SensorFault if stale_source().is_some() => stale_detail_line(),
+        FaultCode::SensorFault => fit_to_line("Module NTC fault"),
+        FaultCode::CurrentSensorFault => zero_detail_line(meas.current_zero_v),
+        FaultCode::PwmFault => freq_detail_line(meas.measured_freq_hz),
+        FaultCode::NoCoolantFlow => fit_to_line("Check pump/flow"),
+        FaultCode::NoHeatingDetected => no_heating_detail_line(meas.object_temp_c, unit),
+        FaultCode::DcUndervoltage => bus_detail_line(meas.dc_voltage_v),
+        FaultCode::AdcSaturation => fit_to_line("Check V/I sense"),
+        FaultCode::MenuUnresponsive => fit_to_line("Restart if stuck"),
         FaultCode::None => fit_to_line("All clear"),
     }
 }
 
-fn temp_detail_line(label: &str, value: f32, limit: f32) -> String<16> {
+fn temp_detail_line(label: &str, value_c: f32, limit_c: f32, unit: TempUnit) -> String<16> {
+    let mut buf = String::<16>::new();
+    let _ = write!(
+        buf,
+        "{}{:>3.0}>{:.0}{}",
+        label,
+        unit.from_celsius(value_c),
+        unit.from_celsius(limit_c),
+        unit.symbol()
+    );
+    fit_to_line(buf.as_str())
+}
+
+fn power_detail_line(power_kw: f32, limit_kw: f32) -> String<16> {
+    let mut buf = String::<16>::new();
+    let _ = write!(buf, "P {:>4.1}>{:.0}kW", power_kw, limit_kw);
+    fit_to_line(buf.as_str())
+}
+
+fn current_detail_line(current_a: f32, limit_a: f32) -> String<16> {
+    let mut buf = String::<16>::new();
+    let _ = write!(buf, "I {:>3.0}>{:.0}A", current_a, limit_a);
+    fit_to_line(buf.as_str())
+}
+
+fn zero_detail_line(zero_v: f32) -> String<16> {
+    let mut buf = String::<16>::new();
+    let _ = write!(buf, "Zero {:.3}V", zero_v);
+    fit_to_line(buf.as_str())
+}
+
+fn stale_detail_line() -> String<16> {
+    let mut buf = String::<16>::new();
+    if let Some(source) = stale_source() {
+        let _ = write!(buf, "{} stale", source.label());
+    }
+    fit_to_line(buf.as_str())
+}
+
+fn bus_detail_line(bus_v: f32) -> String<16> {
     let mut buf = String::<16>::new();
-    let _ = write!(buf, "{}{:>3.0}>{:.0}C", label, value, limit);
+    let _ = write!(buf, "Bus {:>3.0}<{:.0}V", bus_v, DC_UNDERVOLTAGE_V);
     fit_to_line(buf.as_str())
 }
 
-fn power_detail_line(power_kw: f32) -> String<16> {
+fn no_heating_detail_line(object_c: f32, unit: TempUnit) -> String<16> {
     let mut buf = String::<16>::new();
-    let _ = write!(buf, "P {:>4.1}>{:.0}kW", power_kw, POWER_LIMIT_KW);
+    let _ = write!(
+        buf,
+        "Obj {:>3.0}{} flat",
+        unit.from_celsius(object_c),
+        unit.symbol()
+    );
     fit_to_line(buf.as_str())
 }
 
-fn current_detail_line(current_a: f32) -> String<16> {
+fn freq_detail_line(measured_hz: f32) -> String<16> {
     let mut buf = String::<16>::new();
-    let _ = write!(buf, "I {:>3.0}>{:.0}A", current_a, CURRENT_LIMIT_A);
+    let _ = write!(buf, "Meas {:>5.0}Hz", measured_hz);
     fit_to_line(buf.as_str())
 }
 
-async fn wait_for_release(button: &mut Input<'static>) {
+/// Returns once the button is released, or after `BUTTON_STUCK_MS` if it is stuck down.
+async fn wait_for_release(button: &mut MenuButton) {
     while button.is_low() {
         Timer::after(Duration::from_millis(10)).await;
     }
@@ -495,12 +2429,12 @@ enum WaitOutcome {
 }
 
 async fn wait_for_press(
-    up: &mut Input<'static>,
-    down: &mut Input<'static>,
-    enter: &mut Input<'static>,
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
 ) -> WaitOutcome {
     loop {
-        if current_fault().await != FaultCode::None {
+        if current_fault() != FaultCode::None {
             return WaitOutcome::Fault;
         }
 
@@ -521,7 +2455,115 @@ async fn wait_for_press(
     }
 }
 
-async fn debounce_and_release(button: &mut Input<'static>) {
+/// [`wait_for_press`] for the start screen, where Enter held for `ABOUT_HOLD_MS` returns `None`
+/// (for the About screen) instead of a press.
+async fn wait_for_press_or_enter_hold(
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+    arm_on_hold: bool,
+) -> Option<WaitOutcome> {
+    loop {
+        if current_fault() != FaultCode::None || up.is_low() || down.is_low() {
+            return Some(wait_for_press(up, down, enter).await);
+        }
+        if enter.is_low() {
+            let pressed = Instant::now();
+            let mut armed = false;
+            Timer::after(Duration::from_millis(20)).await;
+            while enter.is_low() {
+                if pressed.elapsed() >= Duration::from_millis(ABOUT_HOLD_MS) {
+                    // Going on to About takes the arming back.
+                    if armed {
+                        CONTROL_SETTINGS.lock().await.armed = false;
+                    }
+                    wait_for_release(enter).await;
+                    return None;
+                }
+                if arm_on_hold && !armed && pressed.elapsed() >= Duration::from_millis(ARM_HOLD_MS)
+                {
+                    CONTROL_SETTINGS.lock().await.armed = true;
+                    chirp();
+                    armed = true;
+                }
+                Timer::after(Duration::from_millis(10)).await;
+            }
+            // A hold that armed is not also a selection.
+            if armed {
+                continue;
+            }
+            return Some(WaitOutcome::Button(ButtonPressed::Enter));
+        }
+        Timer::after(Duration::from_millis(10)).await;
+    }
+}
+
+/// Result of [`wait_for_adjust`]: Up/Down as a signed number of steps.
+enum Adjust {
+    Steps(i32),
+    Enter,
+    Fault,
+}
+
+/// Like [`wait_for_press`] for value screens. A short Up/Down press is one step on release;
+/// held past `REPEAT_DELAY_MS` it returns a step straight away and then one every
+/// `REPEAT_INTERVAL_MS`, growing along `REPEAT_ACCEL_MULTIPLIERS`, so the screen redraws
+/// between repeats. `held_since` carries the hold across calls; start it at `None`.
+async fn wait_for_adjust(
+    up: &mut MenuButton,
+    down: &mut MenuButton,
+    enter: &mut MenuButton,
+    held_since: &mut Option<Instant>,
+) -> Adjust {
+    loop {
+        if current_fault() != FaultCode::None {
+            *held_since = None;
+            return Adjust::Fault;
+        }
+
+        let direction = if up.is_low() {
+            1
+        } else if down.is_low() {
+            -1
+        } else {
+            0
+        };
+        if direction == 0 {
+            *held_since = None;
+            if enter.is_low() {
+                debounce_and_release(enter).await;
+                return Adjust::Enter;
+            }
+            Timer::after(Duration::from_millis(10)).await;
+            continue;
+        }
+        let button = if direction > 0 { &mut *up } else { &mut *down };
+
+        if let Some(since) = *held_since {
+            Timer::after(Duration::from_millis(REPEAT_INTERVAL_MS)).await;
+            if button.is_high() {
+                continue;
+            }
+            let repeating_ms = since.elapsed().as_millis().saturating_sub(REPEAT_DELAY_MS);
+            let stage =
+                ((repeating_ms / REPEAT_ACCEL_MS) as usize).min(REPEAT_ACCEL_MULTIPLIERS.len() - 1);
+            return Adjust::Steps(direction * REPEAT_ACCEL_MULTIPLIERS[stage]);
+        }
+
+        Timer::after(Duration::from_millis(20)).await;
+        let pressed_at = Instant::now();
+        while button.is_low() {
+            if pressed_at.elapsed() >= Duration::from_millis(REPEAT_DELAY_MS) {
+                *held_since = Some(pressed_at);
+                break;
+            }
+            Timer::after(Duration::from_millis(10)).await;
+        }
+        return Adjust::Steps(direction);
+    }
+}
+
+async fn debounce_and_release(button: &mut MenuButton) {
     Timer::after(Duration::from_millis(20)).await;
     wait_for_release(button).await;
 }
@@ -1,78 +1,249 @@
 use core::fmt::Debug;
 use defmt::*;
+use embassy_embedded_hal::SetConfig;
 use embassy_rp::i2c::{self, I2c};
 use embassy_time::{Duration, Timer};
+use libm::roundf;
+
+use crate::utils::{validate_i2c_address, I2cTransfer, InvalidI2cAddress};
 
 /// Default 7‑bit SMBus address
 pub const MLX90614_ADDR: u8 = 0x5A;
 
 /// RAM / EEPROM locations we care about
+const REG_TA: u8 = 0x06; // ambient (sensor die) temperature, read-only RAM
 const REG_TOBJ1: u8 = 0x07; // object temperature 1, read‑only RAM
+const REG_TOBJ2: u8 = 0x08; // object temperature 2, dual-zone parts only
 const EEPROM_EMISSIVITY: u8 = 0x04; // EEPROM emissivity
 const EEPROM_UNLOCK: u8 = 0x0F; // xCx devices only
+/// Opcode bits selecting EEPROM; without them the cell address reads or writes RAM
+const EEPROM_ACCESS: u8 = 0x20;
+
+/// Set in an object temperature word the device could not measure
+const TOBJ_ERROR_FLAG: u16 = 0x8000;
+
+/// Which thermopile to report as the object temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum ObjectChannel {
+    /// `TOBJ1`, present on every part.
+    Object1,
+    /// `TOBJ2`, only on dual-zone parts (MLX90614xBx). See [`Mlx90614::read_object_temp2`].
+    Object2,
+}
+
+/// Emissivities the part can be programmed to; 1.0 is the factory setting.
+const EMISSIVITY_MIN: f32 = 0.1;
+const EMISSIVITY_MAX: f32 = 1.0;
 
-/// Value for ε = 0.82 → round(0.82 × 65535) = 0xD1EB
-const EMISSIVITY_WORD: u16 = 0xD1EB;
+/// Why an EEPROM programming sequence failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum EepromError {
+    Bus(i2c::Error),
+    /// The requested emissivity is outside `EMISSIVITY_MIN..=EMISSIVITY_MAX`.
+    InvalidEmissivity,
+    /// A read-back word's PEC byte did not match, so its value cannot be trusted.
+    Pec {
+        cell: u8,
+    },
+    /// The cell read back something other than what was written.
+    Mismatch {
+        cell: u8,
+        expected: u16,
+        read: u16,
+    },
+}
+
+impl From<i2c::Error> for EepromError {
+    fn from(e: i2c::Error) -> Self {
+        EepromError::Bus(e)
+    }
+}
 
 /// MLX90614 object – owns the I²C peripheral
 pub struct Mlx90614<'d, T: i2c::Instance, M: i2c::Mode> {
     i2c: I2c<'d, T, M>,
+    address: u8,
 }
 
-impl<'d, T: i2c::Instance, M: i2c::Mode> Mlx90614<'d, T, M> {
+impl<'d, T: i2c::Instance, M: i2c::Mode> Mlx90614<'d, T, M>
+where
+    I2c<'d, T, M>: I2cTransfer,
+{
     /// Create a new driver from an already‑configured Embassy I²C bus
     pub fn new(i2c: I2c<'d, T, M>) -> Self {
-        Self { i2c }
+        Self {
+            i2c,
+            address: MLX90614_ADDR,
+        }
+    }
+
+    /// Driver for a sensor whose SMBus address was reprogrammed away from the default.
+    pub fn with_address(i2c: I2c<'d, T, M>, address: u8) -> Result<Self, InvalidI2cAddress> {
+        let address = validate_i2c_address(address)?;
+        Ok(Self { i2c, address })
     }
 
     // ───────────────────────────────── temperature read ─────────────────────────────────
     /// Read object temperature 1 and return it in °C
     pub async fn read_object_temp(&mut self) -> Result<f32, i2c::Error> {
         let raw: u16 = self.read_word(REG_TOBJ1).await?;
-        // data sheet: Temp[°C] = (RAW * 0.02) – 273.15
-        Ok(raw as f32 * 0.02 - 273.15)
+        Ok(raw_to_celsius(raw))
+    }
+
+    /// Read object temperature 2 (the second zone) and return it in °C.
+    ///
+    /// Only dual-zone parts have a second thermopile. On a single-zone part the read either
+    /// fails on the bus (`Err`) or returns a word with the error flag set (`Ok(None)`), so only
+    /// use this where the fitted sensor is known to be dual-zone and fall back to
+    /// [`Self::read_object_temp`] otherwise.
+    pub async fn read_object_temp2(&mut self) -> Result<Option<f32>, i2c::Error> {
+        let raw: u16 = self.read_word(REG_TOBJ2).await?;
+        if raw & TOBJ_ERROR_FLAG != 0 {
+            return Ok(None);
+        }
+        Ok(Some(raw_to_celsius(raw)))
+    }
+
+    /// Read the object temperature of `channel` in °C; `Ok(None)` as for
+    /// [`Self::read_object_temp2`].
+    pub async fn read_object(&mut self, channel: ObjectChannel) -> Result<Option<f32>, i2c::Error> {
+        match channel {
+            ObjectChannel::Object1 => self.read_object_temp().await.map(Some),
+            ObjectChannel::Object2 => self.read_object_temp2().await,
+        }
+    }
+
+    /// Read the ambient (die) temperature and return it in °C
+    pub async fn read_ambient_temp(&mut self) -> Result<f32, i2c::Error> {
+        let raw: u16 = self.read_word(REG_TA).await?;
+        Ok(raw_to_celsius(raw))
+    }
+
+    /// Read ambient and `channel`'s object temperature back to back, as
+    /// `(ambient_c, object_c)`; `Ok(None)` as for [`Self::read_object_temp2`].
+    pub async fn read_both(
+        &mut self,
+        channel: ObjectChannel,
+    ) -> Result<Option<(f32, f32)>, i2c::Error> {
+        let ambient_c = self.read_ambient_temp().await?;
+        let object_c = self.read_object(channel).await?;
+        Ok(object_c.map(|object_c| (ambient_c, object_c)))
+    }
+
+    // ──────────────────────────────────── bus recovery ─────────────────────────────────────
+    /// Re-initialise the I²C controller with `config`, abandoning whatever transfer it was
+    /// stuck in, then give the sensor a moment before the next read.
+    pub async fn recover_bus(&mut self, config: &i2c::Config) {
+        if self.i2c.set_config(config).is_err() {
+            warn!("MLX90614 I2C re-init rejected the bus config");
+        }
+        Timer::after(Duration::from_millis(50)).await;
     }
 
     // ─────────────────────────────── emissivity programming ────────────────────────────
-    /// Program ε = 0.82 permanently (writes cells 0x04 & 0x0F).
+    /// Program `emissivity` (0.1..=1.0) permanently (writes cells 0x04 & 0x0F), then read both
+    /// cells back with PEC and fail unless they hold what was written.
     /// *⚠ A power‑cycle is required for the new value to take effect.*
-    pub async fn program_emissivity_082(&mut self) -> Result<(), i2c::Error> {
+    pub async fn program_emissivity(&mut self, emissivity: f32) -> Result<(), EepromError> {
+        if !(EMISSIVITY_MIN..=EMISSIVITY_MAX).contains(&emissivity) {
+            return Err(EepromError::InvalidEmissivity);
+        }
+        // e.g. ε = 0.82 → round(0.82 × 65535) = 0xD1EB
+        let word = roundf(emissivity * 65535.0) as u16;
+
         // 1) unlock cell 0x0F (device expects the “key” command 0x60).
         self.simple_command(0x60).await?;
         Timer::after(Duration::from_millis(10)).await;
 
         // 2) erase 0x04, then write new value
-        self.write_word(EEPROM_EMISSIVITY, 0x0000).await?;
+        self.write_eeprom(EEPROM_EMISSIVITY, 0x0000).await?;
         Timer::after(Duration::from_millis(10)).await;
-        self.write_word(EEPROM_EMISSIVITY, EMISSIVITY_WORD).await?;
+        self.write_eeprom(EEPROM_EMISSIVITY, word).await?;
         Timer::after(Duration::from_millis(10)).await;
 
         // 3) erase 0x0F, then write new shadow copy
-        self.write_word(EEPROM_UNLOCK, 0x0000).await?;
+        self.write_eeprom(EEPROM_UNLOCK, 0x0000).await?;
         Timer::after(Duration::from_millis(10)).await;
-        self.write_word(EEPROM_UNLOCK, !EMISSIVITY_WORD).await?; // see App‑note
+        self.write_eeprom(EEPROM_UNLOCK, !word).await?; // see App‑note
         Timer::after(Duration::from_millis(10)).await;
 
+        // 4) read both cells back
+        self.verify_cell(EEPROM_EMISSIVITY, word).await?;
+        self.verify_cell(EEPROM_UNLOCK, !word).await
+    }
+
+    async fn verify_cell(&mut self, cell: u8, expected: u16) -> Result<(), EepromError> {
+        let read = self
+            .read_word_pec(EEPROM_ACCESS | cell)
+            .await?
+            .ok_or(EepromError::Pec { cell })?;
+        if read != expected {
+            warn!(
+                "MLX90614 EEPROM 0x{:02x} reads 0x{:04x}, wrote 0x{:04x}",
+                cell, read, expected
+            );
+            return Err(EepromError::Mismatch {
+                cell,
+                expected,
+                read,
+            });
+        }
         Ok(())
     }
 
-    // ───────────────────────────── SMBus helpers (no PEC) ──────────────────────────────
+    // ────────────────────────────────── SMBus helpers ──────────────────────────────────
+    /// Temperature reads leave the PEC byte unchecked.
     async fn read_word(&mut self, cmd: u8) -> Result<u16, i2c::Error> {
         // write command byte, then repeated‑START + read 2 bytes
         let mut buf = [0u8; 3];
-        self.i2c
-            .blocking_write_read(MLX90614_ADDR, &[cmd], &mut buf)?;
+        self.i2c.write_read(self.address, &[cmd], &mut buf).await?;
         Ok(u16::from_le_bytes([buf[0], buf[1]]))
     }
 
+    /// Like `read_word`, but `Ok(None)` unless the PEC byte after the data matches.
+    async fn read_word_pec(&mut self, cmd: u8) -> Result<Option<u16>, i2c::Error> {
+        let mut buf = [0u8; 3];
+        self.i2c.write_read(self.address, &[cmd], &mut buf).await?;
+        let addr = self.address << 1;
+        let pec = smbus_pec(&[addr, cmd, addr | 1, buf[0], buf[1]]);
+        Ok((pec == buf[2]).then(|| u16::from_le_bytes([buf[0], buf[1]])))
+    }
+
+    /// EEPROM writes carry a PEC byte; the part ignores a write without a valid one.
     async fn write_word(&mut self, cmd: u8, data: u16) -> Result<(), i2c::Error> {
-        let mut pkt = [0u8; 3];
-        pkt[0] = cmd;
-        pkt[1..].copy_from_slice(&data.to_le_bytes());
-        self.i2c.blocking_write(MLX90614_ADDR, &pkt)
+        let [lsb, msb] = data.to_le_bytes();
+        let pec = smbus_pec(&[self.address << 1, cmd, lsb, msb]);
+        self.i2c.write(self.address, &[cmd, lsb, msb, pec]).await
+    }
+
+    /// `write_word` to EEPROM `cell` rather than RAM.
+    async fn write_eeprom(&mut self, cell: u8, data: u16) -> Result<(), i2c::Error> {
+        self.write_word(EEPROM_ACCESS | cell, data).await
     }
 
     async fn simple_command(&mut self, cmd: u8) -> Result<(), i2c::Error> {
-        self.i2c.blocking_write(MLX90614_ADDR, &[cmd])
+        self.i2c.write(self.address, &[cmd]).await
+    }
+}
+
+/// SMBus packet error code: CRC-8 (x⁸ + x² + x + 1) over every byte of the transaction,
+/// address bytes included.
+fn smbus_pec(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
     }
+    crc
+}
+
+/// data sheet: Temp[°C] = (RAW * 0.02) – 273.15
+fn raw_to_celsius(raw: u16) -> f32 {
+    raw as f32 * 0.02 - 273.15
 }
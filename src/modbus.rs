@@ -0,0 +1,358 @@
+//! Modbus RTU slave for PLC/SCADA polling (`modbus` feature).
+//!
+//! The RP2040's UART pins are all taken by the power stage, sensors and display, so the port is
+//! a PIO UART on two spare state machines of PIO0: TX on GPIO2, RX on GPIO3, and GPIO28 as the
+//! RS-485 transceiver's driver enable. 8N1 at `BAUD_RATE`, slave address `SLAVE_ADDRESS`.
+//!
+//! Function codes 0x03 (read holding), 0x04 (read input) and 0x06 (write single holding).
+//! Temperatures are i16, two's complement; x10 means tenths.
+//!
+//! Input registers (0x04):
+//!
+//! | Addr | Value                                     | Unit       |
+//! |------|-------------------------------------------|------------|
+//! | 0    | DC bus voltage                            | V x10      |
+//! | 1    | Coil current RMS                          | A x10      |
+//! | 2    | Coil power                                | W          |
+//! | 3    | Measured coil frequency                   | Hz / 10    |
+//! | 4    | Coil temperature                          | °C x10 i16 |
+//! | 5    | PCB temperature                           | °C x10 i16 |
+//! | 6    | Module temperature                        | °C x10 i16 |
+//! | 7    | Object temperature                        | °C x10 i16 |
+//! | 8    | Measurement flags, see `MEAS_*`           | bits       |
+//! | 9    | Active mode, numbered as register 2 below |            |
+//! | 10   | Status flags, see `STATUS_*`              | bits       |
+//! | 11   | Power setpoint                            | W          |
+//! | 12   | Switching frequency                       | Hz / 10    |
+//! | 13   | Power derate                              | %          |
+//! | 14   | Fault code, `FaultCode` order, 0 = none   |            |
+//! | 15   | Fault latched                             | 0/1        |
+//!
+//! Holding registers (0x03/0x06):
+//!
+//! | Addr | Value                                            | Unit | Range                |
+//! |------|--------------------------------------------------|------|----------------------|
+//! | 0    | Manual power                                     | W    | 0..=power limit      |
+//! | 1    | Temperature target                               | °C   | `TARGET_TEMP_*_C`    |
+//! | 2    | Mode: 0 idle, 1 manual, 2 temperature, 3 cooling |      | 0..=3                |
+//!
+//! Writes are refused with exception 04 while a fault is latched. Writing a mode only selects
+//! it, exactly like the menu; heating still needs the operator to arm and press Run. The menu
+//! sets the mode again whenever the operator changes screen.
+
+use defmt::{info, unwrap, warn};
+use embassy_executor::Spawner;
+use embassy_rp::{
+    gpio::{Level, Output, Pin},
+    peripherals::PIO0,
+    pio::{Common, PioPin, StateMachine},
+    pio_programs::uart::{PioUartRx, PioUartRxProgram, PioUartTx, PioUartTxProgram},
+};
+use embassy_time::{with_timeout, Duration, Timer};
+use heapless::Vec;
+
+use crate::{
+    config::{mode_from_u8, mode_to_u8},
+    state::{
+        measurements, ControlMode, CONTROL_SETTINGS, CONTROL_STATUS, FAULT_STATE, LIMITS,
+        TARGET_TEMP_MAX_C, TARGET_TEMP_MIN_C,
+    },
+    storage::request_save,
+};
+
+const SLAVE_ADDRESS: u8 = 1;
+const BAUD_RATE: u32 = 19_200;
+/// End of frame: the spec's fixed 1.75 ms inter-frame gap, rounded up for the timer tick.
+const FRAME_GAP: Duration = Duration::from_micros(2_000);
+/// One 8N1 character, for holding the driver enable until the last byte has left.
+const CHAR_TIME_US: u64 = 10 * 1_000_000 / BAUD_RATE as u64;
+const MAX_FRAME_LEN: usize = 256;
+/// Most registers one read may ask for, as in the spec.
+const MAX_READ_REGISTERS: u16 = 125;
+
+const INPUT_REGISTER_COUNT: u16 = 16;
+const HOLDING_REGISTER_COUNT: u16 = 3;
+
+const MEAS_VALID: u16 = 1 << 0;
+const MEAS_OBJECT_REMOVED: u16 = 1 << 1;
+const MEAS_COIL_NTC_OPEN: u16 = 1 << 2;
+const MEAS_MODULE_NTC_FAULT: u16 = 1 << 3;
+const MEAS_ZERO_DRIFT: u16 = 1 << 4;
+const MEAS_PCB_NTC_FAULT: u16 = 1 << 5;
+
+const STATUS_HEATING: u16 = 1 << 0;
+const STATUS_RUN_ACTIVE: u16 = 1 << 1;
+const STATUS_TARGET_REACHED: u16 = 1 << 2;
+const STATUS_COOLDOWN: u16 = 1 << 3;
+const STATUS_PWM_MISMATCH: u16 = 1 << 4;
+const STATUS_PART_REMOVED: u16 = 1 << 5;
+const STATUS_COOLANT_LOST: u16 = 1 << 6;
+const STATUS_RUNTIME_LIMITED: u16 = 1 << 7;
+const STATUS_COOLDOWN_COMPLETE: u16 = 1 << 8;
+
+const READ_HOLDING: u8 = 0x03;
+const READ_INPUT: u8 = 0x04;
+const WRITE_SINGLE: u8 = 0x06;
+
+#[derive(Debug, Clone, Copy, defmt::Format)]
+enum Exception {
+    IllegalFunction = 0x01,
+    IllegalAddress = 0x02,
+    IllegalValue = 0x03,
+    DeviceFailure = 0x04,
+}
+
+type Frame = Vec<u8, MAX_FRAME_LEN>;
+
+/// Loads the UART programs into PIO0 and spawns the slave task.
+pub fn init(
+    spawner: &Spawner,
+    common: &mut Common<'static, PIO0>,
+    sm_tx: StateMachine<'static, PIO0, 1>,
+    sm_rx: StateMachine<'static, PIO0, 2>,
+    tx_pin: impl PioPin,
+    rx_pin: impl PioPin,
+    driver_enable: impl Pin,
+) {
+    let tx_program = PioUartTxProgram::new(common);
+    let rx_program = PioUartRxProgram::new(common);
+    let tx = PioUartTx::new(BAUD_RATE, common, sm_tx, tx_pin, &tx_program);
+    let rx = PioUartRx::new(BAUD_RATE, common, sm_rx, rx_pin, &rx_program);
+    let driver_enable = Output::new(driver_enable, Level::Low);
+
+    unwrap!(spawner.spawn(modbus_task(tx, rx, driver_enable)));
+}
+
+#[embassy_executor::task]
+async fn modbus_task(
+    mut tx: PioUartTx<'static, PIO0, 1>,
+    mut rx: PioUartRx<'static, PIO0, 2>,
+    mut driver_enable: Output<'static>,
+) {
+    info!("Modbus RTU slave at address {}", SLAVE_ADDRESS);
+    let mut request = Frame::new();
+    loop {
+        request.clear();
+        let first = rx.read_u8().await;
+        request.push(first).ok();
+        while let Ok(byte) = with_timeout(FRAME_GAP, rx.read_u8()).await {
+            // An over-long frame is garbage; keep draining it so it ends at the next gap.
+            request.push(byte).ok();
+        }
+
+        let Some(response) = handle_frame(&request).await else {
+            continue;
+        };
+        driver_enable.set_high();
+        for &byte in response.iter() {
+            tx.write_u8(byte).await;
+        }
+        // The PIO FIFO only says the bytes were queued; keep driving until they are out.
+        Timer::after(Duration::from_micros(CHAR_TIME_US * 5)).await;
+        driver_enable.set_low();
+    }
+}
+
+/// Returns the response to send, or `None` for frames that get no answer: bad CRC, another
+/// slave's address, or a broadcast.
+async fn handle_frame(request: &[u8]) -> Option<Frame> {
+    if request.len() < 4 {
+        return None;
+    }
+    let (body, crc) = request.split_at(request.len() - 2);
+    if u16::from_le_bytes([crc[0], crc[1]]) != crc16(body) {
+        return None;
+    }
+    let address = body[0];
+    if address != SLAVE_ADDRESS && address != 0 {
+        return None;
+    }
+
+    let function = body[1];
+    let result = match function {
+        READ_HOLDING | READ_INPUT => read_registers(function, &body[2..]).await,
+        WRITE_SINGLE => write_register(&body[2..]).await.map(|()| {
+            // The normal response echoes the request.
+            let mut echo = Frame::new();
+            echo.extend_from_slice(&body[2..]).ok();
+            echo
+        }),
+        _ => Err(Exception::IllegalFunction),
+    };
+
+    if address == 0 {
+        return None;
+    }
+    let mut response = Frame::new();
+    match result {
+        Ok(data) => {
+            response.extend_from_slice(&[SLAVE_ADDRESS, function]).ok();
+            response.extend_from_slice(&data).ok();
+        }
+        Err(exception) => {
+            warn!("Modbus function {:#x}: exception {}", function, exception);
+            response
+                .extend_from_slice(&[SLAVE_ADDRESS, function | 0x80, exception as u8])
+                .ok();
+        }
+    }
+    let crc = crc16(&response);
+    response.extend_from_slice(&crc.to_le_bytes()).ok();
+    Some(response)
+}
+
+/// `data` is the request after the function code; returns the byte count and register values.
+async fn read_registers(function: u8, data: &[u8]) -> Result<Frame, Exception> {
+    let [start_hi, start_lo, count_hi, count_lo] = data else {
+        return Err(Exception::IllegalValue);
+    };
+    let start = u16::from_be_bytes([*start_hi, *start_lo]);
+    let count = u16::from_be_bytes([*count_hi, *count_lo]);
+    if count == 0 || count > MAX_READ_REGISTERS {
+        return Err(Exception::IllegalValue);
+    }
+    let register_count = if function == READ_INPUT {
+        INPUT_REGISTER_COUNT
+    } else {
+        HOLDING_REGISTER_COUNT
+    };
+    if start as u32 + count as u32 > register_count as u32 {
+        return Err(Exception::IllegalAddress);
+    }
+
+    let registers = if function == READ_INPUT {
+        input_registers().await
+    } else {
+        holding_registers().await
+    };
+    let mut out = Frame::new();
+    out.push((count * 2) as u8).ok();
+    for &value in &registers[start as usize..(start + count) as usize] {
+        out.extend_from_slice(&value.to_be_bytes()).ok();
+    }
+    Ok(out)
+}
+
+async fn input_registers() -> [u16; INPUT_REGISTER_COUNT as usize] {
+    let meas = measurements();
+    let status = *CONTROL_STATUS.lock().await;
+    let latched = FAULT_STATE.lock().await.latched;
+
+    let flag = |set: bool, bit: u16| if set { bit } else { 0 };
+    let meas_flags = flag(meas.valid, MEAS_VALID)
+        | flag(meas.object_removed, MEAS_OBJECT_REMOVED)
+        | flag(meas.coil_temp_disconnected, MEAS_COIL_NTC_OPEN)
+        | flag(meas.module_temp_disconnected, MEAS_MODULE_NTC_FAULT)
+        | flag(meas.current_zero_drift_fault, MEAS_ZERO_DRIFT)
+        | flag(meas.pcb_temp_disconnected, MEAS_PCB_NTC_FAULT);
+    let status_flags = flag(status.heating_enabled, STATUS_HEATING)
+        | flag(status.run_active, STATUS_RUN_ACTIVE)
+        | flag(status.target_reached, STATUS_TARGET_REACHED)
+        | flag(status.cooldown_active, STATUS_COOLDOWN)
+        | flag(status.pwm_freq_mismatch, STATUS_PWM_MISMATCH)
+        | flag(status.part_removed, STATUS_PART_REMOVED)
+        | flag(status.coolant_flow_lost, STATUS_COOLANT_LOST)
+        | flag(status.runtime_limited, STATUS_RUNTIME_LIMITED)
+        | flag(status.cooldown_complete, STATUS_COOLDOWN_COMPLETE);
+
+    [
+        unsigned(meas.dc_voltage_v * 10.0),
+        unsigned(meas.coil_current_rms_a * 10.0),
+        unsigned(meas.coil_power_kw * 1000.0),
+        unsigned(meas.measured_freq_hz / 10.0),
+        signed(meas.coil_temp_c * 10.0),
+        signed(meas.pcb_temp_c * 10.0),
+        signed(meas.module_temp_c * 10.0),
+        signed(meas.object_temp_c * 10.0),
+        meas_flags,
+        mode_to_u8(status.mode) as u16,
+        status_flags,
+        unsigned(status.power_setpoint_kw * 1000.0),
+        unsigned(status.switching_freq_hz / 10.0),
+        unsigned(status.power_derate * 100.0),
+        status.fault as u16,
+        latched as u16,
+    ]
+}
+
+async fn holding_registers() -> [u16; HOLDING_REGISTER_COUNT as usize] {
+    let settings = *CONTROL_SETTINGS.lock().await;
+    [
+        unsigned(settings.manual_power_kw * 1000.0),
+        unsigned(settings.target_temp_c),
+        mode_to_u8(settings.mode) as u16,
+    ]
+}
+
+async fn write_register(data: &[u8]) -> Result<(), Exception> {
+    let [address_hi, address_lo, value_hi, value_lo] = data else {
+        return Err(Exception::IllegalValue);
+    };
+    let address = u16::from_be_bytes([*address_hi, *address_lo]);
+    let value = u16::from_be_bytes([*value_hi, *value_lo]);
+    if address >= HOLDING_REGISTER_COUNT {
+        return Err(Exception::IllegalAddress);
+    }
+    if FAULT_STATE.lock().await.latched {
+        return Err(Exception::DeviceFailure);
+    }
+
+    let power_limit_kw = LIMITS.lock().await.power_kw();
+    let mut settings = CONTROL_SETTINGS.lock().await;
+    match address {
+        0 => {
+            let power_kw = value as f32 / 1000.0;
+            if power_kw > power_limit_kw {
+                return Err(Exception::IllegalValue);
+            }
+            settings.manual_power_kw = power_kw;
+        }
+        1 => {
+            let target_c = value as f32;
+            if !(TARGET_TEMP_MIN_C..=TARGET_TEMP_MAX_C).contains(&target_c) {
+                return Err(Exception::IllegalValue);
+            }
+            settings.target_temp_c = target_c;
+        }
+        _ => {
+            let mode = u8::try_from(value)
+                .ok()
+                .and_then(mode_from_u8)
+                .filter(|mode| {
+                    !matches!(mode, ControlMode::AutoTune | ControlMode::ManualFrequency)
+                })
+                .ok_or(Exception::IllegalValue)?;
+            settings.mode = mode;
+            if matches!(mode, ControlMode::ManualPower | ControlMode::Temperature) {
+                settings.last_run_mode = mode;
+            }
+        }
+    }
+    drop(settings);
+    request_save();
+    Ok(())
+}
+
+fn unsigned(value: f32) -> u16 {
+    value.clamp(0.0, u16::MAX as f32) as u16
+}
+
+fn signed(value: f32) -> u16 {
+    value.clamp(i16::MIN as f32, i16::MAX as f32) as i16 as u16
+}
+
+/// CRC-16/MODBUS (poly 0xA001 reflected, init 0xFFFF), sent low byte first.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
@@ -0,0 +1,71 @@
+//! NTC thermistor curves for the coil, module and PCB temperature sensors.
+
+use libm::logf;
+
+/// Steinhart-Hart coefficients: 1/T = a + b·ln(R) + c·ln(R)³, T in kelvin, R in ohms.
+pub struct SteinhartHart {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+}
+
+impl SteinhartHart {
+    /// The single-beta curve through `r0` ohms at 25 °C.
+    pub fn from_beta(beta: f32, r0: f32) -> Self {
+        Self {
+            a: 1.0 / 298.15 - logf(r0) / beta,
+            b: 1.0 / beta,
+            c: 0.0,
+        }
+    }
+}
+
+// 10 kΩ / B3950 coil NTC. The single-beta curve drifts by several degrees above ~120 °C.
+pub const COIL_NTC: SteinhartHart = SteinhartHart {
+    a: 1.125_308_9e-3,
+    b: 2.347_111e-4,
+    c: 8.566_303e-8,
+};
+// 5 kΩ / B3468 module NTC; c = 0 reproduces the datasheet beta until fitted to its R-T table.
+pub const MODULE_NTC: SteinhartHart = SteinhartHart {
+    a: 8.980_8e-4,
+    b: 2.883_506e-4,
+    c: 0.0,
+};
+
+/// Temperature in °C of a thermistor reading `resistance` ohms.
+pub fn steinhart_hart_temp(resistance: f32, coeffs: &SteinhartHart) -> f32 {
+    let ln_r = logf(resistance);
+    let inv_t = coeffs.a + coeffs.b * ln_r + coeffs.c * ln_r * ln_r * ln_r;
+    1.0 / inv_t - 273.15
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn coil_ntc_nominal_point() {
+        assert_near(steinhart_hart_temp(10_000.0, &COIL_NTC), 25.0, 0.05);
+    }
+
+    #[test]
+    fn coil_ntc_high_temperature_point() {
+        // B3950 datasheet R-T table: 677 Ω at 100 °C.
+        assert_near(steinhart_hart_temp(677.0, &COIL_NTC), 100.0, 0.5);
+    }
+
+    #[test]
+    fn beta_curve_passes_through_r0() {
+        assert_near(steinhart_hart_temp(5_000.0, &MODULE_NTC), 25.0, 0.05);
+        let pcb = SteinhartHart::from_beta(3_950.0, 10_000.0);
+        assert_near(steinhart_hart_temp(10_000.0, &pcb), 25.0, 0.05);
+    }
+}
@@ -0,0 +1,102 @@
+//! Counter settings for the inverter's phase-correct PWM.
+
+/// Lowest switching frequency the divider has to cover, a margin below anything the control
+/// loop asks for.
+pub const PWM_MIN_FREQ_HZ: u32 = 20_000;
+
+/// Counter settings for the phase-correct inverter PWM at one frequency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PwmTiming {
+    pub divider: u8,
+    pub top: u16,
+    /// Dead time in counter ticks, the offset between the A and B compare values.
+    pub dt_counts: u16,
+}
+
+impl PwmTiming {
+    /// Picks the smallest integer divider that keeps `top` in range down to `PWM_MIN_FREQ_HZ`,
+    /// so the divider, and with it the dead-time resolution, is the same across the whole
+    /// frequency span. Frequency and dead time are rounded to the nearest tick.
+    pub fn new(clock_hz: u32, freq_hz: u32, dt_ns: u32) -> Self {
+        // Phase-correct mode counts up and down: one period is 2 * (top + 1) ticks.
+        let max_ticks_per_period = 2 * (u16::MAX as u64 + 1);
+        let divider = (clock_hz as u64)
+            .div_ceil(PWM_MIN_FREQ_HZ as u64 * max_ticks_per_period)
+            .clamp(1, u8::MAX as u64);
+
+        let tick_hz = clock_hz as u64 / divider;
+        let top = (tick_hz + freq_hz as u64) / (2 * freq_hz.max(1) as u64);
+        let dt_counts = (dt_ns as u64 * tick_hz + 500_000_000) / 1_000_000_000;
+
+        Self {
+            divider: divider as u8,
+            top: top.saturating_sub(1).min(u16::MAX as u64) as u16,
+            dt_counts: dt_counts.min(u16::MAX as u64) as u16,
+        }
+    }
+
+    /// Switching frequency these settings actually produce.
+    pub fn freq_hz(&self, clock_hz: u32) -> u32 {
+        clock_hz / (self.divider as u32 * 2 * (self.top as u32 + 1))
+    }
+
+    /// Dead time these settings actually produce.
+    pub fn dead_time_ns(&self, clock_hz: u32) -> u32 {
+        (self.dt_counts as u64 * self.divider as u64 * 1_000_000_000 / clock_hz as u64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// clk_sys as the firmware runs it.
+    const CLOCK_HZ: u32 = 125_000_000;
+    // control.rs: DEADTIME_NS and MIN/MAX_FREQUENCY_HZ.
+    const DEADTIME_NS: u32 = 512;
+    const MIN_FREQUENCY_HZ: u32 = 29_700;
+    const MAX_FREQUENCY_HZ: u32 = 45_000;
+
+    fn tick_ns(timing: &PwmTiming) -> u32 {
+        timing.divider as u32 * 1_000_000_000 / CLOCK_HZ
+    }
+
+    #[test]
+    fn dead_time_round_trips_across_the_band() {
+        let first = PwmTiming::new(CLOCK_HZ, MIN_FREQUENCY_HZ, DEADTIME_NS);
+        for freq_hz in (MIN_FREQUENCY_HZ..=MAX_FREQUENCY_HZ).step_by(100) {
+            let timing = PwmTiming::new(CLOCK_HZ, freq_hz, DEADTIME_NS);
+            // Same divider, so the same dead time, wherever the control loop is in the band.
+            assert_eq!(timing.divider, first.divider, "{freq_hz} Hz");
+            assert_eq!(timing.dt_counts, first.dt_counts, "{freq_hz} Hz");
+            assert!(timing.dt_counts > 0, "{freq_hz} Hz");
+            let dead_time_ns = timing.dead_time_ns(CLOCK_HZ);
+            assert!(
+                dead_time_ns.abs_diff(DEADTIME_NS) <= tick_ns(&timing) / 2 + 1,
+                "{freq_hz} Hz: {dead_time_ns} ns"
+            );
+        }
+    }
+
+    #[test]
+    fn frequency_round_trips_across_the_band() {
+        for freq_hz in (MIN_FREQUENCY_HZ..=MAX_FREQUENCY_HZ).step_by(100) {
+            let timing = PwmTiming::new(CLOCK_HZ, freq_hz, DEADTIME_NS);
+            // One tick either way of top moves the frequency by about f^2 * 2 * tick.
+            let step_hz = (freq_hz as u64 * freq_hz as u64 * 2 * tick_ns(&timing) as u64
+                / 1_000_000_000) as u32;
+            let actual_hz = timing.freq_hz(CLOCK_HZ);
+            assert!(
+                actual_hz.abs_diff(freq_hz) <= step_hz,
+                "{freq_hz} Hz: got {actual_hz} Hz"
+            );
+        }
+    }
+
+    #[test]
+    fn top_fits_down_to_the_minimum_frequency() {
+        let timing = PwmTiming::new(CLOCK_HZ, PWM_MIN_FREQ_HZ, DEADTIME_NS);
+        assert!(timing.top < u16::MAX);
+        assert!(timing.freq_hz(CLOCK_HZ).abs_diff(PWM_MIN_FREQ_HZ) <= PWM_MIN_FREQ_HZ / 100);
+    }
+}
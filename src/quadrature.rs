@@ -0,0 +1,114 @@
+//! Quadrature decoding for the rotary encoder, kept apart from the GPIO handling in the
+//! firmware's `encoder` module.
+
+/// Quadrature steps between detents on the usual mechanical encoders.
+const STEPS_PER_DETENT: i8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detent {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Quadrature step for each (previous AB, current AB) pair, indexed by `previous << 2 | current`.
+/// Positive is clockwise. Unchanged states and double transitions (a missed edge, contact
+/// bounce) count as no movement.
+const TRANSITIONS: [i8; 16] = [
+    0, 1, -1, 0, //
+    -1, 0, 0, 1, //
+    1, 0, 0, -1, //
+    0, -1, 1, 0, //
+];
+
+/// Turns A/B levels into detents.
+pub struct QuadratureDecoder {
+    state: u8,
+    steps: i8,
+}
+
+impl QuadratureDecoder {
+    /// Starts from the lines' current levels.
+    pub const fn new(a: bool, b: bool) -> Self {
+        Self {
+            state: ((a as u8) << 1) | b as u8,
+            steps: 0,
+        }
+    }
+
+    /// Feeds the current A/B levels; returns a detent once enough steps in one direction have
+    /// added up. Bounce back and forth cancels out.
+    pub fn update(&mut self, a: bool, b: bool) -> Option<Detent> {
+        let next = ((a as u8) << 1) | b as u8;
+        self.steps += TRANSITIONS[((self.state << 2) | next) as usize];
+        self.state = next;
+
+        if self.steps >= STEPS_PER_DETENT {
+            self.steps = 0;
+            Some(Detent::Clockwise)
+        } else if self.steps <= -STEPS_PER_DETENT {
+            self.steps = 0;
+            Some(Detent::CounterClockwise)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One detent of (A, B) levels clockwise from rest at 00.
+    const CW: [(bool, bool); 4] = [(false, true), (true, true), (true, false), (false, false)];
+
+    fn feed(decoder: &mut QuadratureDecoder, levels: &[(bool, bool)]) -> Option<Detent> {
+        let mut detent = None;
+        for &(a, b) in levels {
+            if let Some(d) = decoder.update(a, b) {
+                assert!(detent.is_none(), "more than one detent");
+                detent = Some(d);
+            }
+        }
+        detent
+    }
+
+    #[test]
+    fn clockwise_detent() {
+        let mut decoder = QuadratureDecoder::new(false, false);
+        assert_eq!(feed(&mut decoder, &CW[..3]), None);
+        assert_eq!(feed(&mut decoder, &CW[3..]), Some(Detent::Clockwise));
+    }
+
+    #[test]
+    fn counter_clockwise_detent() {
+        let mut decoder = QuadratureDecoder::new(false, false);
+        let ccw = [(true, false), (true, true), (false, true), (false, false)];
+        assert_eq!(feed(&mut decoder, &ccw[..3]), None);
+        assert_eq!(
+            feed(&mut decoder, &ccw[3..]),
+            Some(Detent::CounterClockwise)
+        );
+    }
+
+    #[test]
+    fn bounce_cancels_out() {
+        let mut decoder = QuadratureDecoder::new(false, false);
+        // A contact chattering on the first edge, then settling back at rest.
+        let bounce = [(false, true), (false, false), (false, true), (false, false)];
+        assert_eq!(feed(&mut decoder, &bounce), None);
+        // Nothing was left over: a full detent still takes exactly four steps.
+        assert_eq!(feed(&mut decoder, &CW[..3]), None);
+        assert_eq!(feed(&mut decoder, &CW[3..]), Some(Detent::Clockwise));
+    }
+
+    #[test]
+    fn double_transition_is_no_step() {
+        let mut decoder = QuadratureDecoder::new(false, false);
+        // 00 -> 11 skips a state, so the direction is unknown.
+        assert_eq!(decoder.update(true, true), None);
+        assert_eq!(decoder.steps, 0);
+        // 11 -> 00 likewise.
+        assert_eq!(decoder.update(false, false), None);
+        assert_eq!(decoder.steps, 0);
+    }
+}
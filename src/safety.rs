@@ -1,37 +1,97 @@
+use core::sync::atomic::{AtomicBool, Ordering};
 use defmt::{info, warn};
 use embassy_rp::gpio::Input;
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    watch::{Receiver, Watch},
+};
 use embassy_time::{Duration, Instant, Timer};
+use libm::fabsf;
 
+use crate::board::DC_UNDERVOLTAGE_V;
+use crate::estop::{gate_fault_active, interlock_open, overcurrent_tripped, reset_overcurrent};
 use crate::state::{
-    FaultCode, Measurements, COIL_TEMP_LIMIT_C, CURRENT_LIMIT_A, FAULT_STATE, MEASUREMENTS,
-    MODULE_TEMP_LIMIT_C, PCB_TEMP_LIMIT_C, POWER_LIMIT_KW,
+    measurements, menu_silent_for, stale_source, ControlMode, ControlStatus, FaultCode,
+    FaultRecord, Limits, Measurements, WarningLevel, CONTROL_SETTINGS, CONTROL_STATUS,
+    FAULT_HISTORY, FAULT_STATE, LIMITS,
 };
 
 const POWER_OVERSHOOT_MARGIN: f32 = 1.05;
 const EARLY_WARNING_MARGIN_C: f32 = 5.0;
+// Inside this much of the early-warning margin the buzzer cadence speeds up.
+const NEAR_WARNING_MARGIN_C: f32 = 2.0;
 const WATCHDOG_LOG_INTERVAL: Duration = Duration::from_secs(2);
+// Within this band below the coil temperature limit a coil heating faster than the rate limit trips
+// CoilOverTemp early instead of overshooting the limit. The rate is taken over a window of
+// several safety passes; per-pass differences are mostly the sensor deadband.
+const COIL_RISE_WARNING_BAND_C: f32 = 15.0;
+const COIL_RISE_RATE_LIMIT_C_PER_S: f32 = 2.0;
+const COIL_RISE_WINDOW: Duration = Duration::from_millis(500);
+// Cross-check between coil power and the IR reading: this much power for NO_HEATING_WINDOW
+// has to lift the object temperature by at least NO_HEATING_MIN_RISE_C, otherwise the IR
+// sensor is not looking at the part (or the coil is not coupling into it).
+const NO_HEATING_MIN_POWER_KW: f32 = 1.5;
+const NO_HEATING_WINDOW: Duration = Duration::from_secs(8);
+const NO_HEATING_MIN_RISE_C: f32 = 2.0;
+// Holding or soaking at target keeps the part flat on purpose, so the check stands down at
+// target, during a soak and within this much of the temperature-mode target.
+const NO_HEATING_TARGET_BAND_C: f32 = 5.0;
+// Consecutive 25 ms passes a gate-driver line has to read faulted before it raises a fault, so
+// a blip from switching noise does not latch GateDriverFault. The interlock is not debounced.
+const GATE_FAULT_DEBOUNCE_PASSES: u8 = 3;
+const GATE_READY_DEBOUNCE_PASSES: u8 = 3;
+// About 300 ms of safety passes: long enough to ride out the bus dip when a run starts.
+const DC_UNDERVOLTAGE_PASSES: u8 = 12;
+// The menu polls its buttons every 10-50 ms and its longest deliberate pause is under a
+// second; silent for this long, it has hung and the operator can no longer stop a run.
+const MENU_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Gate-driver ready input as last seen by `safety_task`.
+static GATE_READY: AtomicBool = AtomicBool::new(false);
+
+/// Tasks that may hold a [`fault_watcher`] at the same time.
+pub const FAULT_RECEIVERS: usize = 3;
+
+/// Active fault code, published on every change of `FAULT_STATE.code`.
+static FAULT_WATCH: Watch<CriticalSectionRawMutex, FaultCode, FAULT_RECEIVERS> =
+    Watch::new_with(FaultCode::None);
+
+pub type FaultReceiver = Receiver<'static, CriticalSectionRawMutex, FaultCode, FAULT_RECEIVERS>;
 
 #[derive(Clone, Copy)]
 struct SafetyReport {
     code: FaultCode,
     snapshot: Measurements,
+    limits: Limits,
 }
 
 #[embassy_executor::task]
-pub async fn safety_task(
-    interlock: &'static mut Input<'static>,
-    gate_fault: &'static mut Input<'static>,
-    gate_ready: &'static mut Input<'static>,
-) {
+pub async fn safety_task(gate_ready: &'static mut Input<'static>) {
     let mut next_watchdog_log = Instant::now();
+    let mut coil_rise = CoilRiseMonitor::new();
+    let mut heating_check = HeatingPlausibility::new();
+    let mut bus_monitor = DcUndervoltageMonitor::new();
+    let mut gpio_faults = GpioFaultDebounce::new();
+    let mut menu_watch = MenuWatch::new();
 
     loop {
-        let report = evaluate_fault(interlock, gate_fault, gate_ready).await;
+        let report = evaluate_fault(
+            gate_ready,
+            &mut gpio_faults,
+            &mut coil_rise,
+            &mut heating_check,
+            &mut bus_monitor,
+            &mut menu_watch,
+        )
+        .await;
         let code = report.code;
+        let warning = warning_level(&report.snapshot, &report.limits, code);
+        let mut transitioned = false;
 
         {
             let mut fault = FAULT_STATE.lock().await;
-            if fault.code != code {
+            fault.warning = warning;
+            if fault.code != code && !fault.latched {
                 if code == FaultCode::None {
                     if fault.code != FaultCode::None {
                         info!(
@@ -67,10 +127,18 @@ pub async fn safety_task(
                     );
                 }
                 fault.code = code;
+                fault.latched = code.latching();
+                FAULT_WATCH.sender().send(code);
+                transitioned = true;
             }
         }
+        if transitioned {
+            record_fault(code, offending_value(code, &report.snapshot)).await;
+        }
 
-        if Instant::now() >= next_watchdog_log && should_log_watchdog(&report.snapshot, code) {
+        if Instant::now() >= next_watchdog_log
+            && should_log_watchdog(&report.snapshot, &report.limits, code)
+        {
             info!(
                 "Safety watch: fault={} coil={}C{} module={}C pcb={}C power={}kW current={}A",
                 code.message(),
@@ -92,85 +160,403 @@ pub async fn safety_task(
     }
 }
 
+/// Gate-driver ready input as last read, undebounced; for display.
+pub fn gate_driver_ready() -> bool {
+    GATE_READY.load(Ordering::Relaxed)
+}
+
+/// Operator acknowledgement; releases a latched fault. A condition that is still present is
+/// picked up again on the next safety pass.
 pub async fn clear_fault() {
     let mut fault = FAULT_STATE.lock().await;
+    if fault.code != FaultCode::None {
+        info!("Fault reset by operator: {}", fault.code.message());
+    }
+    let was_set = fault.code != FaultCode::None;
     fault.code = FaultCode::None;
+    fault.latched = false;
+    reset_overcurrent();
+    FAULT_WATCH.sender().send(FaultCode::None);
+    drop(fault);
+    if was_set {
+        record_fault(FaultCode::None, 0.0).await;
+    }
 }
 
-pub async fn current_fault() -> FaultCode {
-    FAULT_STATE.lock().await.code
+/// Raises `code` straight away, for checks that cannot wait for the next safety pass. Same
+/// rules as a pass: ignored while a latched fault is showing, and latching faults stay until
+/// cleared.
+pub async fn raise_fault(code: FaultCode, value: f32) {
+    let mut fault = FAULT_STATE.lock().await;
+    if fault.code == code || fault.latched {
+        return;
+    }
+    warn!("Fault raised: {} ({})", code.message(), value);
+    fault.code = code;
+    fault.latched = code.latching();
+    FAULT_WATCH.sender().send(code);
+    drop(fault);
+    record_fault(code, value).await;
+}
+
+/// One-shot read of the active fault, without taking the `FAULT_STATE` lock.
+pub fn current_fault() -> FaultCode {
+    FAULT_WATCH.try_get().unwrap_or(FaultCode::None)
+}
+
+/// Receiver for fault transitions, for tasks that would rather await a change than poll.
+/// Panics if `FAULT_RECEIVERS` are already taken; dropping one frees its slot.
+pub fn fault_watcher() -> FaultReceiver {
+    FAULT_WATCH.receiver().unwrap()
+}
+
+async fn record_fault(code: FaultCode, value: f32) {
+    FAULT_HISTORY.lock().await.write(FaultRecord {
+        code,
+        at: Instant::now(),
+        value,
+    });
+}
+
+fn offending_value(code: FaultCode, meas: &Measurements) -> f32 {
+    match code {
+        FaultCode::PowerLimit => meas.coil_power_kw,
+        FaultCode::CoilOverTemp => meas.coil_temp_c,
+        FaultCode::ModuleOverTemp => meas.module_temp_c,
+        FaultCode::PcbOverTemp => meas.pcb_temp_c,
+        FaultCode::CurrentLimit => meas.coil_current_rms_a,
+        FaultCode::CurrentSensorFault => meas.current_zero_v,
+        FaultCode::PwmFault => meas.measured_freq_hz,
+        FaultCode::NoHeatingDetected => meas.object_temp_c,
+        FaultCode::DcUndervoltage => meas.dc_voltage_v,
+        FaultCode::AdcSaturation => meas.coil_current_rms_a,
+        FaultCode::MenuUnresponsive => menu_silent_for().map_or(0.0, |d| d.as_millis() as f32),
+        _ => 0.0,
+    }
 }
 
 async fn evaluate_fault(
-    interlock: &Input<'static>,
-    gate_fault: &Input<'static>,
     gate_ready: &Input<'static>,
+    gpio_faults: &mut GpioFaultDebounce,
+    coil_rise: &mut CoilRiseMonitor,
+    heating_check: &mut HeatingPlausibility,
+    bus_monitor: &mut DcUndervoltageMonitor,
+    menu_watch: &mut MenuWatch,
 ) -> SafetyReport {
-    let mut code = check_gpio_faults(interlock, gate_fault, gate_ready);
-    let meas = *MEASUREMENTS.lock().await;
+    let mut code = gpio_faults.check(gate_ready);
+    if code == FaultCode::None && menu_watch.update() {
+        code = FaultCode::MenuUnresponsive;
+    }
+    let meas = measurements();
+    let limits = *LIMITS.lock().await;
+    let status = *CONTROL_STATUS.lock().await;
+    let coil_running_away = coil_rise.update(&meas, limits.coil_temp_c());
+    let target_c = CONTROL_SETTINGS.lock().await.target_temp_c;
+    let not_heating = heating_check.update(&meas, &status, target_c);
+    let bus_low = bus_monitor.update(&meas, status.heating_enabled);
 
+    // A silently dead sensor task would otherwise leave its last values looking current.
+    if code == FaultCode::None && stale_source().is_some() {
+        code = FaultCode::SensorFault;
+    }
     if code == FaultCode::None {
-        code = detect_measurement_fault(&meas);
+        code = detect_measurement_fault(&meas, &limits, bus_low);
+    }
+    if code == FaultCode::None && coil_running_away {
+        code = FaultCode::CoilOverTemp;
+    }
+    if code == FaultCode::None && not_heating {
+        code = FaultCode::NoHeatingDetected;
+    }
+    if code == FaultCode::None {
+        if status.coolant_flow_lost {
+            code = FaultCode::NoCoolantFlow;
+        } else if status.pwm_freq_mismatch {
+            code = FaultCode::PwmFault;
+        }
     }
 
     SafetyReport {
         code,
         snapshot: meas,
+        limits,
     }
 }
 
-fn check_gpio_faults(
-    interlock: &Input<'static>,
-    gate_fault: &Input<'static>,
-    gate_ready: &Input<'static>,
-) -> FaultCode {
-    if interlock.is_low() {
-        return FaultCode::InterlockOpen;
+/// Menu heartbeat check.
+struct MenuWatch {
+    hung: bool,
+}
+
+impl MenuWatch {
+    fn new() -> Self {
+        Self { hung: false }
     }
-    if gate_fault.is_low() {
-        return FaultCode::GateDriverFault;
+
+    /// Returns true while the menu has been silent past `MENU_HEARTBEAT_TIMEOUT`. Logged on
+    /// its own, since the fault screen that would normally show it is the part that hung.
+    fn update(&mut self) -> bool {
+        let silent = menu_silent_for().unwrap_or(Duration::from_ticks(0));
+        let hung = silent > MENU_HEARTBEAT_TIMEOUT;
+        if hung && !self.hung {
+            warn!(
+                "Menu heartbeat lost for {} ms: UI hung, shutting the drive down",
+                silent.as_millis()
+            );
+        } else if !hung && self.hung {
+            info!("Menu heartbeat back");
+        }
+        self.hung = hung;
+        hung
     }
-    if gate_ready.is_low() {
-        return FaultCode::GateDriverNotReady;
+}
+
+/// Debounced DC bus sag while heating.
+struct DcUndervoltageMonitor {
+    /// The bus has been seen above the floor since boot; a unit powered up with the supply
+    /// still off must not fault.
+    armed: bool,
+    low_passes: u8,
+}
+
+impl DcUndervoltageMonitor {
+    fn new() -> Self {
+        Self {
+            armed: false,
+            low_passes: 0,
+        }
+    }
+
+    /// Returns true once the bus has been below the floor for `DC_UNDERVOLTAGE_PASSES` passes
+    /// in a row while heating.
+    fn update(&mut self, meas: &Measurements, heating: bool) -> bool {
+        if !meas.valid {
+            self.low_passes = 0;
+            return false;
+        }
+        let low = meas.dc_voltage_v < DC_UNDERVOLTAGE_V;
+        if !low {
+            self.armed = true;
+        }
+        debounce(
+            &mut self.low_passes,
+            self.armed && heating && low,
+            DC_UNDERVOLTAGE_PASSES,
+        )
+    }
+}
+
+/// Coil temperature slope, for catching a runaway before it reaches the hard limit.
+struct CoilRiseMonitor {
+    prev: Option<(f32, Instant)>,
+    rate_c_per_s: f32,
+}
+
+impl CoilRiseMonitor {
+    fn new() -> Self {
+        Self {
+            prev: None,
+            rate_c_per_s: 0.0,
+        }
+    }
+
+    /// Returns true while the coil is inside the warning band and rising faster than allowed.
+    fn update(&mut self, meas: &Measurements, coil_limit_c: f32) -> bool {
+        if meas.coil_temp_disconnected {
+            self.prev = None;
+            self.rate_c_per_s = 0.0;
+            return false;
+        }
+
+        let now = Instant::now();
+        match self.prev {
+            Some((prev_c, at)) => {
+                let elapsed = now.saturating_duration_since(at);
+                if elapsed >= COIL_RISE_WINDOW {
+                    self.rate_c_per_s =
+                        (meas.coil_temp_c - prev_c) / (elapsed.as_micros() as f32 / 1_000_000.0);
+                    self.prev = Some((meas.coil_temp_c, now));
+                }
+            }
+            None => self.prev = Some((meas.coil_temp_c, now)),
+        }
+
+        meas.coil_temp_c >= coil_limit_c - COIL_RISE_WARNING_BAND_C
+            && self.rate_c_per_s > COIL_RISE_RATE_LIMIT_C_PER_S
+    }
+}
+
+/// Object temperature at the start of the current stretch of substantial coil power.
+struct HeatingPlausibility {
+    window_start: Option<(f32, Instant)>,
+}
+
+impl HeatingPlausibility {
+    fn new() -> Self {
+        Self { window_start: None }
+    }
+
+    /// Returns true once power has been on for a full window without the object warming.
+    /// `target_c` is the temperature-mode target.
+    fn update(&mut self, meas: &Measurements, status: &ControlStatus, target_c: f32) -> bool {
+        let near_target = status.mode == ControlMode::Temperature
+            && fabsf(meas.object_temp_c - target_c) <= NO_HEATING_TARGET_BAND_C;
+        let holding = status.target_reached || status.soak_remaining_s.is_some() || near_target;
+        // A stale object temperature cannot show a rise either.
+        if !meas.valid
+            || !meas.object_temp_valid
+            || holding
+            || meas.coil_power_kw < NO_HEATING_MIN_POWER_KW
+        {
+            self.window_start = None;
+            return false;
+        }
+
+        let now = Instant::now();
+        let (start_c, at) = *self.window_start.get_or_insert((meas.object_temp_c, now));
+        if meas.object_temp_c - start_c >= NO_HEATING_MIN_RISE_C {
+            // Heating is visible; start a fresh window from here.
+            self.window_start = Some((meas.object_temp_c, now));
+            return false;
+        }
+        now.saturating_duration_since(at) >= NO_HEATING_WINDOW
+    }
+}
+
+/// Consecutive faulted passes per gate-driver line.
+struct GpioFaultDebounce {
+    gate_fault_passes: u8,
+    gate_ready_passes: u8,
+}
+
+impl GpioFaultDebounce {
+    fn new() -> Self {
+        Self {
+            gate_fault_passes: 0,
+            gate_ready_passes: 0,
+        }
+    }
+
+    /// Interlock, gate fault and the overcurrent comparator come from `estop`, which owns those
+    /// inputs and has already cut the gate drive by the time they show up here; the debounce
+    /// only decides whether a gate fault is raised (and latched).
+    fn check(&mut self, gate_ready: &Input<'static>) -> FaultCode {
+        let gate_fault = debounce(
+            &mut self.gate_fault_passes,
+            gate_fault_active(),
+            GATE_FAULT_DEBOUNCE_PASSES,
+        );
+        GATE_READY.store(gate_ready.is_high(), Ordering::Relaxed);
+        let not_ready = debounce(
+            &mut self.gate_ready_passes,
+            gate_ready.is_low(),
+            GATE_READY_DEBOUNCE_PASSES,
+        );
+
+        // Sticky until `clear_fault`, so even a microsecond comparator pulse latches.
+        if overcurrent_tripped() {
+            return FaultCode::CurrentLimit;
+        }
+        if interlock_open() {
+            return FaultCode::InterlockOpen;
+        }
+        if gate_fault {
+            return FaultCode::GateDriverFault;
+        }
+        if not_ready {
+            return FaultCode::GateDriverNotReady;
+        }
+        FaultCode::None
     }
-    FaultCode::None
 }
 
-fn detect_measurement_fault(meas: &Measurements) -> FaultCode {
-    if meas.coil_temp_disconnected {
+/// Counts consecutive `active` passes; true once there have been `passes` of them.
+fn debounce(count: &mut u8, active: bool, passes: u8) -> bool {
+    *count = if active { count.saturating_add(1) } else { 0 };
+    *count >= passes
+}
+
+fn detect_measurement_fault(meas: &Measurements, limits: &Limits, bus_low: bool) -> FaultCode {
+    if meas.coil_temp_disconnected
+        || meas.module_temp_disconnected
+        || meas.pcb_temp_disconnected
+        || (!meas.object_temp_valid && !meas.object_temp_settling)
+        || !meas.ads_healthy
+    {
         return FaultCode::SensorFault;
     }
+    if meas.adc_saturated {
+        return FaultCode::AdcSaturation;
+    }
+    if meas.current_zero_drift_fault {
+        return FaultCode::CurrentSensorFault;
+    }
 
-    if meas.coil_temp_c > COIL_TEMP_LIMIT_C {
+    if meas.coil_temp_c > limits.coil_temp_c() {
         return FaultCode::CoilOverTemp;
     }
-    if meas.module_temp_c > MODULE_TEMP_LIMIT_C {
+    if meas.module_temp_c > limits.module_temp_c() {
         return FaultCode::ModuleOverTemp;
     }
-    if meas.pcb_temp_c > PCB_TEMP_LIMIT_C {
+    if meas.pcb_temp_c > limits.pcb_temp_c() {
         return FaultCode::PcbOverTemp;
     }
 
     if meas.valid {
-        if meas.coil_power_kw > POWER_LIMIT_KW * POWER_OVERSHOOT_MARGIN {
+        if meas.coil_power_kw > limits.power_kw() * POWER_OVERSHOOT_MARGIN {
             return FaultCode::PowerLimit;
         }
-        if meas.coil_current_rms_a > CURRENT_LIMIT_A {
+        if meas.coil_current_rms_a > limits.current_a() {
             return FaultCode::CurrentLimit;
         }
+        if bus_low {
+            return FaultCode::DcUndervoltage;
+        }
     }
 
     FaultCode::None
 }
 
-fn should_log_watchdog(meas: &Measurements, code: FaultCode) -> bool {
+fn warning_level(meas: &Measurements, limits: &Limits, code: FaultCode) -> WarningLevel {
+    if matches!(
+        code,
+        FaultCode::CoilOverTemp | FaultCode::ModuleOverTemp | FaultCode::PcbOverTemp
+    ) {
+        return WarningLevel::Trip;
+    }
+
+    let mut margin = f32::MAX;
+    if !meas.pcb_temp_disconnected {
+        margin = margin.min(limits.pcb_temp_c() - meas.pcb_temp_c);
+    }
+    if !meas.module_temp_disconnected {
+        margin = margin.min(limits.module_temp_c() - meas.module_temp_c);
+    }
+    if !meas.coil_temp_disconnected {
+        margin = margin.min(limits.coil_temp_c() - meas.coil_temp_c);
+    }
+
+    if margin <= 0.0 {
+        WarningLevel::Trip
+    } else if margin <= NEAR_WARNING_MARGIN_C {
+        WarningLevel::Near
+    } else if margin <= EARLY_WARNING_MARGIN_C {
+        WarningLevel::Approaching
+    } else {
+        WarningLevel::None
+    }
+}
+
+fn should_log_watchdog(meas: &Measurements, limits: &Limits, code: FaultCode) -> bool {
     if code != FaultCode::None {
         return true;
     }
 
     meas.coil_temp_disconnected
-        || meas.coil_temp_c >= COIL_TEMP_LIMIT_C - EARLY_WARNING_MARGIN_C
-        || meas.module_temp_c >= MODULE_TEMP_LIMIT_C - EARLY_WARNING_MARGIN_C
-        || meas.pcb_temp_c >= PCB_TEMP_LIMIT_C - EARLY_WARNING_MARGIN_C
-        || (meas.valid && meas.coil_power_kw >= POWER_LIMIT_KW * 0.9)
+        || meas.module_temp_disconnected
+        || meas.pcb_temp_disconnected
+        || meas.coil_temp_c >= limits.coil_temp_c() - EARLY_WARNING_MARGIN_C
+        || meas.module_temp_c >= limits.module_temp_c() - EARLY_WARNING_MARGIN_C
+        || meas.pcb_temp_c >= limits.pcb_temp_c() - EARLY_WARNING_MARGIN_C
+        || (meas.valid && meas.coil_power_kw >= limits.power_kw() * 0.9)
 }
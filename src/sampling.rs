@@ -0,0 +1,43 @@
+//! Sample-rate arithmetic for the RP2040's on-chip ADC.
+
+/// Divider for `read_many_multichannel` so each of `channels` is sampled at `rate_hz`,
+/// clamped to what the register holds.
+pub fn compute_adc_div(adc_clk_hz: u32, rate_hz: u32, channels: u32) -> u16 {
+    if channels == 0 || rate_hz == 0 {
+        return 0;
+    }
+    let div = adc_clk_hz
+        .saturating_div(rate_hz.saturating_mul(channels))
+        .saturating_sub(1);
+    div.min(u16::MAX as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADC_CLK_HZ: u32 = 48_000_000;
+
+    #[test]
+    fn divides_down_to_the_rate() {
+        // 48 MHz / (150 kHz * 2 channels) = 160 clocks per conversion, written as 159.
+        assert_eq!(compute_adc_div(ADC_CLK_HZ, 150_000, 2), 159);
+    }
+
+    #[test]
+    fn scales_with_channel_count() {
+        assert_eq!(compute_adc_div(ADC_CLK_HZ, 150_000, 1), 319);
+        assert_eq!(compute_adc_div(ADC_CLK_HZ, 150_000, 4), 79);
+    }
+
+    #[test]
+    fn clamps_to_the_register() {
+        assert_eq!(compute_adc_div(ADC_CLK_HZ, 1, 1), u16::MAX);
+    }
+
+    #[test]
+    fn no_rate_or_channels_is_free_running() {
+        assert_eq!(compute_adc_div(ADC_CLK_HZ, 0, 2), 0);
+        assert_eq!(compute_adc_div(ADC_CLK_HZ, 150_000, 0), 0);
+    }
+}
@@ -0,0 +1,144 @@
+//! Power-on self-test.
+//!
+//! `main` runs it once the peripherals are up and before the menu, sensor and control tasks
+//! start, so nothing can be armed on a unit with a wiring fault. Each check is a single read;
+//! a failed check is retried until it clears or the operator overrides it.
+
+use core::fmt::Write;
+use defmt::{info, warn};
+use embassy_rp::gpio::Input;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::{String, Vec};
+
+use crate::{
+    board::{DisplayLcd, IrThermometer, SensorAdc, ADS_CHANNELS},
+    menu::MenuButton,
+    sensors::COIL_SENSOR_DISCONNECT_V,
+};
+
+/// MLX90614 die temperatures outside this range mean a bad read rather than a cold workshop.
+const AMBIENT_PLAUSIBLE_MIN_C: f32 = -20.0;
+const AMBIENT_PLAUSIBLE_MAX_C: f32 = 60.0;
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+/// Enter held this long on the failure screen carries on regardless.
+const OVERRIDE_HOLD: Duration = Duration::from_secs(3);
+
+/// A self-test check that did not pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SelfTestFailure {
+    /// The ADS7828 did not acknowledge on I2C.
+    AdcNoAck,
+    /// The MLX90614 did not answer, or its die temperature is implausible.
+    IrAmbient,
+    /// The coil NTC input reads as disconnected.
+    CoilNtcOpen,
+    /// The gate driver is not signalling ready.
+    GateNotReady,
+}
+
+impl SelfTestFailure {
+    pub const fn lcd_label(self) -> &'static str {
+        match self {
+            SelfTestFailure::AdcNoAck => "ADC no ack",
+            SelfTestFailure::IrAmbient => "IR sensor bad",
+            SelfTestFailure::CoilNtcOpen => "Coil NTC open",
+            SelfTestFailure::GateNotReady => "Gate drv wait",
+        }
+    }
+}
+
+/// Failed checks, in the order they are run.
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestResult {
+    failures: Vec<SelfTestFailure, 4>,
+}
+
+impl SelfTestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    pub fn failures(&self) -> &[SelfTestFailure] {
+        &self.failures
+    }
+}
+
+/// Runs every check once.
+pub async fn run(
+    ads: &SensorAdc,
+    mlx: &mut IrThermometer,
+    gate_ready: &Input<'static>,
+) -> SelfTestResult {
+    let mut result = SelfTestResult::default();
+    match ads.get_channel(ADS_CHANNELS.coil_ntc).await {
+        Ok(code) => {
+            if ads.code_to_voltage(code as f32) >= COIL_SENSOR_DISCONNECT_V {
+                result.failures.push(SelfTestFailure::CoilNtcOpen).ok();
+            }
+        }
+        Err(_e) => {
+            result.failures.push(SelfTestFailure::AdcNoAck).ok();
+        }
+    }
+    let ambient_ok = mlx
+        .read_ambient_temp()
+        .await
+        .is_ok_and(|t| (AMBIENT_PLAUSIBLE_MIN_C..=AMBIENT_PLAUSIBLE_MAX_C).contains(&t));
+    if !ambient_ok {
+        result.failures.push(SelfTestFailure::IrAmbient).ok();
+    }
+    if gate_ready.is_low() {
+        result.failures.push(SelfTestFailure::GateNotReady).ok();
+    }
+    result
+}
+
+/// Runs the self-test, and while anything fails keeps the LCD on "Self-test failed" with the
+/// failed checks in turn, re-running it every `RETRY_INTERVAL`. Returns once everything passes
+/// or Enter has been held for `OVERRIDE_HOLD`.
+pub async fn hold_until_passed(
+    lcd: &mut DisplayLcd,
+    enter: &MenuButton,
+    ads: &SensorAdc,
+    mlx: &mut IrThermometer,
+    gate_ready: &Input<'static>,
+) {
+    let mut shown = 0usize;
+    let mut enter_held_since: Option<Instant> = None;
+    let mut reported = false;
+    loop {
+        let result = run(ads, mlx, gate_ready).await;
+        if result.passed() {
+            info!("Self-test passed");
+            return;
+        }
+        if !reported {
+            warn!("Self-test failed: {}", result.failures());
+            reported = true;
+        }
+
+        let failure = result.failures()[shown % result.failures().len()];
+        shown = shown.wrapping_add(1);
+        let mut line = String::<16>::new();
+        write!(line, "{:<16}", failure.lcd_label()).ok();
+        lcd.set_cursor(0, 0).await;
+        lcd.message("Self-test failed").await;
+        lcd.set_cursor(0, 1).await;
+        lcd.message(line.as_str()).await;
+
+        if enter.is_low() {
+            let since = *enter_held_since.get_or_insert(Instant::now());
+            if since.elapsed() >= OVERRIDE_HOLD {
+                warn!("Self-test overridden: {}", result.failures());
+                // Let go of Enter first so the menu does not take the hold as a press.
+                while enter.is_low() {
+                    Timer::after(Duration::from_millis(20)).await;
+                }
+                return;
+            }
+        } else {
+            enter_held_since = None;
+        }
+        Timer::after(RETRY_INTERVAL).await;
+    }
+}
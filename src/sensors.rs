@@ -1,26 +1,108 @@
+use core::sync::atomic::{AtomicU32, Ordering};
 use defmt::*;
 use embassy_hal_internal::PeripheralRef;
 use embassy_rp::{
     adc::{Adc, Async, Channel},
+    clocks,
     gpio::Pull,
+    i2c::Config as I2cConfig,
     peripherals::PIO0,
     pio::{
         self, program::pio_asm, Common, Direction as PioDirection, LoadedProgram, Pin, StateMachine,
     },
 };
-use embassy_time::{Duration, Timer};
-use libm::{logf, sqrtf};
-
-use crate::{ads7828::Ads7828, mlx90614::Mlx90614, state::MEASUREMENTS};
+use embassy_time::{Duration, Instant, Timer};
+use libm::{fabsf, sqrtf};
+
+use crate::{
+    board::{IrThermometer, SensorAdc},
+    channel_buffers::ChannelBuffers,
+    filter::{Ema, MedianEma},
+    mlx90614::ObjectChannel,
+    ntc::{steinhart_hart_temp, SteinhartHart, COIL_NTC, MODULE_NTC},
+    safety::raise_fault,
+    sampling::compute_adc_div,
+    state::{
+        mark_reported, update_measurements, CalPair, FaultCode, MeasurementSource, CALIBRATION,
+        COMMISSIONING, CONTROL_STATUS, CURRENT_PEAK_LIMIT_A,
+    },
+};
 
 const TARGET_SAMPLE_RATE_HZ: u32 = 150_000;
 const PAIRS_PER_BATCH: usize = 512;
 const DMA_BUFFER_LEN: usize = PAIRS_PER_BATCH * 2;
+/// Position of the DC bus voltage input in `adc_task`'s channel array, and so in every sampled
+/// pair: with two inputs the round-robin alternates starting from the first one listed.
+pub const ADC_VOLTAGE_SLOT: usize = 0;
+/// Position of the coil current input, as for [`ADC_VOLTAGE_SLOT`].
+pub const ADC_CURRENT_SLOT: usize = 1;
 const ADC_REF_V: f32 = 3.321;
+// Codes within this of either end of the 12-bit range count as railed.
+const ADC_RAIL_MARGIN: u16 = 8;
+// A batch with more than this fraction of railed samples on one input is not used. The bus
+// voltage input legitimately sits at 0 with the supply off, so only its top rail counts.
+const ADC_SATURATION_FRACTION: f32 = 0.5;
 const VDC_GAIN: f32 = 0.0018615088;
-const CURRENT_CENTER_V: f32 = 1.245; //1.252 in theory but measured slightly lower
+pub(crate) const CURRENT_CENTER_V: f32 = 1.245; //1.252 in theory but measured slightly lower
 const CURRENT_SENSITIVITY_A_PER_V: f32 = 1280.0; // 0.625 V -> 800 A
+
+// The hall sensor zero drifts as it warms up, so the center is re-estimated from batches
+// taken while the inverter is known to be off (after a short settle once heating stops).
+const CURRENT_ZERO_SETTLE: Duration = Duration::from_millis(200);
+const CURRENT_ZERO_TRACK_FACTOR: f32 = 0.05;
+const CURRENT_ZERO_MAX_DRIFT_V: f32 = 0.05; // ~64 A of apparent offset
+
+// A conversion takes 96 ADC clocks; a shorter divider period just runs back to back.
+const ADC_CYCLES_PER_CONVERSION: u32 = 96;
+// Below this the current waveform is mostly noise and zero crossings mean nothing.
+const FREQ_MIN_CURRENT_A: f32 = 10.0;
+// Crossings of the batch mean only count once the current gets this share of Irms past it, so
+// noise riding on the waveform near the mean cannot add extra ones.
+const FREQ_HYSTERESIS_FRACTION: f32 = 0.25;
+
 const POWER_SMOOTH_FACTOR: f32 = 0.2;
+// The MLX die follows the enclosure, not the work; it only needs to be roughly right.
+const AMBIENT_SMOOTH_FACTOR: f32 = 0.05;
+// Temperatures go through a short median first so one bad sample cannot trip a limit.
+const TEMP_SMOOTH_FACTOR: f32 = 0.2;
+const TEMP_MEDIAN_LEN: usize = 3;
+// ads_task scans all eight ADS7828 channels with this gap between scans and publishes their
+// averages once per update period, so each published temperature is the mean of several scans.
+// The averaged coil voltage still sits at the rail with the NTC unplugged, so the disconnect
+// check works on it unchanged.
+const ADS_SCAN_INTERVAL: Duration = Duration::from_millis(5);
+const ADS_UPDATE_PERIOD: Duration = Duration::from_millis(50);
+// After a failed scan the next one waits ADS_SCAN_INTERVAL doubled for every failure in a row,
+// up to ADS_BACKOFF_MAX, so an unplugged board is not hammered (or logged) every few ms. This
+// many failures in a row mark the ADS7828 unhealthy.
+const ADS_BACKOFF_MAX: Duration = Duration::from_secs(1);
+const ADS_UNHEALTHY_FAILURES: u8 = 5;
+// How long a measurement capture logs every ADC batch.
+const CAPTURE_WINDOW: Duration = Duration::from_secs(2);
+// Consecutive failed TOBJ2 reads before mlx_task gives up on the second zone.
+const MLX_ZONE2_FAILURE_LIMIT: u8 = 5;
+// Consecutive failed MLX reads before mlx_task re-initialises its I2C bus.
+const MLX_RECOVERY_FAILURES: u8 = 5;
+// Bus recoveries in a row without a good read before the object temperature is marked stale.
+const MLX_RECOVERY_ATTEMPTS: u8 = 3;
+// The thermopile reads far too low for a while after power-up. mlx_task throws away this many
+// object readings, then waits for this many in a row within the band of each other, none below
+// the floor, before it publishes anything. A sensor that has not settled within the timeout is
+// reported faulted, though it can still settle later.
+const MLX_COLD_START_DISCARD: u8 = 5;
+const MLX_COLD_START_TIMEOUT: Duration = Duration::from_secs(5);
+const MLX_STABLE_READS: u8 = 5;
+const MLX_STABLE_BAND_C: f32 = 1.0;
+const MLX_PLAUSIBLE_MIN_C: f32 = -20.0;
+
+// Filtered values only reach MEASUREMENTS once they move by more than these.
+const DC_VOLTAGE_DEADBAND_V: f32 = 1.0;
+const CURRENT_DEADBAND_A: f32 = 0.5;
+const POWER_DEADBAND_KW: f32 = 0.02;
+const POWER_FACTOR_DEADBAND: f32 = 0.01;
+const FREQ_DEADBAND_HZ: f32 = 100.0;
+const CURRENT_ZERO_DEADBAND_V: f32 = 0.001;
+const TEMP_DEADBAND_C: f32 = 0.2;
 const MAX_VOLTAGE_V: f32 = 1000.0;
 const MAX_CURRENT_A: f32 = 900.0;
 const PWM_MIN_DUTY: f32 = 0.05;
@@ -29,10 +111,62 @@ const PWM_LOW_DUTY: f32 = 0.10;
 const PWM_HIGH_DUTY: f32 = 0.88;
 const PWM_LOW_V: f32 = 0.6;
 const PWM_HIGH_V: f32 = 4.5;
-const MODULE_NTC_BETA: f32 = 3468.0;
-const MODULE_NTC_R0: f32 = 5_000.0;
-const MODULE_NTC_T0_C: f32 = 25.0;
-const COIL_SENSOR_DISCONNECT_V: f32 = 4.5;
+// The module's isolated encoder lowers the duty as VAIN rises; flip for encoders that don't.
+const MODULE_DUTY_INVERTED: bool = true;
+// An open or shorted module NTC drives the encoder outside its 10%..88% span.
+const MODULE_SENSOR_DUTY_MARGIN: f32 = 0.03;
+pub(crate) const COIL_SENSOR_DISCONNECT_V: f32 = 4.5;
+// Supply of the NTC dividers on the ADS7828 inputs.
+const NTC_DIVIDER_SUPPLY_V: f32 = 5.0;
+// A part cools by a few degrees per second at most; a drop this large between two 100 ms MLX
+// reads means the sensor is now looking past the part at the background.
+const PART_REMOVED_STEP_C: f32 = 25.0;
+
+/// Uptime in ms at which the running measurement capture ends; 0 while none is running.
+static CAPTURE_UNTIL_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Starts (or restarts) a measurement capture: for `CAPTURE_WINDOW`, `adc_task` logs the
+/// unfiltered values of every batch instead of leaving transients to the filters.
+pub fn start_capture() {
+    let until = (Instant::now() + CAPTURE_WINDOW).as_millis() as u32;
+    CAPTURE_UNTIL_MS.store(until.max(1), Ordering::Relaxed);
+}
+
+pub fn capture_active() -> bool {
+    let until = CAPTURE_UNTIL_MS.load(Ordering::Relaxed);
+    until != 0 && (Instant::now().as_millis() as u32) < until
+}
+
+/// Which ADS7828 inputs (single-ended, 0..7) the temperature dividers are wired to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdsChannelMap {
+    pub coil_ntc: u8,
+    pub pcb_temp: u8,
+}
+
+/// Sensor on the ADS7828's PCB temperature channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PcbSensor {
+    /// LM35-style linear output: 10 mV/°C with a 0.5 V offset at 0 °C.
+    Lm35,
+    /// NTC to ground with a `series` Ω pull-up to 5 V, like the coil sensor. `r0` is the
+    /// resistance at 25 °C.
+    Ntc { beta: f32, r0: f32, series: f32 },
+}
+
+impl PcbSensor {
+    /// PCB temperature for a channel voltage, or `None` for an NTC reading open or shorted.
+    fn temp_c(&self, voltage: f32) -> Option<f32> {
+        match *self {
+            PcbSensor::Lm35 => Some(((voltage - 0.5) / 0.01).clamp(-40.0, 150.0)),
+            PcbSensor::Ntc { beta, r0, series } => {
+                divider_resistance(voltage, series).map(|resistance| {
+                    steinhart_hart_temp(resistance, &SteinhartHart::from_beta(beta, r0))
+                })
+            }
+        }
+    }
+}
 
 pub fn load_sic_temp_program<'d>(common: &mut Common<'d, PIO0>) -> LoadedProgram<'d, PIO0> {
     let prg = pio_asm!(
@@ -80,6 +214,90 @@ pub fn init_sic_temp_capture<'d>(
     sm
 }
 
+/// Hardware overcurrent trip (`pio-overcurrent` feature). An external comparator watches the
+/// current sensor output and drives GPIO2 high while the coil current is above its threshold;
+/// it has to be push-pull and 3.3 V, or open-collector with a pull-up to 3.3 V and the inputs
+/// arranged so the output releases on overcurrent. The pin's pull-down keeps a disconnected
+/// comparator from tripping, so check it during commissioning.
+///
+/// The state machine pushes a non-zero word a few cycles after the pin goes high and a zero once
+/// it is low again; `estop_task` waits on its RX FIFO next to the interlock. 6 instructions, which
+/// only fit next to the SiC capture without the Modbus UART (which also wants GPIO2).
+pub fn load_overcurrent_program<'d>(common: &mut Common<'d, PIO0>) -> LoadedProgram<'d, PIO0> {
+    let prg = pio_asm!(
+        ".wrap_target",
+        "wait 1 pin 0",
+        "mov isr, ~null",
+        "push block",
+        "wait 0 pin 0",
+        "mov isr, null",
+        "push block",
+        ".wrap"
+    );
+
+    common.load_program(&prg.program)
+}
+
+pub fn init_overcurrent_capture<'d>(
+    program: &LoadedProgram<'d, PIO0>,
+    mut sm: StateMachine<'d, PIO0, 3>,
+    mut pin: Pin<'d, PIO0>,
+) -> StateMachine<'d, PIO0, 3> {
+    pin.set_pull(Pull::Down);
+    sm.set_pin_dirs(PioDirection::In, &[&pin]);
+
+    let mut cfg = pio::Config::default();
+    cfg.use_program(program, &[]);
+    cfg.set_in_pins(&[&pin]);
+    sm.set_config(&cfg);
+    sm
+}
+
+fn coil_current_a(sample: u16, center_v: f32) -> f32 {
+    let i_adc = sample as f32 * (ADC_REF_V / 4095.0);
+    ((i_adc - center_v) * CURRENT_SENSITIVITY_A_PER_V).clamp(-MAX_CURRENT_A, MAX_CURRENT_A)
+}
+
+/// Fundamental of the coil current in an interleaved V/I batch, from its rising crossings of
+/// `mean_a`. A crossing only counts once the current has been below `mean_a - hysteresis_a`
+/// and then rises past `mean_a + hysteresis_a`. Timing rising crossings only keeps the band's
+/// offset out of the period. 0 with fewer than two of them.
+fn crossing_freq_hz(
+    buffer: &[u16],
+    center_v: f32,
+    current_cal: CalPair,
+    mean_a: f32,
+    hysteresis_a: f32,
+    pair_rate_hz: f32,
+) -> f32 {
+    let mut above: Option<bool> = None;
+    let mut rising = 0u32;
+    let mut first_rising = 0usize;
+    let mut last_rising = 0usize;
+
+    for (index, pair) in buffer.chunks_exact(2).enumerate() {
+        let current = current_cal.apply(coil_current_a(pair[ADC_CURRENT_SLOT], center_v)) - mean_a;
+        if current > hysteresis_a {
+            if above == Some(false) {
+                if rising == 0 {
+                    first_rising = index;
+                }
+                last_rising = index;
+                rising += 1;
+            }
+            above = Some(true);
+        } else if current < -hysteresis_a {
+            above = Some(false);
+        }
+    }
+
+    if rising < 2 || last_rising == first_rising {
+        return 0.0;
+    }
+    let span_s = (last_rising - first_rising) as f32 / pair_rate_hz;
+    (rising - 1) as f32 / span_s
+}
+
 #[embassy_executor::task]
 pub async fn adc_task(
     adc: &'static mut Adc<'static, Async>,
@@ -87,18 +305,27 @@ pub async fn adc_task(
     mut dma: PeripheralRef<'static, embassy_rp::peripherals::DMA_CH0>,
 ) {
     static mut DMA_BUFFER: [u16; DMA_BUFFER_LEN] = [0; DMA_BUFFER_LEN];
-    let div = 0;
-    // let mut div = if channel_count == 0 {
-    //     0
-    // } else {
-    //     adc_clk
-    //         .saturating_div(TARGET_SAMPLE_RATE_HZ.saturating_mul(channel_count))
-    //         .saturating_sub(1)
-    // };
-    // if div > u16::MAX as u32 {
-    //     div = u16::MAX as u32;
-    // }
-    // let div = div as u16;
+    let mut current_center_v = CURRENT_CENTER_V;
+    let mut inverter_off_since: Option<Instant> = None;
+    let mut zero_drift_reported = false;
+    let mut vdc_filter = Ema::new(POWER_SMOOTH_FACTOR);
+    let mut irms_filter = Ema::new(POWER_SMOOTH_FACTOR);
+    let mut power_filter = Ema::new(POWER_SMOOTH_FACTOR);
+    let mut apparent_filter = Ema::new(POWER_SMOOTH_FACTOR);
+    let mut pf_filter = Ema::new(POWER_SMOOTH_FACTOR);
+    let mut capture_batches: Option<u32> = None;
+    let mut saturation_reported = false;
+    let adc_clk = clocks::clk_adc_freq();
+    let channel_count = channels.len() as u32;
+    let div = compute_adc_div(adc_clk, TARGET_SAMPLE_RATE_HZ, channel_count);
+    let pair_rate_hz = adc_clk as f32
+        / ((div as u32 + 1).max(ADC_CYCLES_PER_CONVERSION) * channel_count.max(1)) as f32;
+    info!(
+        "ADC div {}: {} Hz per channel, {} ms RMS window",
+        div,
+        pair_rate_hz,
+        PAIRS_PER_BATCH as f32 * 1000.0 / pair_rate_hz
+    );
 
     loop {
         let buffer = unsafe { &mut DMA_BUFFER };
@@ -112,92 +339,404 @@ pub async fn adc_task(
         }
 
         let mut sum_v_sq = 0.0f32;
-        let mut sum_i_sq = 0.0f32;
+        // Welford running mean/variance, so a DC offset left by a slightly wrong
+        // current_center_v doesn't end up in the RMS.
+        let mut i_mean = 0.0f32;
+        let mut i_m2 = 0.0f32;
         let mut sum_vi = 0.0f32;
+        let mut sum_i_adc = 0.0f32;
+        let mut peak_current_a = 0.0f32;
+        let mut railed_v = 0usize;
+        let mut railed_i = 0usize;
+        let cal = *CALIBRATION.lock().await;
+
+        for (index, pair) in buffer.chunks_exact(2).enumerate() {
+            let (v_code, i_code) = (pair[ADC_VOLTAGE_SLOT], pair[ADC_CURRENT_SLOT]);
+            if v_code >= 4095 - ADC_RAIL_MARGIN {
+                railed_v += 1;
+            }
+            if i_code <= ADC_RAIL_MARGIN || i_code >= 4095 - ADC_RAIL_MARGIN {
+                railed_i += 1;
+            }
 
-        for pair in buffer.chunks_exact(2) {
-            let v_sample = pair[0] as f32;
-            let i_sample = pair[1] as f32;
-
-            let v_adc = v_sample * (ADC_REF_V / 4095.0);
-            let i_adc = i_sample * (ADC_REF_V / 4095.0);
+            let v_adc = v_code as f32 * (ADC_REF_V / 4095.0);
+            let i_adc = i_code as f32 * (ADC_REF_V / 4095.0);
 
-            let dc_voltage = (v_adc / VDC_GAIN).clamp(0.0, MAX_VOLTAGE_V);
-            let coil_current = ((i_adc - CURRENT_CENTER_V) * CURRENT_SENSITIVITY_A_PER_V)
-                .clamp(-MAX_CURRENT_A, MAX_CURRENT_A);
+            let dc_voltage = cal
+                .dc_voltage
+                .apply(v_adc / VDC_GAIN)
+                .clamp(0.0, MAX_VOLTAGE_V);
+            let coil_current = cal
+                .coil_current
+                .apply(coil_current_a(i_code, current_center_v));
+            sum_i_adc += i_adc;
+            peak_current_a = peak_current_a.max(fabsf(coil_current));
 
             sum_v_sq += dc_voltage * dc_voltage;
-            sum_i_sq += coil_current * coil_current;
+            let delta = coil_current - i_mean;
+            i_mean += delta / (index + 1) as f32;
+            i_m2 += delta * (coil_current - i_mean);
             sum_vi += dc_voltage * coil_current;
         }
 
+        // Fast path, not waiting for the RMS filter or the next safety pass.
+        if peak_current_a > CURRENT_PEAK_LIMIT_A {
+            raise_fault(FaultCode::CurrentLimit, peak_current_a).await;
+        }
+
         let samples = PAIRS_PER_BATCH as f32;
+        let saturated = railed_v as f32 > samples * ADC_SATURATION_FRACTION
+            || railed_i as f32 > samples * ADC_SATURATION_FRACTION;
+        if saturated {
+            if !saturation_reported {
+                warn!(
+                    "ADC input railed: {} voltage, {} current samples of {}",
+                    railed_v, railed_i, PAIRS_PER_BATCH
+                );
+            }
+            saturation_reported = true;
+            // RMS of a clipped waveform looks plausible but is wrong; keep the last good values.
+            update_measurements(|meas| publish_flag(&mut meas.adc_saturated, true));
+            mark_reported(MeasurementSource::Adc);
+            Timer::after(Duration::from_millis(50)).await;
+            continue;
+        }
+        saturation_reported = false;
         let vrms = sqrtf((sum_v_sq / samples).max(0.0));
-        let irms = sqrtf((sum_i_sq / samples).max(0.0));
+        let irms = sqrtf((i_m2 / samples).max(0.0));
+        debug!("Coil current DC offset: {} A", i_mean);
         let power_kw = ((sum_vi / samples) / 1000.0).clamp(0.0, 20.0);
-        info!("Vdc: {} V, Irms: {} A, P: {} kW", vrms, irms, power_kw);
-        {
-            let mut guard = MEASUREMENTS.lock().await;
-            guard.dc_voltage_v = smooth_value(guard.dc_voltage_v, vrms);
-            guard.coil_current_rms_a = smooth_value(guard.coil_current_rms_a, irms);
-            guard.coil_power_kw = smooth_value(guard.coil_power_kw, power_kw);
-            guard.valid = true;
+        let apparent_power_kw = vrms * irms / 1000.0;
+        let power_factor = if apparent_power_kw > 0.0 {
+            (power_kw / apparent_power_kw).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let measured_freq_hz = if irms >= FREQ_MIN_CURRENT_A {
+            crossing_freq_hz(
+                buffer,
+                current_center_v,
+                cal.coil_current,
+                i_mean,
+                irms * FREQ_HYSTERESIS_FRACTION,
+                pair_rate_hz,
+            )
+        } else {
+            0.0
+        };
+        info!(
+            "Vdc: {} V, Irms: {} A, P: {} kW, S: {} kVA, PF: {}, f: {} Hz",
+            vrms, irms, power_kw, apparent_power_kw, power_factor, measured_freq_hz
+        );
+        if capture_active() {
+            let batch = capture_batches.get_or_insert(0);
+            info!(
+                "Capture {}: Vdc {} V, Irms {} A, Ipk {} A, P {} kW, f {} Hz",
+                batch, vrms, irms, peak_current_a, power_kw, measured_freq_hz
+            );
+            *batch += 1;
+        } else if let Some(batches) = capture_batches.take() {
+            CAPTURE_UNTIL_MS.store(0, Ordering::Relaxed);
+            info!("Capture done: {} batches", batches);
         }
+
+        let heating = CONTROL_STATUS.lock().await.heating_enabled;
+        if heating {
+            inverter_off_since = None;
+        } else {
+            let now = Instant::now();
+            let off_since = *inverter_off_since.get_or_insert(now);
+            if now.saturating_duration_since(off_since) >= CURRENT_ZERO_SETTLE {
+                let batch_zero_v = sum_i_adc / samples;
+                current_center_v += CURRENT_ZERO_TRACK_FACTOR * (batch_zero_v - current_center_v);
+            }
+        }
+        let zero_drift_fault =
+            fabsf(current_center_v - CURRENT_CENTER_V) > CURRENT_ZERO_MAX_DRIFT_V;
+        if zero_drift_fault && !zero_drift_reported {
+            warn!(
+                "Current sensor zero drifted to {} V (nominal {} V)",
+                current_center_v, CURRENT_CENTER_V
+            );
+        }
+        zero_drift_reported = zero_drift_fault;
+
+        let vdc_filtered = vdc_filter.update(vrms);
+        let irms_filtered = irms_filter.update(irms);
+        let power_filtered = power_filter.update(power_kw);
+        let apparent_filtered = apparent_filter.update(apparent_power_kw);
+        let pf_filtered = pf_filter.update(power_factor);
+        update_measurements(|meas| {
+            let mut changed = publish(&mut meas.dc_voltage_v, vdc_filtered, DC_VOLTAGE_DEADBAND_V);
+            changed |= publish(
+                &mut meas.coil_current_rms_a,
+                irms_filtered,
+                CURRENT_DEADBAND_A,
+            );
+            changed |= publish(&mut meas.coil_power_kw, power_filtered, POWER_DEADBAND_KW);
+            changed |= publish(
+                &mut meas.apparent_power_kw,
+                apparent_filtered,
+                POWER_DEADBAND_KW,
+            );
+            changed |= publish(&mut meas.power_factor, pf_filtered, POWER_FACTOR_DEADBAND);
+            changed |= publish(
+                &mut meas.measured_freq_hz,
+                measured_freq_hz,
+                FREQ_DEADBAND_HZ,
+            );
+            changed |= publish(
+                &mut meas.current_zero_v,
+                current_center_v,
+                CURRENT_ZERO_DEADBAND_V,
+            );
+            changed |= publish_flag(&mut meas.current_zero_drift_fault, zero_drift_fault);
+            changed |= publish_flag(&mut meas.adc_saturated, false);
+            changed | publish_flag(&mut meas.valid, true)
+        });
+        mark_reported(MeasurementSource::Adc);
         Timer::after(Duration::from_millis(50)).await;
     }
 }
 
 #[embassy_executor::task]
-pub async fn ads_task(ads: &'static Ads7828<'static>) {
+pub async fn ads_task(ads: &'static SensorAdc, channels: AdsChannelMap, pcb_sensor: PcbSensor) {
+    let mut coil_filter = MedianEma::<TEMP_MEDIAN_LEN>::new(TEMP_SMOOTH_FACTOR);
+    let mut pcb_filter = MedianEma::<TEMP_MEDIAN_LEN>::new(TEMP_SMOOTH_FACTOR);
+    let mut buffers = ChannelBuffers::new();
+    let mut next_update = Instant::now() + ADS_UPDATE_PERIOD;
+    let mut failures = 0u8;
+
     loop {
-        match ads.get_channels(false).await {
+        match ads.get_channels().await {
             Ok(raw) => {
-                let coil_temp_v = code_to_voltage(raw[6]);
-                let pcb_temp_v = code_to_voltage(raw[3]);
-
-                let coil_temp_c = ntc_pullup_temp(coil_temp_v);
-                let pcb_temp_c = pcb_temp_v_to_c(pcb_temp_v);
-                let coil_disconnected = coil_temp_v >= COIL_SENSOR_DISCONNECT_V;
-
-                {
-                    let mut guard = MEASUREMENTS.lock().await;
-                    guard.coil_temp_disconnected = coil_disconnected;
-                    if !coil_disconnected {
-                        guard.coil_temp_c = smooth_value(guard.coil_temp_c, coil_temp_c);
-                    }
-                    guard.pcb_temp_c = smooth_value(guard.pcb_temp_c, pcb_temp_c);
-                    info!(
-                        "Coil temp: {} C{}, PCB temp: {} C",
-                        coil_temp_c,
-                        if coil_disconnected {
-                            " (disconnected)"
-                        } else {
-                            ""
-                        },
-                        pcb_temp_c
+                if failures > 0 {
+                    info!("ADS7828 back after {} failed scans", failures);
+                    failures = 0;
+                    update_measurements(|meas| publish_flag(&mut meas.ads_healthy, true));
+                }
+                buffers.add_samples(&raw);
+            }
+            Err(_e) => {
+                failures = failures.saturating_add(1);
+                if failures == 1 {
+                    warn!("ADS7828 error, backing off");
+                } else if failures == ADS_UNHEALTHY_FAILURES {
+                    warn!(
+                        "ADS7828 failed {} scans in a row, coil and PCB temperatures unavailable",
+                        failures
                     );
+                    update_measurements(|meas| publish_flag(&mut meas.ads_healthy, false));
                 }
+                let backoff = ADS_SCAN_INTERVAL * (1u32 << failures.min(8));
+                Timer::after(backoff.min(ADS_BACKOFF_MAX)).await;
+                continue;
             }
-            Err(_e) => warn!("ADS7828 error"),
         }
+        // A scan takes several milliseconds of bus time, so the scans are paced by a sleep
+        // after each rather than a ticker that slow scans would overrun back to back.
+        if Instant::now() < next_update {
+            Timer::after(ADS_SCAN_INTERVAL).await;
+            continue;
+        }
+        next_update = Instant::now() + ADS_UPDATE_PERIOD;
 
-        Timer::after(Duration::from_millis(50)).await;
+        let averages = (
+            buffers.read_and_clear(channels.coil_ntc as usize),
+            buffers.read_and_clear(channels.pcb_temp as usize),
+        );
+        buffers.clear();
+        if let (Some(coil_code), Some(pcb_code)) = averages {
+            let coil_temp_v = ads.code_to_voltage(coil_code);
+            let pcb_temp_v = ads.code_to_voltage(pcb_code);
+
+            let coil_temp_c = CALIBRATION
+                .lock()
+                .await
+                .coil_temp
+                .apply(ntc_pullup_temp(coil_temp_v));
+            let pcb_offset_c = COMMISSIONING.lock().await.pcb_temp_offset_c;
+            let pcb_temp_c = pcb_sensor.temp_c(pcb_temp_v).map(|t| t + pcb_offset_c);
+            let coil_disconnected = coil_temp_v >= COIL_SENSOR_DISCONNECT_V;
+            let pcb_disconnected = pcb_temp_c.is_none();
+
+            if !coil_disconnected {
+                coil_filter.update(coil_temp_c);
+            }
+            if let Some(pcb_temp_c) = pcb_temp_c {
+                pcb_filter.update(pcb_temp_c);
+            }
+            let coil_filtered = coil_filter.value();
+            let pcb_filtered = pcb_filter.value();
+            update_measurements(|meas| {
+                publish_flag(&mut meas.coil_temp_disconnected, coil_disconnected)
+                    | publish_flag(&mut meas.pcb_temp_disconnected, pcb_disconnected)
+                    | publish(&mut meas.coil_temp_c, coil_filtered, TEMP_DEADBAND_C)
+                    | publish(&mut meas.pcb_temp_c, pcb_filtered, TEMP_DEADBAND_C)
+            });
+            mark_reported(MeasurementSource::Ads7828);
+            info!(
+                "Coil temp: {} C{}, PCB temp: {} C{}",
+                coil_temp_c,
+                if coil_disconnected {
+                    " (disconnected)"
+                } else {
+                    ""
+                },
+                pcb_filtered,
+                if pcb_disconnected {
+                    " (sensor fault)"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        Timer::after(ADS_SCAN_INTERVAL).await;
+    }
+}
+
+/// Holds the object temperature back until the MLX90614 has settled after power-up.
+struct MlxColdStart {
+    discarded: u8,
+    stable: u8,
+    last: Option<f32>,
+    started: Instant,
+    gave_up: bool,
+}
+
+impl MlxColdStart {
+    fn new() -> Self {
+        Self {
+            discarded: 0,
+            stable: 0,
+            last: None,
+            started: Instant::now(),
+            gave_up: false,
+        }
+    }
+
+    /// True once, when `MLX_COLD_START_TIMEOUT` has passed without the readings settling.
+    fn timed_out(&mut self) -> bool {
+        if self.gave_up || self.started.elapsed() < MLX_COLD_START_TIMEOUT {
+            return false;
+        }
+        self.gave_up = true;
+        true
+    }
+
+    /// Feeds one object reading; true once the readings can be trusted.
+    fn settled(&mut self, t: f32) -> bool {
+        if self.discarded < MLX_COLD_START_DISCARD {
+            self.discarded += 1;
+            return false;
+        }
+        if t < MLX_PLAUSIBLE_MIN_C {
+            self.stable = 0;
+            self.last = None;
+            return false;
+        }
+        let steady = self
+            .last
+            .is_some_and(|last| fabsf(t - last) <= MLX_STABLE_BAND_C);
+        self.stable = if steady { self.stable + 1 } else { 1 };
+        self.last = Some(t);
+        self.stable >= MLX_STABLE_READS
     }
 }
 
 #[embassy_executor::task]
-pub async fn mlx_task(
-    mut mlx: Mlx90614<'static, embassy_rp::peripherals::I2C0, embassy_rp::i2c::Blocking>,
-) {
+pub async fn mlx_task(mut mlx: IrThermometer, mut channel: ObjectChannel, i2c_config: I2cConfig) {
+    let mut last_reading: Option<f32> = None;
+    let mut zone2_failures = 0u8;
+    let mut read_failures = 0u8;
+    let mut recoveries = 0u8;
+    let mut object_filter = MedianEma::<TEMP_MEDIAN_LEN>::new(TEMP_SMOOTH_FACTOR);
+    let mut ambient_filter = Ema::new(AMBIENT_SMOOTH_FACTOR);
+    let mut cold_start = Some(MlxColdStart::new());
+
     loop {
-        match mlx.read_object_temp().await {
-            Ok(t) => {
-                let mut guard = MEASUREMENTS.lock().await;
-                guard.object_temp_c = smooth_value(guard.object_temp_c, t);
+        if cold_start.as_mut().is_some_and(MlxColdStart::timed_out) {
+            warn!("MLX90614 has not settled since power-up, object temperature faulted");
+            update_measurements(|meas| publish_flag(&mut meas.object_temp_settling, false));
+        }
+        let reading = mlx.read_both(channel).await;
+        // A single-zone part cannot serve TOBJ2; rather than go blind, carry on with TOBJ1.
+        if channel == ObjectChannel::Object2 {
+            zone2_failures = match reading {
+                Ok(Some(_)) => 0,
+                _ => zone2_failures.saturating_add(1),
+            };
+            if zone2_failures >= MLX_ZONE2_FAILURE_LIMIT {
+                warn!("MLX90614 object 2 unreadable, single-zone part? Using object 1");
+                channel = ObjectChannel::Object1;
+            }
+        }
+        match reading {
+            Ok(Some((ambient, raw_t))) => {
+                read_failures = 0;
+                recoveries = 0;
+                let t = CALIBRATION.lock().await.object_temp.apply(raw_t);
+                let ambient_filtered = ambient_filter.update(ambient);
+                if cold_start.as_mut().is_some_and(|warmup| !warmup.settled(t)) {
+                    update_measurements(|meas| {
+                        publish(&mut meas.ambient_temp_c, ambient_filtered, TEMP_DEADBAND_C)
+                    });
+                    info!("IR object temp {} C, waiting for the sensor to settle", t);
+                    mark_reported(MeasurementSource::Mlx90614);
+                    Timer::after(Duration::from_millis(100)).await;
+                    continue;
+                }
+                if cold_start.take().is_some() {
+                    info!("IR object temp settled at {} C", t);
+                }
+                let removed = last_reading.is_some_and(|last| last - t > PART_REMOVED_STEP_C);
+                last_reading = Some(t);
+                // The smoothed history belonged to the part that is gone.
+                let object_filtered = if removed {
+                    object_filter.reset(t)
+                } else {
+                    object_filter.update(t)
+                };
+                update_measurements(|meas| {
+                    publish_flag(&mut meas.object_temp_valid, true)
+                        | publish_flag(&mut meas.object_temp_settling, false)
+                        | publish_flag(&mut meas.object_removed, removed)
+                        | publish(&mut meas.object_temp_c, object_filtered, TEMP_DEADBAND_C)
+                        | publish(&mut meas.ambient_temp_c, ambient_filtered, TEMP_DEADBAND_C)
+                });
+                if removed {
+                    warn!("IR object temp dropped to {} C, part removed?", t);
+                }
                 info!("IR object temp: {} C", t);
             }
-            Err(_e) => warn!("MLX90614 read error"),
+            Ok(None) => warn!("MLX90614 flagged the object reading invalid"),
+            Err(_e) => {
+                warn!("MLX90614 read error");
+                read_failures += 1;
+                if read_failures >= MLX_RECOVERY_FAILURES {
+                    read_failures = 0;
+                    recoveries = recoveries.saturating_add(1);
+                    warn!(
+                        "MLX90614 unreadable, re-initialising I2C (attempt {})",
+                        recoveries
+                    );
+                    mlx.recover_bus(&i2c_config).await;
+                    if recoveries == MLX_RECOVERY_ATTEMPTS {
+                        warn!("MLX90614 still unreadable, object temperature is stale");
+                    }
+                    if recoveries >= MLX_RECOVERY_ATTEMPTS {
+                        // Past its recoveries the sensor is faulted, settled or not.
+                        update_measurements(|meas| {
+                            publish_flag(&mut meas.object_temp_valid, false)
+                                | publish_flag(&mut meas.object_temp_settling, false)
+                        });
+                    }
+                }
+            }
         }
+        // Failed reads count too: those are handled above, this only says the task is alive.
+        mark_reported(MeasurementSource::Mlx90614);
         Timer::after(Duration::from_millis(100)).await;
     }
 }
@@ -207,6 +746,7 @@ pub async fn sic_temp_task(mut sm: StateMachine<'static, PIO0, 0>) {
     const SAMPLES: usize = 128;
 
     sm.set_enable(true);
+    let mut module_filter = MedianEma::<TEMP_MEDIAN_LEN>::new(TEMP_SMOOTH_FACTOR);
 
     loop {
         let mut duty_sum = 0.0f32;
@@ -218,74 +758,94 @@ pub async fn sic_temp_task(mut sm: StateMachine<'static, PIO0, 0>) {
             let low_cycles = sm.rx().wait_pull().await as f32;
             let total = high_cycles + low_cycles;
             if total > 0.0 {
-                let duty = (high_cycles / total).clamp(PWM_MIN_DUTY, PWM_MAX_DUTY);
-                duty_sum += duty;
+                duty_sum += high_cycles / total;
                 collected += 1;
             }
         }
 
-        let duty = (duty_sum / SAMPLES as f32).clamp(PWM_MIN_DUTY, PWM_MAX_DUTY);
+        let raw_duty = duty_sum / SAMPLES as f32;
+        let disconnected = raw_duty < PWM_LOW_DUTY - MODULE_SENSOR_DUTY_MARGIN
+            || raw_duty > PWM_HIGH_DUTY + MODULE_SENSOR_DUTY_MARGIN;
+        let duty = raw_duty.clamp(PWM_MIN_DUTY, PWM_MAX_DUTY);
         let voltage = duty_to_voltage(duty);
         let resistance = (voltage / 0.000203) - 5100.0; // 5.1k in series with current source to stay within 0.6-4.5V range
-        let module_temp_c = ntc_beta_temp(resistance);
+        let module_temp_c = CALIBRATION
+            .lock()
+            .await
+            .module_temp
+            .apply(module_ntc_temp(resistance));
 
-        {
-            let mut guard = MEASUREMENTS.lock().await;
-            guard.module_temp_c = smooth_value(guard.module_temp_c, module_temp_c);
+        if !disconnected {
+            module_filter.update(module_temp_c);
         }
+        let module_filtered = module_filter.value();
+        update_measurements(|meas| {
+            publish_flag(&mut meas.module_temp_disconnected, disconnected)
+                | publish(&mut meas.module_temp_c, module_filtered, TEMP_DEADBAND_C)
+        });
+        mark_reported(MeasurementSource::SicModule);
         info!(
-            "SiC module temp: duty {} resistance {} temp {} C",
-            duty, resistance, module_temp_c
+            "SiC module temp: duty {} resistance {} temp {} C{}",
+            raw_duty,
+            resistance,
+            module_temp_c,
+            if disconnected { " (sensor fault)" } else { "" }
         );
 
         Timer::after(Duration::from_millis(500)).await;
     }
 }
 
-fn smooth_value(previous: f32, new_value: f32) -> f32 {
-    if !previous.is_finite() || previous == 0.0 {
-        new_value
+/// Copies `value` into the published `slot` if it moved by more than `deadband`.
+fn publish(slot: &mut f32, value: f32, deadband: f32) -> bool {
+    if fabsf(value - *slot) > deadband {
+        *slot = value;
+        true
     } else {
-        previous + POWER_SMOOTH_FACTOR * (new_value - previous)
+        false
     }
 }
 
-fn code_to_voltage(code: u16) -> f32 {
-    (code as f32 / 4095.0) * 5.0
+fn publish_flag(slot: &mut bool, value: bool) -> bool {
+    let changed = *slot != value;
+    *slot = value;
+    changed
 }
 
 fn ntc_pullup_temp(voltage: f32) -> f32 {
     const SERIES_R: f32 = 10_000.0;
-    const BETA: f32 = 3950.0;
-    const R0: f32 = 10_000.0;
-    const T0_K: f32 = 298.15;
 
-    if voltage <= 0.01 || voltage >= 4.99 {
-        return 0.0;
+    match divider_resistance(voltage, SERIES_R) {
+        Some(resistance) => steinhart_hart_temp(resistance, &COIL_NTC),
+        None => 0.0,
     }
-
-    let resistance = SERIES_R * voltage / (5.0 - voltage);
-    let inv_t = 1.0 / T0_K + logf(resistance / R0) / BETA;
-    1.0 / inv_t - 273.15
 }
 
-fn pcb_temp_v_to_c(voltage: f32) -> f32 {
-    ((voltage - 0.5) / 0.01).clamp(-40.0, 150.0)
+/// Resistance of an NTC to ground under a `series_r` pull-up, or `None` with the input at
+/// either rail (sensor open or shorted).
+fn divider_resistance(voltage: f32, series_r: f32) -> Option<f32> {
+    if voltage <= 0.01 || voltage >= NTC_DIVIDER_SUPPLY_V - 0.01 {
+        return None;
+    }
+    Some(series_r * voltage / (NTC_DIVIDER_SUPPLY_V - voltage))
 }
 
 fn duty_to_voltage(duty: f32) -> f32 {
     // Datasheet: duty grows from 10%->88% while VAIN drops 4.5 V->0.6 V (linear mapping).
     let duty = duty.clamp(PWM_LOW_DUTY, PWM_HIGH_DUTY);
     let duty_span = PWM_HIGH_DUTY - PWM_LOW_DUTY;
-    let decreasing_ratio = (PWM_HIGH_DUTY - duty) / duty_span;
-    PWM_LOW_V + decreasing_ratio * (PWM_HIGH_V - PWM_LOW_V)
+    let ratio = (duty - PWM_LOW_DUTY) / duty_span;
+    let ratio = if MODULE_DUTY_INVERTED {
+        1.0 - ratio
+    } else {
+        ratio
+    };
+    PWM_LOW_V + ratio * (PWM_HIGH_V - PWM_LOW_V)
 }
 
-fn ntc_beta_temp(resistance: f32) -> f32 {
+fn module_ntc_temp(resistance: f32) -> f32 {
     if resistance <= 10.0 {
         return 0.0;
     }
-    let t0_k = MODULE_NTC_T0_C + 273.15;
-    let inv_t = 1.0 / t0_k + logf(resistance / MODULE_NTC_R0) / MODULE_NTC_BETA;
-    1.0 / inv_t - 273.15
+    steinhart_hart_temp(resistance, &MODULE_NTC)
 }
@@ -0,0 +1,397 @@
+//! Operator settings and the other values kept in the configuration block.
+//!
+//! Plain data with no hardware behind it, so [`crate::config`] can encode it on any target.
+//! The firmware keeps the live copies in `state`, which re-exports everything here.
+
+// The `const fn new()` constructors initialise the statics in `state`; a `Default` impl beside
+// each would only be a second spelling of it.
+#![allow(clippy::new_without_default)]
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlMode {
+    Idle,
+    ManualPower,
+    Temperature,
+    Cooldown,
+    /// One-shot low-duty sweep for the tank resonance; see `state::TuneState`.
+    AutoTune,
+    /// Bench characterisation: the PWM runs at `ControlSettings::manual_freq_hz` with the power
+    /// loop bypassed. Only offered from the engineering menu.
+    ManualFrequency,
+}
+
+impl ControlMode {
+    /// Modes the run button starts and stops, and that only heat while a run is active.
+    pub const fn is_heating(self) -> bool {
+        matches!(
+            self,
+            ControlMode::ManualPower | ControlMode::Temperature | ControlMode::ManualFrequency
+        )
+    }
+}
+
+/// What the run button does while no heating mode is selected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleRunAction {
+    /// Acknowledge the press with a chirp and do nothing else.
+    Ignore,
+    /// Switch to `last_run_mode` and start running straight away.
+    StartLastMode,
+}
+
+/// Unit temperatures are shown in. Everything is stored and controlled in °C; the commissioning
+/// wizard also stays in °C.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TempUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl TempUnit {
+    /// `celsius` in this unit, for display.
+    pub fn from_celsius(self, celsius: f32) -> f32 {
+        match self {
+            TempUnit::Celsius => celsius,
+            TempUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    /// A value in this unit back in °C.
+    pub fn to_celsius(self, value: f32) -> f32 {
+        match self {
+            TempUnit::Celsius => value,
+            TempUnit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+        }
+    }
+
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            TempUnit::Celsius => "C",
+            TempUnit::Fahrenheit => "F",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ControlSettings {
+    pub mode: ControlMode,
+    pub manual_power_kw: f32,
+    pub target_temp_c: f32,
+    /// Keep servoing at the target instead of stopping and prompting for cooldown.
+    pub hold_at_target: bool,
+    /// Minimum power kept on while holding or soaking at target in temperature mode; 0
+    /// disables the floor.
+    pub power_floor_kw: f32,
+    pub idle_run_action: IdleRunAction,
+    /// Heating mode most recently selected from the menu.
+    pub last_run_mode: ControlMode,
+    /// Set by holding Enter on a status screen; the run button only starts heating while this
+    /// is set. Used up by the start, and cleared by any fault or mode change. Never stored.
+    pub armed: bool,
+    pub temp_unit: TempUnit,
+    /// Heating stops and the head goes to cooldown after this many seconds of a run; 0 leaves
+    /// only the control task's max runtime. Set by loading a [`Profile`].
+    pub run_time_limit_s: u16,
+    /// Switching frequency in `ControlMode::ManualFrequency`. Never stored.
+    pub manual_freq_hz: f32,
+    /// Temperature mode keeps servoing at the target for this many seconds once it is reached,
+    /// before `hold_at_target` decides what happens next; 0 for no soak.
+    pub soak_s: u16,
+    /// Tank resonance found by the last completed auto-tune sweep; the power loop starts from
+    /// here instead of its built-in frequency. `None` until a sweep has completed.
+    pub resonant_freq_hz: Option<f32>,
+}
+
+impl ControlSettings {
+    pub const fn new() -> Self {
+        Self {
+            mode: ControlMode::ManualPower,
+            manual_power_kw: 5.0,
+            target_temp_c: 120.0,
+            hold_at_target: false,
+            power_floor_kw: 0.0,
+            idle_run_action: IdleRunAction::Ignore,
+            last_run_mode: ControlMode::ManualPower,
+            armed: false,
+            temp_unit: TempUnit::Celsius,
+            run_time_limit_s: 0,
+            manual_freq_hz: 40_000.0,
+            soak_s: 0,
+            resonant_freq_hz: None,
+        }
+    }
+}
+
+pub const PROFILE_COUNT: usize = 4;
+pub const PROFILE_NAME_LEN: usize = 8;
+
+/// A named heating preset: the mode and its setpoint, and an optional run-time limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Profile {
+    /// ASCII, padded with spaces.
+    pub name: [u8; PROFILE_NAME_LEN],
+    /// `ManualPower` or `Temperature`.
+    pub mode: ControlMode,
+    pub manual_power_kw: f32,
+    pub target_temp_c: f32,
+    pub run_time_limit_s: u16,
+}
+
+impl Profile {
+    /// Preset in the first slot until the operator overwrites it: the power-on settings.
+    pub const DEFAULT: Profile = Profile::from_settings(*b"Default ", &ControlSettings::new());
+
+    /// A preset holding the heating mode and setpoints of `settings`.
+    pub const fn from_settings(name: [u8; PROFILE_NAME_LEN], settings: &ControlSettings) -> Self {
+        Self {
+            name,
+            mode: settings.last_run_mode,
+            manual_power_kw: settings.manual_power_kw,
+            target_temp_c: settings.target_temp_c,
+            run_time_limit_s: settings.run_time_limit_s,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name).unwrap_or("?").trim_end()
+    }
+
+    /// Copies the preset into `settings`; the caller switches to its mode.
+    pub fn apply(&self, settings: &mut ControlSettings) {
+        settings.last_run_mode = self.mode;
+        settings.manual_power_kw = self.manual_power_kw;
+        settings.target_temp_c = self.target_temp_c;
+        settings.run_time_limit_s = self.run_time_limit_s;
+    }
+}
+
+/// The preset slots and which one was loaded last.
+#[derive(Debug, Clone, Copy)]
+pub struct Profiles {
+    pub slots: [Option<Profile>; PROFILE_COUNT],
+    pub selected: usize,
+}
+
+impl Profiles {
+    pub const fn new() -> Self {
+        Self {
+            slots: [Some(Profile::DEFAULT), None, None, None],
+            selected: 0,
+        }
+    }
+
+    /// Loads slot `index` into `settings` and marks it selected. An empty slot loads
+    /// `ControlSettings::new()`, keeping only the operator's temperature unit and the tuned
+    /// resonance, which belong to the unit rather than the job.
+    pub fn load(&mut self, index: usize, settings: &mut ControlSettings) {
+        match self.slots.get(index).copied().flatten() {
+            Some(profile) => profile.apply(settings),
+            None => {
+                *settings = ControlSettings {
+                    temp_unit: settings.temp_unit,
+                    resonant_freq_hz: settings.resonant_freq_hz,
+                    ..ControlSettings::new()
+                }
+            }
+        }
+        self.selected = index.min(PROFILE_COUNT - 1);
+    }
+}
+
+/// Lifetime use of the unit, for maintenance scheduling. Kept in the settings record.
+#[derive(Debug, Clone, Copy)]
+pub struct UsageStats {
+    /// Runs started and then stopped, by the operator, a fault or a limit.
+    pub heating_cycles: u32,
+}
+
+impl UsageStats {
+    pub const fn new() -> Self {
+        Self { heating_cycles: 0 }
+    }
+}
+
+/// Results of the first-boot commissioning wizard.
+#[derive(Debug, Clone, Copy)]
+pub struct Commissioning {
+    /// Heating is refused until the wizard has been completed.
+    pub commissioned: bool,
+    /// One-point trim added to the PCB temperature sensor reading.
+    pub pcb_temp_offset_c: f32,
+}
+
+impl Commissioning {
+    pub const fn new() -> Self {
+        Self {
+            commissioned: false,
+            pcb_temp_offset_c: 0.0,
+        }
+    }
+}
+
+/// Linear trim of one sensor: `raw * gain + offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalPair {
+    pub offset: f32,
+    pub gain: f32,
+}
+
+impl CalPair {
+    pub const IDENTITY: Self = Self {
+        offset: 0.0,
+        gain: 1.0,
+    };
+
+    pub fn apply(self, raw: f32) -> f32 {
+        raw * self.gain + self.offset
+    }
+}
+
+/// Per-unit sensor trims, applied by the sensor tasks before filtering so everything
+/// downstream sees calibrated values. Loaded from flash at boot, or from
+/// `board::SENSOR_CALIBRATION` when there is no stored configuration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    pub dc_voltage: CalPair,
+    pub coil_current: CalPair,
+    pub coil_temp: CalPair,
+    pub module_temp: CalPair,
+    pub object_temp: CalPair,
+}
+
+impl Calibration {
+    pub const fn new() -> Self {
+        Self {
+            dc_voltage: CalPair::IDENTITY,
+            coil_current: CalPair::IDENTITY,
+            coil_temp: CalPair::IDENTITY,
+            module_temp: CalPair::IDENTITY,
+            object_temp: CalPair::IDENTITY,
+        }
+    }
+}
+
+/// Range the temperature-mode target can be set to, from the menu or remotely.
+pub const TARGET_TEMP_MIN_C: f32 = 40.0;
+pub const TARGET_TEMP_MAX_C: f32 = 350.0;
+/// Hard ceilings for [`Limits`]: the hardware ratings, which no runtime setting can exceed.
+pub const POWER_LIMIT_MAX_KW: f32 = 12.0;
+pub const CURRENT_LIMIT_MAX_A: f32 = 180.0;
+pub const COIL_TEMP_LIMIT_MAX_C: f32 = 100.0;
+pub const MODULE_TEMP_LIMIT_MAX_C: f32 = 100.0;
+pub const PCB_TEMP_LIMIT_MAX_C: f32 = 95.0;
+/// Peak coil current checked by `adc_task` against every raw sample of a batch, tripping as soon
+/// as the batch is in. The RMS limit in [`Limits`] is checked by `safety_task` against the
+/// filtered value, so it reacts within a few 50 ms batches and ignores short spikes. A sine at
+/// the highest allowed RMS limit peaks at about 255 A, so this leaves room for ripple and noise
+/// but catches a shorted coil or a tank driven far off resonance. Both raise
+/// `FaultCode::CurrentLimit`.
+pub const CURRENT_PEAK_LIMIT_A: f32 = 300.0;
+
+/// One of the adjustable [`Limits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    PowerKw,
+    CurrentA,
+    CoilTempC,
+    ModuleTempC,
+    PcbTempC,
+}
+
+impl LimitKind {
+    pub const ALL: [LimitKind; 5] = [
+        LimitKind::PowerKw,
+        LimitKind::CurrentA,
+        LimitKind::CoilTempC,
+        LimitKind::ModuleTempC,
+        LimitKind::PcbTempC,
+    ];
+
+    /// Lowest and highest value the limit can be set to. The highest is its hard ceiling.
+    pub const fn range(self) -> (f32, f32) {
+        match self {
+            LimitKind::PowerKw => (1.0, POWER_LIMIT_MAX_KW),
+            LimitKind::CurrentA => (20.0, CURRENT_LIMIT_MAX_A),
+            LimitKind::CoilTempC => (40.0, COIL_TEMP_LIMIT_MAX_C),
+            LimitKind::ModuleTempC => (40.0, MODULE_TEMP_LIMIT_MAX_C),
+            LimitKind::PcbTempC => (40.0, PCB_TEMP_LIMIT_MAX_C),
+        }
+    }
+
+    /// Menu adjustment per button step.
+    pub const fn step(self) -> f32 {
+        match self {
+            LimitKind::PowerKw => 0.5,
+            LimitKind::CurrentA => 5.0,
+            _ => 1.0,
+        }
+    }
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            LimitKind::PowerKw => "Power limit",
+            LimitKind::CurrentA => "Current limit",
+            LimitKind::CoilTempC => "Coil temp limit",
+            LimitKind::ModuleTempC => "Module temp lim",
+            LimitKind::PcbTempC => "PCB temp limit",
+        }
+    }
+
+    pub const fn unit(self) -> &'static str {
+        match self {
+            LimitKind::PowerKw => "kW",
+            LimitKind::CurrentA => "A",
+            _ => "C",
+        }
+    }
+}
+
+/// Trip and clamp limits used by `control` and `safety`, adjustable from the engineering menu.
+/// Every write goes through [`Limits::set`], which clamps to [`LimitKind::range`], so no value
+/// can be raised above its hard ceiling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    values: [f32; LimitKind::ALL.len()],
+}
+
+impl Limits {
+    /// The limits this firmware shipped with as compile-time constants.
+    pub const fn new() -> Self {
+        Self {
+            values: [10.0, 150.0, 80.0, 85.0, 85.0],
+        }
+    }
+
+    pub fn get(&self, kind: LimitKind) -> f32 {
+        self.values[kind as usize]
+    }
+
+    /// Sets `kind` to `value` clamped to its range; NaN leaves it unchanged.
+    pub fn set(&mut self, kind: LimitKind, value: f32) {
+        let (min, max) = kind.range();
+        if !value.is_nan() {
+            self.values[kind as usize] = value.clamp(min, max);
+        }
+    }
+
+    pub fn power_kw(&self) -> f32 {
+        self.get(LimitKind::PowerKw)
+    }
+
+    /// Filtered RMS coil current.
+    pub fn current_a(&self) -> f32 {
+        self.get(LimitKind::CurrentA)
+    }
+
+    pub fn coil_temp_c(&self) -> f32 {
+        self.get(LimitKind::CoilTempC)
+    }
+
+    pub fn module_temp_c(&self) -> f32 {
+        self.get(LimitKind::ModuleTempC)
+    }
+
+    pub fn pcb_temp_c(&self) -> f32 {
+        self.get(LimitKind::PcbTempC)
+    }
+}
@@ -0,0 +1,128 @@
+//! Bench simulation of the sensor inputs (`sim` feature).
+//!
+//! `sim_task` stands in for the ADC, ADS7828, MLX90614 and SiC module tasks: it publishes
+//! `Measurements` worked out from what `control_task` reports in `CONTROL_STATUS`, so the menu,
+//! control and safety paths can be run end to end without heating anything. `GateDrive` never
+//! switches in this build: the PWM stays off and the gate drivers stay disabled.
+//!
+//! The model is deliberately rough. The coil delivers the power setpoint after a short lag, the
+//! object warms with the energy put into it and loses heat towards ambient, faster while the
+//! coolant runs in cooldown. The bench frequency mode asks for no power and so heats nothing.
+
+use defmt::info;
+use embassy_time::{Duration, Ticker};
+
+use crate::{
+    sensors::CURRENT_CENTER_V,
+    state::{mark_reported, update_measurements, MeasurementSource, CONTROL_STATUS},
+};
+
+const SIM_PERIOD: Duration = Duration::from_millis(50);
+const SIM_DT_S: f32 = 0.050;
+const SIM_AMBIENT_C: f32 = 22.0;
+const SIM_DC_BUS_V: f32 = 325.0;
+const SIM_POWER_FACTOR: f32 = 0.8;
+// Fundamental RMS of the half-bridge's square wave, as a fraction of the DC bus.
+const SIM_FUNDAMENTAL_PER_V: f32 = 0.45;
+// First-order lag of the delivered power behind the setpoint.
+const SIM_POWER_TAU_S: f32 = 0.1;
+// Object: warming per kJ delivered, and the fraction of its rise over ambient lost each second
+// (more with the coolant on).
+const SIM_OBJECT_C_PER_KJ: f32 = 4.0;
+const SIM_OBJECT_LOSS_PER_S: f32 = 0.01;
+const SIM_OBJECT_COOLED_LOSS_PER_S: f32 = 0.1;
+// Coil: a small share of the power heats it; the coolant pulls it back.
+const SIM_COIL_C_PER_KJ: f32 = 0.05;
+const SIM_COIL_LOSS_PER_S: f32 = 0.05;
+// Module and PCB sit this far over ambient per kW delivered.
+const SIM_MODULE_C_PER_KW: f32 = 4.0;
+const SIM_PCB_C_PER_KW: f32 = 1.5;
+
+/// Simulated plant state.
+struct SimModel {
+    power_kw: f32,
+    object_c: f32,
+    coil_c: f32,
+}
+
+impl SimModel {
+    const fn new() -> Self {
+        Self {
+            power_kw: 0.0,
+            object_c: SIM_AMBIENT_C,
+            coil_c: SIM_AMBIENT_C,
+        }
+    }
+
+    fn step(&mut self, setpoint_kw: f32, cooling: bool, dt: f32) {
+        self.power_kw += (setpoint_kw - self.power_kw) * (dt / (SIM_POWER_TAU_S + dt));
+        let object_loss = if cooling {
+            SIM_OBJECT_COOLED_LOSS_PER_S
+        } else {
+            SIM_OBJECT_LOSS_PER_S
+        };
+        self.object_c += self.power_kw * dt * SIM_OBJECT_C_PER_KJ
+            - (self.object_c - SIM_AMBIENT_C) * object_loss * dt;
+        self.coil_c += self.power_kw * dt * SIM_COIL_C_PER_KJ
+            - (self.coil_c - SIM_AMBIENT_C) * SIM_COIL_LOSS_PER_S * dt;
+    }
+}
+
+#[embassy_executor::task]
+pub async fn sim_task() {
+    info!("Sensor simulation running, no real sensors are read");
+    let mut model = SimModel::new();
+    let mut ticker = Ticker::every(SIM_PERIOD);
+
+    loop {
+        let status = *CONTROL_STATUS.lock().await;
+        let setpoint_kw = if status.heating_enabled {
+            status.power_setpoint_kw
+        } else {
+            0.0
+        };
+        model.step(setpoint_kw, status.cooldown_active, SIM_DT_S);
+
+        let power_kw = model.power_kw;
+        let apparent_kw = power_kw / SIM_POWER_FACTOR;
+        let current_a = apparent_kw * 1000.0 / (SIM_DC_BUS_V * SIM_FUNDAMENTAL_PER_V);
+        update_measurements(|meas| {
+            meas.dc_voltage_v = SIM_DC_BUS_V;
+            meas.coil_power_kw = power_kw;
+            meas.apparent_power_kw = apparent_kw;
+            meas.power_factor = if power_kw > 0.01 {
+                SIM_POWER_FACTOR
+            } else {
+                0.0
+            };
+            meas.coil_current_rms_a = current_a;
+            meas.measured_freq_hz = if status.heating_enabled {
+                status.switching_freq_hz
+            } else {
+                0.0
+            };
+            meas.object_temp_c = model.object_c;
+            meas.ambient_temp_c = SIM_AMBIENT_C;
+            meas.coil_temp_c = model.coil_c;
+            meas.module_temp_c = SIM_AMBIENT_C + power_kw * SIM_MODULE_C_PER_KW;
+            meas.pcb_temp_c = SIM_AMBIENT_C + power_kw * SIM_PCB_C_PER_KW;
+            meas.current_zero_v = CURRENT_CENTER_V;
+            meas.object_removed = false;
+            meas.object_temp_valid = true;
+            meas.object_temp_settling = false;
+            meas.ads_healthy = true;
+            meas.coil_temp_disconnected = false;
+            meas.module_temp_disconnected = false;
+            meas.pcb_temp_disconnected = false;
+            meas.current_zero_drift_fault = false;
+            meas.adc_saturated = false;
+            meas.valid = true;
+            true
+        });
+        for source in MeasurementSource::ALL {
+            mark_reported(source);
+        }
+
+        ticker.next().await;
+    }
+}
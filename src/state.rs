@@ -1,31 +1,11 @@
 use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
 
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, watch::Watch};
+use embassy_time::{Duration, Instant};
+use heapless::{HistoryBuf, Vec};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ControlMode {
-    Idle,
-    ManualPower,
-    Temperature,
-    Cooldown,
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct ControlSettings {
-    pub mode: ControlMode,
-    pub manual_power_kw: f32,
-    pub target_temp_c: f32,
-}
-
-impl ControlSettings {
-    pub const fn new() -> Self {
-        Self {
-            mode: ControlMode::ManualPower,
-            manual_power_kw: 5.0,
-            target_temp_c: 120.0,
-        }
-    }
-}
+pub use induction_shrink_fit::settings::*;
 
 #[derive(Debug, Clone, Copy)]
 pub struct ControlStatus {
@@ -34,8 +14,31 @@ pub struct ControlStatus {
     pub run_active: bool,
     pub target_reached: bool,
     pub cooldown_active: bool,
+    /// In cooldown, the object and coil are cool enough to stop the coolant.
+    pub cooldown_complete: bool,
     pub power_setpoint_kw: f32,
+    /// Frequency being switched at, dither included.
     pub switching_freq_hz: f32,
+    /// The power loop's frequency before dithering; the same as `switching_freq_hz` outside
+    /// the power loop.
+    pub center_freq_hz: f32,
+    pub drive_phase: DrivePhase,
+    /// The coil current stopped following the commanded switching frequency.
+    pub pwm_freq_mismatch: bool,
+    /// Heating was paused because the object temperature fell faster than cooling allows.
+    pub part_removed: bool,
+    /// Coolant flow dropped out while a run was active.
+    pub coolant_flow_lost: bool,
+    pub tune: TuneState,
+    /// Informational, not a fault: the run hit the max runtime or the settings' run-time limit
+    /// and was sent to cooldown.
+    pub runtime_limited: bool,
+    /// Share of the power limit currently allowed by module temperature; 1.0 when not derating.
+    pub power_derate: f32,
+    /// Seconds left of the soak at target; `None` outside one.
+    pub soak_remaining_s: Option<u16>,
+    /// The soak at target has run its full time this run.
+    pub soak_complete: bool,
     pub fault: FaultCode,
 }
 
@@ -47,24 +50,165 @@ impl ControlStatus {
             run_active: false,
             target_reached: false,
             cooldown_active: false,
+            cooldown_complete: false,
             power_setpoint_kw: 0.0,
             switching_freq_hz: 0.0,
+            center_freq_hz: 0.0,
+            drive_phase: DrivePhase::Off,
+            pwm_freq_mismatch: false,
+            part_removed: false,
+            coolant_flow_lost: false,
+            tune: TuneState::Idle,
+            runtime_limited: false,
+            power_derate: 1.0,
+            soak_remaining_s: None,
+            soak_complete: false,
             fault: FaultCode::None,
         }
     }
 }
 
+/// What is setting the switching frequency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrivePhase {
+    Off,
+    /// Sweeping down from the top of the band after the drive came on.
+    SoftStart,
+    /// Holding the coil current at a safe level until the power loop takes over.
+    ConstantCurrent,
+    PowerLoop,
+    /// Ramping the frequency up after a soft fault, see `FaultSeverity::Soft`.
+    RampDown,
+    /// Driven at a set frequency: the bench mode or the resonance sweep.
+    FixedFrequency,
+}
+
+/// Where the resonance sweep run in `ControlMode::AutoTune` has got to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TuneState {
+    Idle,
+    /// Currently driving `freq_hz`.
+    Sweeping {
+        freq_hz: f32,
+    },
+    /// Frequency of peak coil current; the power loop now starts from here.
+    Done {
+        resonant_freq_hz: f32,
+        peak_current_a: f32,
+    },
+    /// Stopped early by a fault or lost coolant flow; the previous start frequency is kept.
+    Aborted,
+}
+
+/// Controller saturation counts for the current run, for tuning.
+#[derive(Debug, Clone, Copy)]
+pub struct ControlDiagnostics {
+    pub freq_min_clamps: u32,
+    pub freq_max_clamps: u32,
+    pub temp_integrator_saturations: u32,
+    pub power_limit_hits: u32,
+}
+
+impl ControlDiagnostics {
+    pub const fn new() -> Self {
+        Self {
+            freq_min_clamps: 0,
+            freq_max_clamps: 0,
+            temp_integrator_saturations: 0,
+            power_limit_hits: 0,
+        }
+    }
+}
+
+/// Energy put into the coil during the current run.
+#[derive(Debug, Clone, Copy)]
+pub struct EnergyStats {
+    /// Integrated `coil_power_kw` while heating; reset when a run starts.
+    pub delivered_energy_kwh: f32,
+}
+
+impl EnergyStats {
+    pub const fn new() -> Self {
+        Self {
+            delivered_energy_kwh: 0.0,
+        }
+    }
+}
+
+/// Peaks of the current (or last) run, for the operator's log. Reset when a run starts.
+#[derive(Debug, Clone, Copy)]
+pub struct RunStats {
+    pub peak_current_a: f32,
+    pub peak_module_temp_c: f32,
+    pub peak_power_kw: f32,
+    /// Run start to first reaching the target in temperature mode; `None` until then, and
+    /// always in manual power.
+    pub time_to_target: Option<Duration>,
+}
+
+impl RunStats {
+    pub const fn new() -> Self {
+        Self {
+            peak_current_a: 0.0,
+            peak_module_temp_c: 0.0,
+            peak_power_kw: 0.0,
+            time_to_target: None,
+        }
+    }
+
+    /// Folds `meas` into the peaks. Snapshots not yet `valid` (the ADC still settling) are
+    /// skipped, since their zeros or filter transients say nothing about the run.
+    pub fn record(&mut self, meas: &Measurements) {
+        if !meas.valid {
+            return;
+        }
+        self.peak_current_a = self.peak_current_a.max(meas.coil_current_rms_a);
+        self.peak_module_temp_c = self.peak_module_temp_c.max(meas.module_temp_c);
+        self.peak_power_kw = self.peak_power_kw.max(meas.coil_power_kw);
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Measurements {
     pub dc_voltage_v: f32,
     pub coil_current_rms_a: f32,
     pub coil_power_kw: f32,
+    /// Vrms * Irms of the same batch as `coil_power_kw`.
+    pub apparent_power_kw: f32,
+    /// `coil_power_kw / apparent_power_kw`, clamped to 0..=1; 0 with no current.
+    pub power_factor: f32,
+    /// Coil current fundamental from zero crossings; 0 when the current is too small to tell.
+    pub measured_freq_hz: f32,
     pub coil_temp_c: f32,
     pub pcb_temp_c: f32,
     pub module_temp_c: f32,
     pub object_temp_c: f32,
+    /// MLX90614 die temperature, standing in for the ambient around the work.
+    pub ambient_temp_c: f32,
+    /// Set for the sample where the object temperature stepped down implausibly fast.
+    pub object_removed: bool,
+    /// Cleared once the IR thermometer stays unreadable through repeated bus recoveries;
+    /// `object_temp_c` is then the last good reading, not a current one. Also clear from boot
+    /// until the thermometer has settled, while `object_temp_settling` is set.
+    pub object_temp_valid: bool,
+    /// Set from boot until the IR thermometer's readings have settled, or it has failed to
+    /// settle in time; `object_temp_c` holds nothing meaningful yet.
+    pub object_temp_settling: bool,
+    /// Cleared after repeated failed ADS7828 scans; `coil_temp_c` and `pcb_temp_c` are then
+    /// the last good readings, not current ones.
+    pub ads_healthy: bool,
     pub valid: bool,
     pub coil_temp_disconnected: bool,
+    pub module_temp_disconnected: bool,
+    /// The PCB NTC reads open or shorted; `pcb_temp_c` is the last good reading. Never set for
+    /// an LM35.
+    pub pcb_temp_disconnected: bool,
+    /// Tracked zero-current output of the hall sensor, in ADC volts.
+    pub current_zero_v: f32,
+    pub current_zero_drift_fault: bool,
+    /// The last ADC batch was mostly railed (see `FaultCode::AdcSaturation`); the electrical
+    /// values are from the batch before it.
+    pub adc_saturated: bool,
 }
 
 impl Measurements {
@@ -73,12 +217,25 @@ impl Measurements {
             dc_voltage_v: 0.0,
             coil_current_rms_a: 0.0,
             coil_power_kw: 0.0,
+            apparent_power_kw: 0.0,
+            power_factor: 0.0,
+            measured_freq_hz: 0.0,
             coil_temp_c: 0.0,
             pcb_temp_c: 0.0,
             module_temp_c: 0.0,
             object_temp_c: 0.0,
+            ambient_temp_c: 0.0,
+            object_removed: false,
+            object_temp_valid: false,
+            object_temp_settling: true,
+            ads_healthy: true,
             valid: false,
             coil_temp_disconnected: false,
+            module_temp_disconnected: false,
+            pcb_temp_disconnected: false,
+            current_zero_v: 0.0,
+            current_zero_drift_fault: false,
+            adc_saturated: false,
         }
     }
 }
@@ -95,6 +252,15 @@ pub enum FaultCode {
     GateDriverNotReady,
     SensorFault,
     CurrentLimit,
+    CurrentSensorFault,
+    PwmFault,
+    NoCoolantFlow,
+    NoHeatingDetected,
+    DcUndervoltage,
+    /// Most of an ADC batch sat at the end of the range on the voltage or current input.
+    AdcSaturation,
+    /// The menu stopped checking in, so the operator has no display and no way to stop a run.
+    MenuUnresponsive,
 }
 
 impl FaultCode {
@@ -108,8 +274,15 @@ impl FaultCode {
             FaultCode::InterlockOpen => "Interlock open",
             FaultCode::GateDriverFault => "Gate driver fault",
             FaultCode::GateDriverNotReady => "Gate driver not ready",
-            FaultCode::SensorFault => "Coil temperature sensor fault",
+            FaultCode::SensorFault => "Temperature sensor fault",
             FaultCode::CurrentLimit => "Current limit exceeded",
+            FaultCode::CurrentSensorFault => "Current sensor zero drift",
+            FaultCode::PwmFault => "Switching frequency mismatch",
+            FaultCode::NoCoolantFlow => "No coolant flow",
+            FaultCode::NoHeatingDetected => "Power applied but object not heating",
+            FaultCode::DcUndervoltage => "DC bus undervoltage",
+            FaultCode::AdcSaturation => "ADC input railed",
+            FaultCode::MenuUnresponsive => "Menu not responding",
         }
     }
 
@@ -123,10 +296,56 @@ impl FaultCode {
             FaultCode::InterlockOpen => "Interlock open",
             FaultCode::GateDriverFault => "Gate drv fault",
             FaultCode::GateDriverNotReady => "Gate drv wait",
-            FaultCode::SensorFault => "Coil sns fault",
+            FaultCode::SensorFault => "Temp sns fault",
             FaultCode::CurrentLimit => "Current limit",
+            FaultCode::CurrentSensorFault => "Cur sns drift",
+            FaultCode::PwmFault => "PWM fault",
+            FaultCode::NoCoolantFlow => "No coolant flow",
+            FaultCode::NoHeatingDetected => "No heating seen",
+            FaultCode::DcUndervoltage => "DC bus low",
+            FaultCode::AdcSaturation => "ADC railed",
+            FaultCode::MenuUnresponsive => "Menu hung",
+        }
+    }
+
+    /// How `control_task` stops the drive for this fault.
+    pub const fn severity(self) -> FaultSeverity {
+        match self {
+            FaultCode::InterlockOpen
+            | FaultCode::GateDriverFault
+            | FaultCode::GateDriverNotReady
+            | FaultCode::CurrentLimit
+            | FaultCode::CurrentSensorFault
+            | FaultCode::PwmFault
+            | FaultCode::AdcSaturation => FaultSeverity::Hard,
+            FaultCode::None
+            | FaultCode::PowerLimit
+            | FaultCode::CoilOverTemp
+            | FaultCode::ModuleOverTemp
+            | FaultCode::PcbOverTemp
+            | FaultCode::SensorFault
+            | FaultCode::NoCoolantFlow
+            | FaultCode::NoHeatingDetected
+            | FaultCode::DcUndervoltage
+            | FaultCode::MenuUnresponsive => FaultSeverity::Soft,
         }
     }
+
+    /// Faults that stay active after their cause goes away, until the operator clears them.
+    pub const fn latching(self) -> bool {
+        matches!(
+            self,
+            FaultCode::PowerLimit
+                | FaultCode::CoilOverTemp
+                | FaultCode::ModuleOverTemp
+                | FaultCode::PcbOverTemp
+                | FaultCode::GateDriverFault
+                | FaultCode::CurrentLimit
+                | FaultCode::NoHeatingDetected
+                | FaultCode::DcUndervoltage
+                | FaultCode::MenuUnresponsive
+        )
+    }
 }
 
 impl fmt::Display for FaultCode {
@@ -135,29 +354,198 @@ impl fmt::Display for FaultCode {
     }
 }
 
+/// How hard a fault stops the inverter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultSeverity {
+    /// A limit was crossed but the power stage is sound: the frequency is ramped up to bring
+    /// the tank current down before the gates go off.
+    Soft,
+    /// The e-stop inputs, the current measurement or the switching itself cannot be trusted:
+    /// the gates go off at once.
+    Hard,
+}
+
+/// How close the hottest monitored temperature is to its trip limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningLevel {
+    None,
+    Approaching,
+    Near,
+    Trip,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct FaultState {
     pub code: FaultCode,
+    /// `code` is held until `safety::clear_fault` is called, even if the condition is gone.
+    pub latched: bool,
+    pub warning: WarningLevel,
 }
 
 impl FaultState {
     pub const fn new() -> Self {
         Self {
             code: FaultCode::None,
+            latched: false,
+            warning: WarningLevel::None,
         }
     }
 }
 
-pub const POWER_LIMIT_KW: f32 = 10.0;
-pub const CURRENT_LIMIT_A: f32 = 150.0;
-pub const COIL_TEMP_LIMIT_C: f32 = 80.0;
-pub const MODULE_TEMP_LIMIT_C: f32 = 85.0;
-pub const PCB_TEMP_LIMIT_C: f32 = 85.0;
+/// One fault transition; `code` is `FaultCode::None` when the fault cleared.
+#[derive(Debug, Clone, Copy)]
+pub struct FaultRecord {
+    pub code: FaultCode,
+    pub at: Instant,
+    /// The measurement that tripped `code`, in its own units, or 0 where there is none.
+    pub value: f32,
+}
+
+pub const FAULT_HISTORY_LEN: usize = 16;
 
-pub static MEASUREMENTS: Mutex<CriticalSectionRawMutex, Measurements> =
-    Mutex::new(Measurements::new());
+/// Receivers that may await measurement changes at the same time.
+pub const MEASUREMENT_RECEIVERS: usize = 4;
+
+/// Latest sensor snapshot. Producers publish through [`update_measurements`]; consumers copy
+/// the current value with [`measurements`] instead of holding a lock while they work.
+pub static MEASUREMENTS: Watch<CriticalSectionRawMutex, Measurements, MEASUREMENT_RECEIVERS> =
+    Watch::new_with(Measurements::new());
 pub static CONTROL_SETTINGS: Mutex<CriticalSectionRawMutex, ControlSettings> =
     Mutex::new(ControlSettings::new());
 pub static CONTROL_STATUS: Mutex<CriticalSectionRawMutex, ControlStatus> =
     Mutex::new(ControlStatus::new());
+pub static CONTROL_DIAGNOSTICS: Mutex<CriticalSectionRawMutex, ControlDiagnostics> =
+    Mutex::new(ControlDiagnostics::new());
+pub static ENERGY_STATS: Mutex<CriticalSectionRawMutex, EnergyStats> =
+    Mutex::new(EnergyStats::new());
+pub static RUN_STATS: Mutex<CriticalSectionRawMutex, RunStats> = Mutex::new(RunStats::new());
+pub static USAGE_STATS: Mutex<CriticalSectionRawMutex, UsageStats> = Mutex::new(UsageStats::new());
+pub static LIMITS: Mutex<CriticalSectionRawMutex, Limits> = Mutex::new(Limits::new());
+pub static PROFILES: Mutex<CriticalSectionRawMutex, Profiles> = Mutex::new(Profiles::new());
 pub static FAULT_STATE: Mutex<CriticalSectionRawMutex, FaultState> = Mutex::new(FaultState::new());
+pub static COMMISSIONING: Mutex<CriticalSectionRawMutex, Commissioning> =
+    Mutex::new(Commissioning::new());
+pub static CALIBRATION: Mutex<CriticalSectionRawMutex, Calibration> =
+    Mutex::new(Calibration::new());
+pub static FAULT_HISTORY: Mutex<
+    CriticalSectionRawMutex,
+    HistoryBuf<FaultRecord, FAULT_HISTORY_LEN>,
+> = Mutex::new(HistoryBuf::new());
+
+pub fn measurements() -> Measurements {
+    MEASUREMENTS.try_get().unwrap_or(Measurements::new())
+}
+
+/// Applies `update` to the published snapshot. Receivers are only woken when `update` reports
+/// that it changed something.
+pub fn update_measurements(update: impl Fn(&mut Measurements) -> bool) {
+    MEASUREMENTS
+        .sender()
+        .send_if_modified(|slot| slot.as_mut().is_some_and(|meas| update(meas)));
+}
+
+/// A task feeding [`Measurements`]. Each reports at its own cadence; one that stops reporting
+/// leaves its last values in the snapshot, so [`stale_source`] watches for that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeasurementSource {
+    /// On-chip ADC: DC bus, coil current, power, frequency.
+    Adc,
+    /// ADS7828: coil and PCB temperatures.
+    Ads7828,
+    /// MLX90614: object and ambient temperatures.
+    Mlx90614,
+    /// PIO capture of the SiC module's temperature PWM.
+    SicModule,
+}
+
+impl MeasurementSource {
+    pub const ALL: [Self; 4] = [
+        MeasurementSource::Adc,
+        MeasurementSource::Ads7828,
+        MeasurementSource::Mlx90614,
+        MeasurementSource::SicModule,
+    ];
+
+    /// How often the source's task normally reports.
+    pub const fn interval(self) -> Duration {
+        match self {
+            MeasurementSource::Adc => Duration::from_millis(100),
+            MeasurementSource::Ads7828 => Duration::from_millis(50),
+            MeasurementSource::Mlx90614 => Duration::from_millis(150),
+            MeasurementSource::SicModule => Duration::from_millis(1_000),
+        }
+    }
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            MeasurementSource::Adc => "ADC",
+            MeasurementSource::Ads7828 => "ADS7828",
+            MeasurementSource::Mlx90614 => "IR sensor",
+            MeasurementSource::SicModule => "Module PWM",
+        }
+    }
+}
+
+/// A source is stale once it has gone this many of its intervals without reporting.
+const SOURCE_STALE_INTERVALS: u32 = 5;
+/// Sources are not checked this soon after their tasks were spawned.
+const SOURCE_STALE_STARTUP_GRACE: Duration = Duration::from_secs(5);
+
+/// Uptime in ms of each source's last report, indexed by `MeasurementSource as usize`.
+static SOURCE_REPORTED_MS: [AtomicU32; 4] = [const { AtomicU32::new(0) }; 4];
+/// Uptime in ms when the sensor tasks were spawned; 0 until then. The self-test can hold them
+/// back for as long as it takes to pass.
+static SENSORS_STARTED_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Records that the sensor tasks have just been spawned, starting the stale-source grace.
+pub fn sensors_started() {
+    SENSORS_STARTED_MS.store(Instant::now().as_millis().max(1) as u32, Ordering::Relaxed);
+}
+
+/// Records that `source`'s task is alive and has just reported.
+pub fn mark_reported(source: MeasurementSource) {
+    SOURCE_REPORTED_MS[source as usize].store(Instant::now().as_millis() as u32, Ordering::Relaxed);
+}
+
+/// Uptime in ms of the menu's last heartbeat; 0 until `menu_task` first runs.
+static MENU_HEARTBEAT_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Records that the menu is still polling its buttons.
+pub fn menu_heartbeat() {
+    MENU_HEARTBEAT_MS.store(Instant::now().as_millis().max(1) as u32, Ordering::Relaxed);
+}
+
+/// How long since the menu's last heartbeat, or `None` before it has started.
+pub fn menu_silent_for() -> Option<Duration> {
+    match MENU_HEARTBEAT_MS.load(Ordering::Relaxed) {
+        0 => None,
+        beat_ms => {
+            let now_ms = Instant::now().as_millis() as u32;
+            Some(Duration::from_millis(now_ms.wrapping_sub(beat_ms) as u64))
+        }
+    }
+}
+
+/// The first source that has not reported within `SOURCE_STALE_INTERVALS` of its interval.
+pub fn stale_source() -> Option<MeasurementSource> {
+    let now_ms = Instant::now().as_millis() as u32;
+    let started_ms = SENSORS_STARTED_MS.load(Ordering::Relaxed);
+    let since_start = Duration::from_millis(now_ms.wrapping_sub(started_ms) as u64);
+    if started_ms == 0 || since_start < SOURCE_STALE_STARTUP_GRACE {
+        return None;
+    }
+    MeasurementSource::ALL.into_iter().find(|&source| {
+        let reported_ms = SOURCE_REPORTED_MS[source as usize].load(Ordering::Relaxed);
+        let age = Duration::from_millis(now_ms.wrapping_sub(reported_ms) as u64);
+        age > source.interval() * SOURCE_STALE_INTERVALS
+    })
+}
+
+/// Recorded fault transitions, newest first.
+pub async fn fault_history() -> Vec<FaultRecord, FAULT_HISTORY_LEN> {
+    let history = FAULT_HISTORY.lock().await;
+    let mut records: Vec<FaultRecord, FAULT_HISTORY_LEN> =
+        history.oldest_ordered().copied().collect();
+    records.reverse();
+    records
+}
@@ -0,0 +1,94 @@
+//! Settings persistence in the last sector of the QSPI flash.
+//!
+//! The sector holds one [`PersistedConfig`] block. Anything that does not check out is ignored
+//! and the unit boots with defaults.
+
+use defmt::{info, warn};
+use embassy_futures::select::{select, Either};
+use embassy_rp::{
+    flash::{self, Blocking, Flash, ERASE_SIZE},
+    peripherals::FLASH,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+
+use crate::{
+    board,
+    config::{PersistedConfig, CONFIG_LEN},
+    state::{
+        CALIBRATION, COMMISSIONING, CONTROL_SETTINGS, CONTROL_STATUS, LIMITS, PROFILES, USAGE_STATS,
+    },
+};
+
+/// Size of the flash chip, must match `__flash_size` in memory.x.
+pub const FLASH_SIZE: usize = 16 * 1024 * 1024;
+const SETTINGS_OFFSET: u32 = (FLASH_SIZE - ERASE_SIZE) as u32;
+// Wait for the operator to stop changing things before writing.
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(3);
+// Erasing a sector stalls execution from flash, including the control and safety loops. It
+// also stops the watchdog feed; a sector erase (45 ms typical, 400 ms worst case on the
+// W25Q128) still fits inside control::WATCHDOG_TIMEOUT.
+const SAVE_RETRY_WHILE_HEATING: Duration = Duration::from_secs(1);
+
+pub type SettingsFlash = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+
+static SAVE_REQUEST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Reads the stored record, or `None` if it is blank, from another version or corrupt.
+pub fn load_settings(flash: &mut SettingsFlash) -> Option<PersistedConfig> {
+    let mut buf = [0u8; CONFIG_LEN];
+    if flash.blocking_read(SETTINGS_OFFSET, &mut buf).is_err() {
+        warn!("Settings flash read failed");
+        return None;
+    }
+    PersistedConfig::deserialize(&buf, board::SENSOR_CALIBRATION)
+}
+
+pub fn save_settings(
+    flash: &mut SettingsFlash,
+    stored: &PersistedConfig,
+) -> Result<(), flash::Error> {
+    let buf = stored.serialize();
+    let mut current = [0u8; CONFIG_LEN];
+    if flash.blocking_read(SETTINGS_OFFSET, &mut current).is_ok() && current == buf {
+        return Ok(());
+    }
+    flash.blocking_erase(SETTINGS_OFFSET, SETTINGS_OFFSET + ERASE_SIZE as u32)?;
+    flash.blocking_write(SETTINGS_OFFSET, &buf)
+}
+
+/// Asks [`storage_task`] to write the current settings once they have settled.
+pub fn request_save() {
+    SAVE_REQUEST.signal(());
+}
+
+#[embassy_executor::task]
+pub async fn storage_task(mut flash: SettingsFlash) {
+    loop {
+        SAVE_REQUEST.wait().await;
+        // Every further request restarts the wait, so a burst of edits ends in one write.
+        loop {
+            if let Either::Second(()) =
+                select(SAVE_REQUEST.wait(), Timer::after(SAVE_DEBOUNCE)).await
+            {
+                break;
+            }
+        }
+        while CONTROL_STATUS.lock().await.heating_enabled {
+            Timer::after(SAVE_RETRY_WHILE_HEATING).await;
+        }
+
+        let stored = PersistedConfig {
+            settings: *CONTROL_SETTINGS.lock().await,
+            commissioning: *COMMISSIONING.lock().await,
+            limits: *LIMITS.lock().await,
+            profiles: *PROFILES.lock().await,
+            usage: *USAGE_STATS.lock().await,
+            calibration: *CALIBRATION.lock().await,
+        };
+        match save_settings(&mut flash, &stored) {
+            Ok(()) => info!("Settings saved"),
+            Err(_e) => warn!("Settings flash write failed"),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Bench telemetry over USB CDC-ACM (`usb-telemetry` feature).
+//!
+//! Once a host opens the port it gets one header line followed by a CSV frame of the current
+//! measurements and control status every `FRAME_PERIOD`. Frames the host does not pick up in
+//! time are dropped rather than queued.
+//!
+//! With `dev-cli` the same USB device also carries the `dev_cli` command port.
+
+use core::fmt::Write;
+use defmt::{info, unwrap};
+use embassy_executor::Spawner;
+use embassy_rp::{
+    bind_interrupts,
+    peripherals::USB,
+    usb::{Driver, InterruptHandler},
+};
+use embassy_time::{with_timeout, Duration, Instant, Ticker};
+use embassy_usb::{
+    class::cdc_acm::{CdcAcmClass, State},
+    Builder, Config, UsbDevice,
+};
+use heapless::String;
+use static_cell::StaticCell;
+
+use crate::state::{measurements, CONTROL_STATUS};
+
+const FRAME_PERIOD: Duration = Duration::from_millis(100);
+const PACKET_TIMEOUT: Duration = Duration::from_millis(5);
+pub(crate) const MAX_PACKET_SIZE: u16 = 64;
+
+const HEADER: &str = "t_ms,vdc_v,irms_a,power_kw,apparent_kva,pf,meas_freq_hz,coil_c,pcb_c,\
+module_c,object_c,ambient_c,object_removed,valid,coil_disc,module_disc,zero_v,zero_drift,mode,\
+heating,run,target_reached,cooldown,cooldown_done,setpoint_kw,switch_freq_hz,pwm_mismatch,\
+part_removed,coolant_lost,runtime_limited,derate,fault\r\n";
+
+pub(crate) type UsbDriver = Driver<'static, USB>;
+
+bind_interrupts!(struct UsbIrqs {
+    USBCTRL_IRQ => InterruptHandler<USB>;
+});
+
+static CONFIG_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+static BOS_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
+static CDC_STATE: StaticCell<State<'static>> = StaticCell::new();
+
+/// Brings up the USB device and spawns the tasks that service it.
+pub fn init(spawner: &Spawner, usb: USB) {
+    let driver = Driver::new(usb, UsbIrqs);
+
+    let mut config = Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("Induction Shrink");
+    config.product = Some("Shrink-fit telemetry");
+    config.serial_number = None;
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+    // Two CDC-ACM functions need interface association descriptors to enumerate.
+    #[cfg(feature = "dev-cli")]
+    {
+        config.device_class = 0xEF;
+        config.device_sub_class = 0x02;
+        config.device_protocol = 0x01;
+        config.composite_with_iads = true;
+    }
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        CONFIG_DESCRIPTOR.init([0; 256]),
+        BOS_DESCRIPTOR.init([0; 256]),
+        &mut [],
+        CONTROL_BUF.init([0; 64]),
+    );
+    let class = CdcAcmClass::new(&mut builder, CDC_STATE.init(State::new()), MAX_PACKET_SIZE);
+    #[cfg(feature = "dev-cli")]
+    let cli_class = crate::dev_cli::add_port(&mut builder);
+    let usb = builder.build();
+
+    unwrap!(spawner.spawn(usb_task(usb)));
+    unwrap!(spawner.spawn(telemetry_task(class)));
+    #[cfg(feature = "dev-cli")]
+    unwrap!(spawner.spawn(crate::dev_cli::dev_cli_task(cli_class)));
+}
+
+#[embassy_executor::task]
+async fn usb_task(mut usb: UsbDevice<'static, UsbDriver>) -> ! {
+    usb.run().await
+}
+
+#[embassy_executor::task]
+async fn telemetry_task(mut class: CdcAcmClass<'static, UsbDriver>) {
+    loop {
+        class.wait_connection().await;
+        info!("Telemetry host connected");
+        let _ = send_line(&mut class, HEADER).await;
+
+        let mut ticker = Ticker::every(FRAME_PERIOD);
+        while class.dtr() {
+            ticker.next().await;
+            let frame = build_frame().await;
+            // A host that stopped reading just loses frames; the next one starts a fresh line.
+            let _ = send_line(&mut class, frame.as_str()).await;
+        }
+        info!("Telemetry host disconnected");
+    }
+}
+
+async fn build_frame() -> String<384> {
+    let meas = measurements();
+    let status = *CONTROL_STATUS.lock().await;
+
+    let mut line = String::<384>::new();
+    let _ = write!(
+        line,
+        "{},{:.1},{:.1},{:.2},{:.2},{:.2},{:.0},{:.1},{:.1},{:.1},{:.1},{:.1},\
+         {},{},{},{},{:.3},{},",
+        Instant::now().as_millis(),
+        meas.dc_voltage_v,
+        meas.coil_current_rms_a,
+        meas.coil_power_kw,
+        meas.apparent_power_kw,
+        meas.power_factor,
+        meas.measured_freq_hz,
+        meas.coil_temp_c,
+        meas.pcb_temp_c,
+        meas.module_temp_c,
+        meas.object_temp_c,
+        meas.ambient_temp_c,
+        meas.object_removed as u8,
+        meas.valid as u8,
+        meas.coil_temp_disconnected as u8,
+        meas.module_temp_disconnected as u8,
+        meas.current_zero_v,
+        meas.current_zero_drift_fault as u8,
+    );
+    let _ = write!(
+        line,
+        "{:?},{},{},{},{},{},{:.2},{:.0},{},{},{},{},{:.2},{:?}\r\n",
+        status.mode,
+        status.heating_enabled as u8,
+        status.run_active as u8,
+        status.target_reached as u8,
+        status.cooldown_active as u8,
+        status.cooldown_complete as u8,
+        status.power_setpoint_kw,
+        status.switching_freq_hz,
+        status.pwm_freq_mismatch as u8,
+        status.part_removed as u8,
+        status.coolant_flow_lost as u8,
+        status.runtime_limited as u8,
+        status.power_derate,
+        status.fault,
+    );
+    line
+}
+
+/// Sends `line` in packet-sized chunks. Gives up on the first packet the host does not take
+/// within `PACKET_TIMEOUT`; returns whether the whole line went out.
+async fn send_line(class: &mut CdcAcmClass<'static, UsbDriver>, line: &str) -> bool {
+    let bytes = line.as_bytes();
+    for chunk in bytes.chunks(MAX_PACKET_SIZE as usize) {
+        match with_timeout(PACKET_TIMEOUT, class.write_packet(chunk)).await {
+            Ok(Ok(())) => {}
+            _ => return false,
+        }
+    }
+    // A full final packet needs a zero-length packet to end the transfer.
+    if bytes.len() % MAX_PACKET_SIZE as usize == 0 {
+        return matches!(
+            with_timeout(PACKET_TIMEOUT, class.write_packet(&[])).await,
+            Ok(Ok(()))
+        );
+    }
+    true
+}
@@ -1,42 +1,128 @@
 use defmt::info;
 use embassy_rp::{
     clocks,
+    i2c::{self, I2c},
     pwm::{Config, Pwm, SetDutyCycle},
 };
 
+use crate::{board::INVERTER_INVERT_B, pwm_timing::PwmTiming};
+
 pub fn pwm_enable(pwm_ch: &mut Pwm<'_>, dt_ns: u32, desired_freq_hz: u32) {
-    let clock_freq_hz = clocks::clk_sys_freq();
-    let divider = 2u8;
-    let period = ((clock_freq_hz / (desired_freq_hz * divider as u32)) / 2) as u16 - 1;
+    pwm_enable_with_duty(pwm_ch, dt_ns, desired_freq_hz, 50);
+}
 
-    // Calculate the dead time in clock cycles - dt_ns * 125MHz / 16 / 1_000_000_000
-    // dt = 1 = 1 period of 125MHz clock divider by divider = 8ns * 16 = 128ns
-    let dt = (((dt_ns * (clock_freq_hz / 1_000_000)) / (divider as u32)) / 1_000) as u16;
+/// Like [`pwm_enable`], but the high side only conducts for `duty_percent` of the period.
+pub fn pwm_enable_with_duty(
+    pwm_ch: &mut Pwm<'_>,
+    dt_ns: u32,
+    desired_freq_hz: u32,
+    duty_percent: u8,
+) {
+    let clock_freq_hz = clocks::clk_sys_freq();
+    let timing = PwmTiming::new(clock_freq_hz, desired_freq_hz, dt_ns);
+    let period = timing.top;
+    let dt = timing.dt_counts;
 
-    info!("PWM period: {}", period);
-    info!("PWM divider: {}", divider);
-    info!("PWM dt: {}", dt);
-    info!("PWM dt_ns: {}", dt_ns);
+    info!(
+        "PWM divider {}, top {}, dead time {} ticks: {} Hz, dead time {} ns",
+        timing.divider,
+        timing.top,
+        timing.dt_counts,
+        timing.freq_hz(clock_freq_hz),
+        timing.dead_time_ns(clock_freq_hz)
+    );
 
     let mut c = Config::default();
     c.top = period;
-    c.divider = divider.into();
+    c.divider = timing.divider.into();
     c.phase_correct = true;
-    c.invert_b = true; // Invert B output
+    c.invert_b = INVERTER_INVERT_B;
     pwm_ch.set_config(&c);
 
-    let (pwm_ch0_a, pwm_ch0_b) = pwm_ch.split_by_ref();
-    if let (Some(ref mut a), Some(ref mut b)) = (pwm_ch0_a, pwm_ch0_b) {
-        a.set_duty_cycle_fraction((period - dt) / 2, period)
-            .unwrap();
-        b.set_duty_cycle_fraction((period + dt) / 2, period)
+    // Twice the high-side on time, so 50% reproduces the symmetric (period +- dt) / 2 split.
+    let on_x2 = (period as u32 * duty_percent.min(100) as u32 / 50) as u16;
+    let (pwm_a, pwm_b) = pwm_ch.split_by_ref();
+    if let (Some(ref mut a), Some(ref mut b)) = (pwm_a, pwm_b) {
+        a.set_duty_cycle_fraction(on_x2.saturating_sub(dt) / 2, period)
             .unwrap();
+        b.set_duty_cycle_fraction(
+            on_x2.saturating_add(dt).min(period.saturating_mul(2)) / 2,
+            period,
+        )
+        .unwrap();
     }
 }
 
-pub fn pwm_disable(pwm_ch: &mut Pwm<'static>) {
+pub fn pwm_disable(pwm_ch: &mut Pwm<'_>) {
     let _ = pwm_ch.set_duty_cycle_fully_off();
     let mut cfg = Config::default();
     cfg.enable = false;
     pwm_ch.set_config(&cfg);
 }
+
+/// A 7-bit I2C address that is reserved or does not fit in 7 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidI2cAddress(pub u8);
+
+/// Accepts non-reserved 7-bit addresses (0x08..=0x77).
+pub fn validate_i2c_address(address: u8) -> Result<u8, InvalidI2cAddress> {
+    if (0x08..=0x77).contains(&address) {
+        Ok(address)
+    } else {
+        Err(InvalidI2cAddress(address))
+    }
+}
+
+/// Byte transfers the sensor drivers make, so one driver serves both bus modes: on a
+/// `Blocking` bus they stall the executor for the whole transfer, on an `Async` bus other tasks
+/// run while the controller's FIFO drains.
+pub(crate) trait I2cTransfer {
+    async fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), i2c::Error>;
+    async fn read(&mut self, address: u8, buf: &mut [u8]) -> Result<(), i2c::Error>;
+    /// Write then read with a repeated start between them.
+    async fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buf: &mut [u8],
+    ) -> Result<(), i2c::Error>;
+}
+
+impl<T: i2c::Instance> I2cTransfer for I2c<'_, T, i2c::Blocking> {
+    async fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), i2c::Error> {
+        self.blocking_write(address, bytes)
+    }
+
+    async fn read(&mut self, address: u8, buf: &mut [u8]) -> Result<(), i2c::Error> {
+        self.blocking_read(address, buf)
+    }
+
+    async fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buf: &mut [u8],
+    ) -> Result<(), i2c::Error> {
+        self.blocking_write_read(address, bytes, buf)
+    }
+}
+
+impl<T: i2c::Instance> I2cTransfer for I2c<'_, T, i2c::Async> {
+    async fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), i2c::Error> {
+        self.write_async(address, bytes.iter().copied()).await
+    }
+
+    async fn read(&mut self, address: u8, buf: &mut [u8]) -> Result<(), i2c::Error> {
+        self.read_async(address, buf).await
+    }
+
+    async fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buf: &mut [u8],
+    ) -> Result<(), i2c::Error> {
+        self.write_read_async(address, bytes.iter().copied(), buf)
+            .await
+    }
+}
@@ -0,0 +1,7 @@
+//! Firmware identification for the splash and About screens, so field support can tell what a
+//! unit is running.
+
+/// Crate version from `Cargo.toml`.
+pub const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// UTC date (YYYY-MM-DD) of the build, set by `build.rs`.
+pub const BUILD_DATE: &str = env!("BUILD_DATE");